@@ -0,0 +1,300 @@
+//! Keeps a local copy of `/files` in sync with an instance by following
+//! `/api/v3/files/changes`. Only changed files are downloaded, every download is
+//! checked against its sha256 before it replaces the local copy.
+//!
+//! Usage: `mirror-sync <url> <directory> [--interval <seconds>] [--key <api key>]`
+//!
+//! Anonymous downloads share the `files_download` rate limit of everyone else, pass an
+//! organization API key (or set `MIRROR_SYNC_KEY`) so a dedicated policy applies.
+//! Rate limited requests are retried after the `Retry-After` the server sends.
+
+use anyhow::Context;
+use serde::Deserialize;
+use sha2::Digest;
+use std::{
+    path::{Component, Path, PathBuf},
+    time::Duration,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+const CURSOR_FILE: &str = ".mirror-sync-cursor";
+const PAGE_SIZE: usize = 500;
+const MAX_ATTEMPTS: u32 = 10;
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+const USAGE: &str = "usage: mirror-sync <url> <directory> [--interval <seconds>] [--key <api key>]";
+
+#[derive(Deserialize)]
+struct Change {
+    path: String,
+    url: Option<String>,
+    deleted: bool,
+    size: Option<i64>,
+    sha256: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Changes {
+    changes: Vec<Change>,
+    cursor: i64,
+    has_more: bool,
+}
+
+struct Mirror {
+    client: reqwest::Client,
+    url: String,
+    directory: PathBuf,
+}
+
+impl Mirror {
+    /// Resolves a feed path below the mirror directory, refusing anything that
+    /// could escape it.
+    fn local_path(&self, path: &str) -> anyhow::Result<PathBuf> {
+        let path = Path::new(path);
+        if path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            anyhow::bail!("refusing unsafe path {}", path.display());
+        }
+
+        Ok(self.directory.join(path))
+    }
+
+    async fn read_cursor(&self) -> anyhow::Result<i64> {
+        match tokio::fs::read_to_string(self.directory.join(CURSOR_FILE)).await {
+            Ok(cursor) => cursor.trim().parse().context("invalid cursor file"),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    async fn write_cursor(&self, cursor: i64) -> anyhow::Result<()> {
+        let path = self.directory.join(CURSOR_FILE);
+        let temporary = path.with_extension("tmp");
+
+        tokio::fs::write(&temporary, cursor.to_string()).await?;
+        tokio::fs::rename(&temporary, &path).await?;
+
+        Ok(())
+    }
+
+    /// Sends a GET to `url`, waiting out rate limits with the `Retry-After` of the
+    /// response or an exponential backoff when there is none.
+    async fn get(&self, url: &str) -> anyhow::Result<reqwest::Response> {
+        let mut backoff = Duration::from_secs(1);
+        let mut attempt = 1;
+
+        loop {
+            let response = self.client.get(url).send().await?;
+            if response.status() != reqwest::StatusCode::TOO_MANY_REQUESTS
+                || attempt >= MAX_ATTEMPTS
+            {
+                return Ok(response.error_for_status()?);
+            }
+
+            let wait = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(backoff)
+                .clamp(Duration::from_secs(1), MAX_BACKOFF);
+
+            tracing::warn!("rate limited, retrying {url} in {}s", wait.as_secs());
+            tokio::time::sleep(wait).await;
+
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            attempt += 1;
+        }
+    }
+
+    async fn sha256(path: &Path) -> std::io::Result<Option<[u8; 32]>> {
+        let mut file = match tokio::fs::File::open(path).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let mut hasher = sha2::Sha256::new();
+        let mut buffer = vec![0; 64 * 1024];
+
+        loop {
+            match file.read(&mut buffer).await? {
+                0 => break,
+                n => hasher.update(&buffer[..n]),
+            }
+        }
+
+        Ok(Some(hasher.finalize().into()))
+    }
+
+    async fn remove(&self, change: &Change) -> anyhow::Result<()> {
+        let path = self.local_path(&change.path)?;
+
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => tracing::info!("removed {}", change.path),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+
+        Ok(())
+    }
+
+    async fn pull(&self, change: &Change) -> anyhow::Result<()> {
+        let (Some(url), Some(sha256)) = (&change.url, &change.sha256) else {
+            anyhow::bail!("change for {} has no url or sha256", change.path);
+        };
+
+        let expected = hex::decode(sha256).context("invalid sha256 in feed")?;
+        let path = self.local_path(&change.path)?;
+
+        if Self::sha256(&path)
+            .await?
+            .is_some_and(|hash| hash[..] == expected[..])
+        {
+            return Ok(());
+        }
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let temporary = path.with_file_name(format!(
+            ".{}.mirror-sync",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+
+        let result = async {
+            let mut response = self.get(&format!("{url}?mirror=none")).await?;
+            let mut file = tokio::fs::File::create(&temporary).await?;
+            let mut hasher = sha2::Sha256::new();
+            let mut size = 0u64;
+
+            while let Some(chunk) = response.chunk().await? {
+                hasher.update(&chunk);
+                file.write_all(&chunk).await?;
+                size += chunk.len() as u64;
+            }
+
+            file.sync_all().await?;
+
+            let actual = hasher.finalize();
+            if actual[..] != expected[..] {
+                anyhow::bail!(
+                    "sha256 mismatch for {} (expected {sha256}, got {}, {size} of {} bytes)",
+                    change.path,
+                    hex::encode(actual),
+                    change.size.unwrap_or_default()
+                );
+            }
+
+            tokio::fs::rename(&temporary, &path).await?;
+
+            Ok::<_, anyhow::Error>(())
+        }
+        .await;
+
+        if result.is_err() {
+            tokio::fs::remove_file(&temporary).await.ok();
+        } else {
+            tracing::info!("pulled {}", change.path);
+        }
+
+        result
+    }
+
+    /// Applies every change after the stored cursor, persisting the cursor after each
+    /// page so an interrupted run resumes where it stopped.
+    async fn sync(&self) -> anyhow::Result<()> {
+        let mut cursor = self.read_cursor().await?;
+
+        loop {
+            let page: Changes = self
+                .get(&format!(
+                    "{}/api/v3/files/changes?cursor={cursor}&limit={PAGE_SIZE}",
+                    self.url
+                ))
+                .await?
+                .json()
+                .await?;
+
+            for change in page.changes.iter() {
+                if change.deleted {
+                    self.remove(change).await?;
+                } else {
+                    self.pull(change).await?;
+                }
+            }
+
+            if page.cursor != cursor {
+                cursor = page.cursor;
+                self.write_cursor(cursor).await?;
+            }
+
+            if !page.has_more {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt()
+        .with_target(false)
+        .with_level(true)
+        .init();
+
+    let mut args = std::env::args().skip(1);
+    let (Some(url), Some(directory)) = (args.next(), args.next()) else {
+        anyhow::bail!(USAGE);
+    };
+
+    let mut interval = None;
+    let mut key = std::env::var("MIRROR_SYNC_KEY")
+        .ok()
+        .filter(|key| !key.is_empty());
+    while let Some(flag) = args.next() {
+        match (flag.as_str(), args.next()) {
+            ("--interval", Some(seconds)) => {
+                interval = Some(Duration::from_secs(
+                    seconds.parse().context("invalid --interval value")?,
+                ));
+            }
+            ("--key", Some(value)) => key = Some(value),
+            _ => anyhow::bail!(USAGE),
+        }
+    }
+
+    tokio::fs::create_dir_all(&directory).await?;
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    if let Some(key) = key {
+        headers.insert(
+            reqwest::header::AUTHORIZATION,
+            key.parse().context("invalid api key")?,
+        );
+    }
+
+    let mirror = Mirror {
+        client: reqwest::Client::builder()
+            .user_agent(concat!("mirror-sync/", env!("CARGO_PKG_VERSION")))
+            .default_headers(headers)
+            .build()?,
+        url: url.trim_end_matches('/').to_string(),
+        directory: PathBuf::from(directory),
+    };
+
+    let Some(interval) = interval else {
+        return mirror.sync().await;
+    };
+
+    loop {
+        if let Err(err) = mirror.sync().await {
+            tracing::error!("failed to sync mirror: {:?}", err);
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
@@ -1,23 +1,184 @@
 use crate::{env::RedisMode, response::ApiResponse};
+use compact_str::CompactString;
+use futures_util::StreamExt;
+use rand::distr::SampleString;
 use rustis::{
     client::Client,
-    commands::{GenericCommands, InfoSection, ServerCommands, SetExpiration, StringCommands},
+    commands::{
+        GenericCommands, InfoSection, PubSubCommands, ScriptingCommands, ServerCommands,
+        SetCondition, SetExpiration, StringCommands,
+    },
     resp::BulkString,
 };
-use serde::{Serialize, de::DeserializeOwned};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use std::{
+    collections::HashMap,
     future::Future,
-    sync::{Arc, atomic::AtomicUsize},
+    sync::{Arc, Mutex, atomic::AtomicUsize},
+    time::{Duration, Instant},
 };
+use utoipa::ToSchema;
+
+const INVALIDATION_CHANNEL: &str = "cache::invalidate";
+
+const LOCK_PREFIX: &str = "cache::lock::";
+const TAG_PREFIX: &str = "cache::tag::";
+const LOCK_TTL: Duration = Duration::from_secs(5);
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+const GET_WITH_TTL_SCRIPT: &str = r#"
+return {redis.call('GET', KEYS[1]), redis.call('PTTL', KEYS[1])}
+"#;
+const TAG_SCRIPT: &str = r#"
+local ttl = tonumber(ARGV[1])
+for i = 1, #KEYS do
+  redis.call('SADD', KEYS[i], ARGV[2])
+  if redis.call('TTL', KEYS[i]) < ttl then
+    redis.call('EXPIRE', KEYS[i], ttl)
+  end
+end
+return #KEYS
+"#;
+const INVALIDATE_TAG_SCRIPT: &str = r#"
+local keys = redis.call('SMEMBERS', KEYS[1])
+for i = 1, #keys, 1000 do
+  redis.call('DEL', unpack(keys, i, math.min(i + 999, #keys)))
+end
+redis.call('DEL', KEYS[1])
+return keys
+"#;
+const UNLOCK_SCRIPT: &str = r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+  return redis.call('DEL', KEYS[1])
+end
+return 0
+"#;
 
 #[derive(Serialize)]
 struct BulkStringRef<'a>(#[serde(serialize_with = "::rustis::resp::serialize_byte_buf")] &'a [u8]);
 
+/// How long [`Cache::cached_with`] keeps a value and the tags it is registered under.
+#[derive(Clone)]
+pub struct CacheOptions {
+    ttl: u64,
+    stale: u64,
+    tags: Vec<CompactString>,
+}
+
+impl CacheOptions {
+    #[inline]
+    pub const fn new(ttl: u64) -> Self {
+        Self {
+            ttl,
+            stale: 0,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Registers the value under `tag`, such as `org:1` or `type:PAPER`, so
+    /// [`Cache::invalidate_tag`] removes it.
+    #[inline]
+    pub fn tag(mut self, tag: impl Into<CompactString>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    /// Keeps the value for up to `stale` seconds after it expires, so
+    /// [`Cache::cached_revalidate`] can serve it while it is recomputed in the
+    /// background. [`Cache::cached_with`] treats it as missing.
+    #[inline]
+    pub const fn stale_while_revalidate(mut self, stale: u64) -> Self {
+        self.stale = stale;
+        self
+    }
+}
+
+#[derive(Deserialize)]
+struct CachedValue<T> {
+    /// Unix timestamp in milliseconds after which the value is stale.
+    fresh_until: i64,
+    value: T,
+}
+
+#[derive(Serialize)]
+struct CachedValueRef<'a, T> {
+    fresh_until: i64,
+    value: &'a T,
+}
+
+/// The computation of one key on this instance, removed from the map once nobody
+/// is waiting on it anymore.
+struct Flight<'a> {
+    flights: &'a Mutex<HashMap<CompactString, Arc<tokio::sync::Mutex<()>>>>,
+    key: &'a str,
+    lock: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl Drop for Flight<'_> {
+    fn drop(&mut self) {
+        let mut flights = self.flights.lock().unwrap();
+
+        // the map and this flight hold the only references
+        if Arc::strong_count(&self.lock) <= 2 {
+            flights.remove(self.key);
+        }
+    }
+}
+
+#[derive(Clone)]
+struct LocalEntry {
+    value: Arc<[u8]>,
+    ttl: Duration,
+}
+
+struct LocalExpiry;
+
+impl moka::Expiry<CompactString, LocalEntry> for LocalExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &CompactString,
+        value: &LocalEntry,
+        _created_at: Instant,
+    ) -> Option<Duration> {
+        Some(value.ttl)
+    }
+}
+
+#[derive(ToSchema, Serialize, Deserialize, Default)]
+pub struct CacheTierStats {
+    pub hits: usize,
+    pub misses: usize,
+}
+
+#[derive(ToSchema, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+#[schema(rename_all = "camelCase")]
+pub struct CacheStats {
+    /// The in-process cache of this instance, misses fall through to Redis.
+    #[schema(inline)]
+    pub local: CacheTierStats,
+    #[schema(inline)]
+    pub redis: CacheTierStats,
+
+    pub local_entries: u64,
+    /// Approximate size of the in-process cache in bytes.
+    pub local_size: u64,
+}
+
 pub struct Cache {
     pub client: Client,
 
-    cache_hits: AtomicUsize,
-    cache_misses: AtomicUsize,
+    /// Serialized values of [`Self::cached`] kept in memory for at most
+    /// `CACHE_LOCAL_MAX_TTL` seconds and never longer than they live in Redis.
+    /// Invalidations are broadcast to every instance over [`INVALIDATION_CHANNEL`].
+    local: Option<moka::sync::Cache<CompactString, LocalEntry>>,
+    local_max_ttl: Duration,
+    flights: Mutex<HashMap<CompactString, Arc<tokio::sync::Mutex<()>>>>,
+
+    local_hits: AtomicUsize,
+    local_misses: AtomicUsize,
+    redis_hits: AtomicUsize,
+    redis_misses: AtomicUsize,
 }
 
 impl Cache {
@@ -39,8 +200,28 @@ impl Cache {
                 .await
                 .unwrap(),
             },
-            cache_hits: AtomicUsize::new(0),
-            cache_misses: AtomicUsize::new(0),
+            local: if env.cache_local_max_ttl > 0 && env.cache_local_max_size > 0 {
+                Some(
+                    moka::sync::Cache::builder()
+                        .name("cache")
+                        .max_capacity(env.cache_local_max_size)
+                        .weigher(|key: &CompactString, entry: &LocalEntry| {
+                            (key.len() + entry.value.len())
+                                .try_into()
+                                .unwrap_or(u32::MAX)
+                        })
+                        .expire_after(LocalExpiry)
+                        .build(),
+                )
+            } else {
+                None
+            },
+            local_max_ttl: Duration::from_secs(env.cache_local_max_ttl),
+            flights: Mutex::new(HashMap::new()),
+            local_hits: AtomicUsize::new(0),
+            local_misses: AtomicUsize::new(0),
+            redis_hits: AtomicUsize::new(0),
+            redis_misses: AtomicUsize::new(0),
         };
 
         let version: String = instance.client.info([InfoSection::Server]).await.unwrap();
@@ -61,17 +242,243 @@ impl Cache {
         instance
     }
 
-    #[inline]
-    pub fn cache_hits(&self) -> usize {
-        self.cache_hits.load(std::sync::atomic::Ordering::Relaxed)
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            local: CacheTierStats {
+                hits: self.local_hits.load(std::sync::atomic::Ordering::Relaxed),
+                misses: self.local_misses.load(std::sync::atomic::Ordering::Relaxed),
+            },
+            redis: CacheTierStats {
+                hits: self.redis_hits.load(std::sync::atomic::Ordering::Relaxed),
+                misses: self.redis_misses.load(std::sync::atomic::Ordering::Relaxed),
+            },
+            local_entries: self.local.as_ref().map_or(0, |local| local.entry_count()),
+            local_size: self.local.as_ref().map_or(0, |local| local.weighted_size()),
+        }
     }
 
-    #[inline]
-    pub fn cache_misses(&self) -> usize {
-        self.cache_misses.load(std::sync::atomic::Ordering::Relaxed)
+    /// Applies invalidations published by any instance to the local cache, resubscribing
+    /// whenever the connection drops.
+    pub async fn listen(&self) {
+        let Some(local) = &self.local else {
+            return;
+        };
+
+        loop {
+            match self.client.subscribe(INVALIDATION_CHANNEL).await {
+                Ok(mut stream) => {
+                    // anything published while we were not subscribed is lost
+                    local.invalidate_all();
+
+                    while let Some(message) = stream.next().await {
+                        match message {
+                            Ok(message) => {
+                                self.invalidate_local(&String::from_utf8_lossy(&message.payload))
+                            }
+                            Err(err) => {
+                                tracing::warn!("cache invalidation subscription failed: {:?}", err);
+                                break;
+                            }
+                        }
+                    }
+                }
+                Err(err) => {
+                    tracing::error!("failed to subscribe to cache invalidations: {:?}", err);
+                }
+            }
+
+            local.invalidate_all();
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+    }
+
+    /// Handles an invalidation message, the keys to drop separated by newlines.
+    fn invalidate_local(&self, message: &str) {
+        let Some(local) = &self.local else {
+            return;
+        };
+
+        for key in message.lines() {
+            local.invalidate(key);
+        }
+    }
+
+    async fn broadcast_invalidation(&self, keys: &[String]) -> Result<(), anyhow::Error> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+
+        let message = keys.join("\n");
+        self.invalidate_local(&message);
+        self.client.publish(INVALIDATION_CHANNEL, message).await?;
+
+        Ok(())
+    }
+
+    fn insert_local(&self, key: &str, value: &[u8], ttl: Duration) {
+        if let Some(local) = &self.local {
+            let ttl = ttl.min(self.local_max_ttl);
+            if ttl.is_zero() {
+                return;
+            }
+
+            local.insert(
+                key.into(),
+                LocalEntry {
+                    value: value.into(),
+                    ttl,
+                },
+            );
+        }
+    }
+
+    /// Reads `key` from the local cache, falling back to Redis. Values read from Redis
+    /// are kept locally for as long as they are fresh.
+    async fn lookup<T: DeserializeOwned>(
+        &self,
+        key: &str,
+        record: bool,
+    ) -> Result<Option<CachedValue<T>>, anyhow::Error> {
+        let count = |counter: &AtomicUsize| {
+            if record {
+                counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            }
+        };
+
+        if let Some(local) = &self.local {
+            if let Some(value) = local
+                .get(key)
+                .and_then(|entry| rmp_serde::from_slice::<CachedValue<T>>(&entry.value).ok())
+            {
+                count(&self.local_hits);
+
+                return Ok(Some(value));
+            }
+
+            count(&self.local_misses);
+        }
+
+        let (cached_value, ttl_ms): (Option<BulkString>, i64) = if self.local.is_some() {
+            self.client
+                .eval(GET_WITH_TTL_SCRIPT, [key], Vec::<String>::new())
+                .await?
+        } else {
+            (self.client.get(key).await?, -1)
+        };
+
+        let Some((value, serialized)) = cached_value.and_then(|v| {
+            rmp_serde::from_slice::<CachedValue<T>>(&v)
+                .ok()
+                .map(|value| (value, v))
+        }) else {
+            return Ok(None);
+        };
+        count(&self.redis_hits);
+
+        // stale values are only served from redis, so a refresh on another
+        // instance is seen right away
+        let fresh = value.fresh_until - chrono::Utc::now().timestamp_millis();
+        let remaining = if ttl_ms >= 0 {
+            fresh.min(ttl_ms)
+        } else {
+            fresh
+        };
+        if remaining > 0 {
+            self.insert_local(key, &serialized, Duration::from_millis(remaining as u64));
+        }
+
+        Ok(Some(value))
+    }
+
+    async fn store<T: Serialize>(
+        &self,
+        key: &str,
+        options: &CacheOptions,
+        value: &T,
+    ) -> Result<(), anyhow::Error> {
+        let serialized = rmp_serde::to_vec(&CachedValueRef {
+            fresh_until: chrono::Utc::now().timestamp_millis() + options.ttl as i64 * 1000,
+            value,
+        })?;
+
+        self.client
+            .set_with_options(
+                key,
+                BulkStringRef(&serialized),
+                None,
+                SetExpiration::Ex(options.ttl + options.stale),
+            )
+            .await?;
+
+        if !options.tags.is_empty() {
+            self.client
+                .eval::<i64>(
+                    TAG_SCRIPT,
+                    options
+                        .tags
+                        .iter()
+                        .map(|tag| format!("{TAG_PREFIX}{tag}"))
+                        .collect::<Vec<_>>(),
+                    [(options.ttl + options.stale).to_string(), key.to_string()],
+                )
+                .await?;
+        }
+        self.insert_local(key, &serialized, Duration::from_secs(options.ttl));
+
+        Ok(())
+    }
+
+    fn flight<'a>(&'a self, key: &'a str) -> Flight<'a> {
+        let lock = self
+            .flights
+            .lock()
+            .unwrap()
+            .entry(key.into())
+            .or_default()
+            .clone();
+
+        Flight {
+            flights: &self.flights,
+            key,
+            lock,
+        }
+    }
+
+    async fn try_lock(&self, key: &str, token: &str) -> Result<bool, anyhow::Error> {
+        Ok(self
+            .client
+            .set_with_options(
+                format!("{LOCK_PREFIX}{key}"),
+                token,
+                Some(SetCondition::NX),
+                SetExpiration::Px(LOCK_TTL.as_millis() as u64),
+            )
+            .await?)
+    }
+
+    /// Claims `name` for `ttl` across every instance, so a periodic job started on each
+    /// of them only runs on the first to claim it. The claim is never released, it expires.
+    pub async fn claim(&self, name: &str, ttl: Duration) -> Result<bool, anyhow::Error> {
+        Ok(self
+            .client
+            .set_with_options(
+                format!("{LOCK_PREFIX}{name}"),
+                "1",
+                Some(SetCondition::NX),
+                SetExpiration::Px(ttl.as_millis() as u64),
+            )
+            .await?)
     }
 
-    #[tracing::instrument(skip(self, fn_compute))]
+    async fn unlock(&self, key: &str, token: &str) -> Result<(), anyhow::Error> {
+        self.client
+            .eval::<i64>(UNLOCK_SCRIPT, [format!("{LOCK_PREFIX}{key}")], [token])
+            .await?;
+
+        Ok(())
+    }
+
+    #[inline]
     pub async fn cached<T, F, Fut, FutErr>(
         &self,
         key: &str,
@@ -84,36 +491,165 @@ impl Cache {
         Fut: Future<Output = Result<T, FutErr>>,
         FutErr: Into<anyhow::Error> + Send + Sync + 'static,
     {
-        let cached_value: Option<BulkString> = self.client.get(key).await?;
+        self.cached_with(key, CacheOptions::new(ttl), fn_compute)
+            .await
+    }
 
-        match cached_value.and_then(|v| rmp_serde::from_slice::<T>(&v).ok()) {
-            Some(value) => {
-                self.cache_hits
-                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    /// Returns the cached value of `key` or computes it. Concurrent misses on this
+    /// instance wait for a single computation, and a short Redis lock keeps other
+    /// instances from running it at the same time.
+    #[tracing::instrument(skip(self, options, fn_compute))]
+    pub async fn cached_with<T, F, Fut, FutErr>(
+        &self,
+        key: &str,
+        options: CacheOptions,
+        fn_compute: F,
+    ) -> Result<T, anyhow::Error>
+    where
+        T: Serialize + DeserializeOwned + Send,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, FutErr>>,
+        FutErr: Into<anyhow::Error> + Send + Sync + 'static,
+    {
+        if let Some(value) = self.lookup_fresh::<T>(key, true).await? {
+            return Ok(value);
+        }
+
+        let flight = self.flight(key);
+        let _guard = flight.lock.lock().await;
 
-                Ok(value)
+        if let Some(value) = self.lookup_fresh::<T>(key, false).await? {
+            return Ok(value);
+        }
+
+        let token = rand::distr::Alphanumeric.sample_string(&mut rand::rng(), 16);
+        let start = Instant::now();
+        let locked = loop {
+            if self.try_lock(key, &token).await? {
+                break true;
             }
-            None => {
-                let result = match fn_compute().await {
-                    Ok(result) => result,
-                    Err(err) => return Err(err.into()),
-                };
-                self.cache_misses
-                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-
-                let serialized = rmp_serde::to_vec(&result)?;
-                self.client
-                    .set_with_options(
-                        key,
-                        BulkStringRef(&serialized),
-                        None,
-                        SetExpiration::Ex(ttl),
-                    )
-                    .await?;
 
-                Ok(result)
+            // give up on the other instance after the lock would have expired
+            if start.elapsed() >= LOCK_TTL {
+                break false;
+            }
+
+            tokio::time::sleep(LOCK_POLL_INTERVAL).await;
+
+            if let Some(value) = self.lookup_fresh::<T>(key, false).await? {
+                return Ok(value);
             }
+        };
+
+        self.compute(key, &options, locked.then_some(token.as_str()), fn_compute)
+            .await
+    }
+
+    /// Like [`Self::cached_with`], but once the value is stale every caller gets it
+    /// right away while a background task recomputes it, at most once across
+    /// instances. Only a missing value is waited on.
+    #[tracing::instrument(skip(self, options, fn_compute))]
+    pub async fn cached_revalidate<T, F, Fut, FutErr>(
+        self: &Arc<Self>,
+        key: &str,
+        options: CacheOptions,
+        fn_compute: F,
+    ) -> Result<T, anyhow::Error>
+    where
+        T: Serialize + DeserializeOwned + Send + 'static,
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<T, FutErr>> + Send + 'static,
+        FutErr: Into<anyhow::Error> + Send + Sync + 'static,
+    {
+        let stale = match self.lookup::<T>(key, true).await? {
+            Some(value) if value.fresh_until > chrono::Utc::now().timestamp_millis() => {
+                return Ok(value.value);
+            }
+            Some(value) => value.value,
+            None => return self.cached_with(key, options, fn_compute).await,
+        };
+
+        // someone on this instance is already refreshing it
+        let flight = self.flight(key);
+        let Ok(guard) = flight.lock.clone().try_lock_owned() else {
+            return Ok(stale);
+        };
+
+        let cache = Arc::clone(self);
+        let key = CompactString::from(key);
+        tokio::spawn(async move {
+            let flight = cache.flight(&key);
+
+            let result = async {
+                if cache.lookup_fresh::<T>(&key, false).await?.is_some() {
+                    return Ok(());
+                }
+
+                let token = rand::distr::Alphanumeric.sample_string(&mut rand::rng(), 16);
+                if !cache.try_lock(&key, &token).await? {
+                    return Ok(());
+                }
+
+                cache
+                    .compute(&key, &options, Some(&token), fn_compute)
+                    .await
+                    .map(|_| ())
+            }
+            .await;
+            if let Err(err) = result {
+                tracing::error!("failed to revalidate cached value {}: {:?}", key, err);
+            }
+
+            // released before the flight so it can be removed from the map
+            drop(guard);
+            drop(flight);
+        });
+
+        Ok(stale)
+    }
+
+    /// Reads `key` and returns it only while it is fresh.
+    async fn lookup_fresh<T: DeserializeOwned>(
+        &self,
+        key: &str,
+        record: bool,
+    ) -> Result<Option<T>, anyhow::Error> {
+        Ok(self
+            .lookup::<T>(key, record)
+            .await?
+            .filter(|value| value.fresh_until > chrono::Utc::now().timestamp_millis())
+            .map(|value| value.value))
+    }
+
+    /// Runs `fn_compute` and stores its result, releasing the Redis lock held under
+    /// `token` afterwards.
+    async fn compute<T, F, Fut, FutErr>(
+        &self,
+        key: &str,
+        options: &CacheOptions,
+        token: Option<&str>,
+        fn_compute: F,
+    ) -> Result<T, anyhow::Error>
+    where
+        T: Serialize,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, FutErr>>,
+        FutErr: Into<anyhow::Error> + Send + Sync + 'static,
+    {
+        let result = fn_compute().await.map_err(Into::into);
+        self.redis_misses
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+
+        let stored = match &result {
+            Ok(result) => self.store(key, options, result).await,
+            Err(_) => Ok(()),
+        };
+        if let Some(token) = token {
+            self.unlock(key, token).await?;
         }
+        stored?;
+
+        result
     }
 
     pub async fn ratelimit(
@@ -188,33 +724,33 @@ impl Cache {
 
     pub async fn invalidate(&self, key: &str) -> Result<(), anyhow::Error> {
         self.client.del(key).await?;
+        self.broadcast_invalidation(&[key.to_string()]).await?;
 
         Ok(())
     }
 
-    pub async fn clear_organization_key(&self, key_id: &str) -> Result<(), anyhow::Error> {
+    /// Removes every value registered under `tag` by [`CacheOptions::tag`].
+    pub async fn invalidate_tag(&self, tag: &str) -> Result<(), anyhow::Error> {
         let keys: Vec<String> = self
             .client
-            .keys(format!("organization::key::{key_id}::*"))
+            .eval(
+                INVALIDATE_TAG_SCRIPT,
+                [format!("{TAG_PREFIX}{tag}")],
+                Vec::<String>::new(),
+            )
             .await?;
-
-        if !keys.is_empty() {
-            self.client.del(keys).await?;
-        }
+        self.broadcast_invalidation(&keys).await?;
 
         Ok(())
     }
 
-    pub async fn clear_organization(&self, organization: i32) -> Result<(), anyhow::Error> {
-        let keys: Vec<String> = self
-            .client
-            .keys(format!("organization::{organization}*"))
-            .await?;
-
-        if !keys.is_empty() {
-            self.client.del(keys).await?;
-        }
+    #[inline]
+    pub async fn clear_organization_key(&self, key_id: &str) -> Result<(), anyhow::Error> {
+        self.invalidate_tag(&format!("org_key:{key_id}")).await
+    }
 
-        Ok(())
+    #[inline]
+    pub async fn clear_organization(&self, organization: i32) -> Result<(), anyhow::Error> {
+        self.invalidate_tag(&format!("org:{organization}")).await
     }
 }
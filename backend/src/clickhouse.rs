@@ -1,6 +1,6 @@
 use clickhouse::query::RowCursor;
 use serde::Deserialize;
-use std::sync::Arc;
+use std::{net::Ipv6Addr, sync::Arc};
 
 pub struct Clickhouse {
     client: clickhouse::Client,
@@ -343,7 +343,7 @@ impl Clickhouse {
                                     uniqExact(ip) AS unique_ips,
                                     sum(bytes_sent) AS total_bytes
                                 FROM file_requests
-                                WHERE status = 200
+                                WHERE status IN (200, 206)
                                 GROUP BY
                                     root,
                                     path,
@@ -468,7 +468,7 @@ impl Clickhouse {
                                 FROM file_requests
                                 WHERE
                                     _partition_date >= toDate(now() - INTERVAL 7 DAY)
-                                    AND status = 200
+                                    AND status IN (200, 206)
                                 GROUP BY
                                     root,
                                     path,
@@ -580,4 +580,64 @@ impl Clickhouse {
     pub fn client(&self) -> &clickhouse::Client {
         &self.client
     }
+
+    /// Drops the monthly partitions of `table` that lie entirely before `days` ago, so
+    /// rows are removed once the whole month they belong to has expired.
+    pub async fn drop_expired_partitions(
+        &self,
+        table: &str,
+        days: u32,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let partitions: Vec<String> = self
+            .client
+            .query(
+                r#"
+                SELECT DISTINCT partition_id
+                FROM system.parts
+                WHERE database = currentDatabase()
+                    AND table = ?
+                    AND active
+                    AND toUInt32OrZero(partition_id) BETWEEN 1 AND toYYYYMM(today() - ?) - 1
+                "#,
+            )
+            .bind(table)
+            .bind(days)
+            .fetch_all()
+            .await?;
+
+        for partition in partitions.iter() {
+            self.client
+                .query(&format!("ALTER TABLE {table} DROP PARTITION ID ?"))
+                .bind(partition)
+                .execute()
+                .await?;
+        }
+
+        Ok(partitions)
+    }
+
+    /// Deletes every `requests` and `file_requests` row recorded for one of `ips`, or
+    /// replaces their address with `::` when `anonymize` is set. Both run as mutations
+    /// that ClickHouse applies in the background.
+    pub async fn erase_ips(&self, ips: &[Ipv6Addr], anonymize: bool) -> Result<(), anyhow::Error> {
+        if ips.is_empty() {
+            return Ok(());
+        }
+
+        let ips = ips.iter().map(|ip| ip.to_string()).collect::<Vec<_>>();
+
+        for table in ["requests", "file_requests"] {
+            let query = if anonymize {
+                format!(
+                    "ALTER TABLE {table} UPDATE ip = toIPv6('::') WHERE has(?, IPv6NumToString(ip))"
+                )
+            } else {
+                format!("ALTER TABLE {table} DELETE WHERE has(?, IPv6NumToString(ip))")
+            };
+
+            self.client.query(&query).bind(&ips).execute().await?;
+        }
+
+        Ok(())
+    }
 }
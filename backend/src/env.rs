@@ -9,11 +9,35 @@ pub enum RedisMode {
     Sentinel,
 }
 
+#[derive(Clone, Copy)]
+pub enum FilesStorage {
+    Local,
+    S3,
+}
+
+/// Applied to client IPs before they are stored with analytics or sessions.
+#[derive(Clone)]
+pub enum IpAnonymization {
+    None,
+    Truncate { ipv4_prefix: u8, ipv6_prefix: u8 },
+    Hash { key: String },
+}
+
+/// How much of a request body is stored for routes that opt into capturing it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RequestBodyCapture {
+    None,
+    Hashes,
+    Full,
+}
+
 #[derive(Clone)]
 pub struct Env {
     pub redis_url: Option<String>,
     pub redis_sentinels: Option<Vec<String>>,
     pub redis_mode: RedisMode,
+    pub cache_local_max_size: u64,
+    pub cache_local_max_ttl: u64,
 
     pub sentry_url: Option<String>,
     pub database_migrate: bool,
@@ -41,11 +65,41 @@ pub struct Env {
     pub clickhouse_username: String,
     pub clickhouse_password: String,
 
+    pub geoip_database: Option<String>,
+    pub geoip_ip_api: bool,
+
+    pub analytics_queue_max_length: u64,
+    pub analytics_batch_size: usize,
+    pub analytics_flush_rows: u64,
+    pub analytics_flush_bytes: u64,
+    pub analytics_flush_interval: u64,
+    pub analytics_retention_days: u32,
+    pub session_retention_days: u32,
+    pub files_changes_retention_days: u32,
+    pub ip_anonymization: IpAnonymization,
+    pub request_body_capture: RequestBodyCapture,
+    pub request_body_max_size: usize,
+
     pub files_cache: String,
+    pub files_cache_max_size: u64,
+    pub files_cache_max_file_size: u64,
+    pub files_cache_min_requests: u32,
+    pub files_cache_warm_count: i64,
+    pub files_cache_warm_budget: u64,
+    pub files_cache_warm_interval: u64,
+    pub files_archive_max_size: u64,
     pub files_location: String,
+    pub files_storage: FilesStorage,
+    pub files_signing_secret: Option<String>,
+    pub files_s3_prefix: String,
+    pub files_mirror_redirect: bool,
+    pub files_mirror_redirect_min_size: u64,
 
     pub bind: String,
     pub port: u16,
+    pub trusted_proxies: Vec<ipnetwork::IpNetwork>,
+    pub proxy_protocol: bool,
+    pub ratelimit_ipv6_prefix: u8,
 
     pub app_debug: bool,
     pub app_url: String,
@@ -74,6 +128,37 @@ impl Env {
             _ => panic!("Invalid REDIS_MODE"),
         };
 
+        let ip_anonymization = match std::env::var("IP_ANONYMIZATION")
+            .unwrap_or("none".to_string())
+            .trim_matches('"')
+        {
+            "none" => IpAnonymization::None,
+            "truncate" => IpAnonymization::Truncate {
+                ipv4_prefix: std::env::var("IP_TRUNCATE_IPV4_PREFIX")
+                    .unwrap_or("24".to_string())
+                    .trim_matches('"')
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|prefix| *prefix <= 32)
+                    .context("Invalid IP_TRUNCATE_IPV4_PREFIX value")?,
+                ipv6_prefix: std::env::var("IP_TRUNCATE_IPV6_PREFIX")
+                    .unwrap_or("48".to_string())
+                    .trim_matches('"')
+                    .parse::<u8>()
+                    .ok()
+                    .filter(|prefix| *prefix <= 128)
+                    .context("Invalid IP_TRUNCATE_IPV6_PREFIX value")?,
+            },
+            "hash" => IpAnonymization::Hash {
+                key: std::env::var("IP_HASH_KEY")
+                    .ok()
+                    .map(|s| s.trim_matches('"').to_string())
+                    .filter(|s| !s.is_empty())
+                    .expect("IP_HASH_KEY is required"),
+            },
+            _ => panic!("Invalid IP_ANONYMIZATION"),
+        };
+
         let env = Self {
             redis_url: match redis_mode {
                 RedisMode::Redis => Some(
@@ -96,6 +181,18 @@ impl Env {
                 ),
             },
             redis_mode,
+            cache_local_max_size: std::env::var("CACHE_LOCAL_MAX_SIZE_MB")
+                .unwrap_or("64".to_string())
+                .trim_matches('"')
+                .parse::<u64>()
+                .context("Invalid CACHE_LOCAL_MAX_SIZE_MB value")?
+                * 1024
+                * 1024,
+            cache_local_max_ttl: std::env::var("CACHE_LOCAL_MAX_TTL")
+                .unwrap_or("10".to_string())
+                .trim_matches('"')
+                .parse()
+                .context("Invalid CACHE_LOCAL_MAX_TTL value")?,
 
             sentry_url: std::env::var("SENTRY_URL")
                 .ok()
@@ -186,6 +283,81 @@ impl Env {
                 .trim_matches('"')
                 .to_string(),
 
+            geoip_database: std::env::var("GEOIP_DATABASE")
+                .ok()
+                .map(|s| s.trim_matches('"').to_string())
+                .filter(|s| !s.is_empty()),
+            // only the fallback for a missing database by default, so a configured
+            // database never sends addresses to ip-api.com unless asked to
+            geoip_ip_api: std::env::var("GEOIP_IP_API")
+                .unwrap_or_else(|_| {
+                    (!std::env::var("GEOIP_DATABASE")
+                        .is_ok_and(|s| !s.trim_matches('"').is_empty()))
+                    .to_string()
+                })
+                .trim_matches('"')
+                .parse()
+                .context("Invalid GEOIP_IP_API value")?,
+
+            analytics_queue_max_length: std::env::var("ANALYTICS_QUEUE_MAX_LENGTH")
+                .unwrap_or("1000000".to_string())
+                .trim_matches('"')
+                .parse()
+                .context("Invalid ANALYTICS_QUEUE_MAX_LENGTH value")?,
+            analytics_batch_size: std::env::var("ANALYTICS_BATCH_SIZE")
+                .unwrap_or("1000".to_string())
+                .trim_matches('"')
+                .parse()
+                .context("Invalid ANALYTICS_BATCH_SIZE value")?,
+            analytics_flush_rows: std::env::var("ANALYTICS_FLUSH_ROWS")
+                .unwrap_or("100000".to_string())
+                .trim_matches('"')
+                .parse()
+                .context("Invalid ANALYTICS_FLUSH_ROWS value")?,
+            analytics_flush_bytes: std::env::var("ANALYTICS_FLUSH_BYTES_MB")
+                .unwrap_or("16".to_string())
+                .trim_matches('"')
+                .parse::<u64>()
+                .context("Invalid ANALYTICS_FLUSH_BYTES_MB value")?
+                * 1024
+                * 1024,
+            analytics_flush_interval: std::env::var("ANALYTICS_FLUSH_INTERVAL")
+                .unwrap_or("5".to_string())
+                .trim_matches('"')
+                .parse()
+                .context("Invalid ANALYTICS_FLUSH_INTERVAL value")?,
+            analytics_retention_days: std::env::var("ANALYTICS_RETENTION_DAYS")
+                .unwrap_or("0".to_string())
+                .trim_matches('"')
+                .parse()
+                .context("Invalid ANALYTICS_RETENTION_DAYS value")?,
+            session_retention_days: std::env::var("SESSION_RETENTION_DAYS")
+                .unwrap_or("0".to_string())
+                .trim_matches('"')
+                .parse()
+                .context("Invalid SESSION_RETENTION_DAYS value")?,
+            files_changes_retention_days: std::env::var("FILES_CHANGES_RETENTION_DAYS")
+                .unwrap_or("30".to_string())
+                .trim_matches('"')
+                .parse()
+                .context("Invalid FILES_CHANGES_RETENTION_DAYS value")?,
+            ip_anonymization,
+            request_body_capture: match std::env::var("REQUEST_BODY_CAPTURE")
+                .unwrap_or("full".to_string())
+                .trim_matches('"')
+            {
+                "none" => RequestBodyCapture::None,
+                "hashes" => RequestBodyCapture::Hashes,
+                "full" => RequestBodyCapture::Full,
+                _ => panic!("Invalid REQUEST_BODY_CAPTURE"),
+            },
+            request_body_max_size: std::env::var("REQUEST_BODY_MAX_SIZE_KB")
+                .unwrap_or("16".to_string())
+                .trim_matches('"')
+                .parse::<usize>()
+                .context("Invalid REQUEST_BODY_MAX_SIZE_KB value")?
+                * 1024,
+
             bind: std::env::var("BIND")
                 .unwrap_or("0.0.0.0".to_string())
                 .trim_matches('"')
@@ -194,15 +366,110 @@ impl Env {
                 .unwrap_or("6969".to_string())
                 .parse()
                 .unwrap(),
+            trusted_proxies: std::env::var("TRUSTED_PROXIES")
+                .unwrap_or(
+                    "127.0.0.0/8,10.0.0.0/8,172.16.0.0/12,192.168.0.0/16,::1/128,fc00::/7"
+                        .to_string(),
+                )
+                .trim_matches('"')
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .context("Invalid TRUSTED_PROXIES value")?,
+            proxy_protocol: std::env::var("PROXY_PROTOCOL")
+                .unwrap_or("false".to_string())
+                .trim_matches('"')
+                .parse()
+                .context("Invalid PROXY_PROTOCOL value")?,
+            ratelimit_ipv6_prefix: std::env::var("RATELIMIT_IPV6_PREFIX")
+                .unwrap_or("64".to_string())
+                .trim_matches('"')
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= 128)
+                .context("Invalid RATELIMIT_IPV6_PREFIX value")?,
 
             files_cache: std::env::var("FILES_CACHE")
                 .unwrap_or("/mnt/mcjars-cache".to_string())
                 .trim_matches('"')
                 .to_string(),
+            files_cache_max_size: std::env::var("FILES_CACHE_MAX_SIZE_MB")
+                .unwrap_or("5120".to_string())
+                .trim_matches('"')
+                .parse::<u64>()
+                .context("Invalid FILES_CACHE_MAX_SIZE_MB value")?
+                * 1024
+                * 1024,
+            files_cache_max_file_size: std::env::var("FILES_CACHE_MAX_FILE_SIZE_MB")
+                .unwrap_or("1024".to_string())
+                .trim_matches('"')
+                .parse::<u64>()
+                .context("Invalid FILES_CACHE_MAX_FILE_SIZE_MB value")?
+                * 1024
+                * 1024,
+            files_cache_min_requests: std::env::var("FILES_CACHE_MIN_REQUESTS")
+                .unwrap_or("1".to_string())
+                .trim_matches('"')
+                .parse()
+                .context("Invalid FILES_CACHE_MIN_REQUESTS value")?,
+            files_cache_warm_count: std::env::var("FILES_CACHE_WARM_COUNT")
+                .unwrap_or("0".to_string())
+                .trim_matches('"')
+                .parse()
+                .context("Invalid FILES_CACHE_WARM_COUNT value")?,
+            files_cache_warm_budget: std::env::var("FILES_CACHE_WARM_BUDGET_MB")
+                .unwrap_or("1024".to_string())
+                .trim_matches('"')
+                .parse::<u64>()
+                .context("Invalid FILES_CACHE_WARM_BUDGET_MB value")?
+                * 1024
+                * 1024,
+            files_cache_warm_interval: std::env::var("FILES_CACHE_WARM_INTERVAL")
+                .unwrap_or("3600".to_string())
+                .trim_matches('"')
+                .parse()
+                .context("Invalid FILES_CACHE_WARM_INTERVAL value")?,
+            files_archive_max_size: std::env::var("FILES_ARCHIVE_MAX_SIZE_MB")
+                .unwrap_or("2048".to_string())
+                .trim_matches('"')
+                .parse::<u64>()
+                .context("Invalid FILES_ARCHIVE_MAX_SIZE_MB value")?
+                * 1024
+                * 1024,
             files_location: std::env::var("FILES_LOCATION")
                 .unwrap_or("/mnt/mcjars".to_string())
                 .trim_matches('"')
                 .to_string(),
+            files_storage: match std::env::var("FILES_STORAGE")
+                .unwrap_or("local".to_string())
+                .trim_matches('"')
+            {
+                "local" => FilesStorage::Local,
+                "s3" => FilesStorage::S3,
+                _ => panic!("Invalid FILES_STORAGE"),
+            },
+            files_signing_secret: std::env::var("FILES_SIGNING_SECRET")
+                .ok()
+                .map(|s| s.trim_matches('"').to_string())
+                .filter(|s| !s.is_empty()),
+            files_s3_prefix: std::env::var("FILES_S3_PREFIX")
+                .unwrap_or("files".to_string())
+                .trim_matches('"')
+                .to_string(),
+            files_mirror_redirect: std::env::var("FILES_MIRROR_REDIRECT")
+                .unwrap_or("false".to_string())
+                .trim_matches('"')
+                .parse()
+                .context("Invalid FILES_MIRROR_REDIRECT value")?,
+            files_mirror_redirect_min_size: std::env::var("FILES_MIRROR_REDIRECT_MIN_SIZE_MB")
+                .unwrap_or("16".to_string())
+                .trim_matches('"')
+                .parse::<u64>()
+                .context("Invalid FILES_MIRROR_REDIRECT_MIN_SIZE_MB value")?
+                * 1024
+                * 1024,
 
             app_debug: std::env::var("APP_DEBUG")
                 .unwrap_or("false".to_string())
@@ -1,20 +1,45 @@
 use crate::models::file::File;
 use chrono::NaiveDateTime;
 use compact_str::ToCompactString;
+use serde::{Deserialize, Serialize};
+use sha2::Digest;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     path::{Path, PathBuf},
+    pin::Pin,
     sync::{
         Arc,
         atomic::{AtomicU64, Ordering},
     },
+    task::{Context, Poll},
 };
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, ReadBuf},
     sync::{Mutex, RwLock, watch},
 };
 
 const BUFFER_SIZE: usize = 64 * 1024;
+const MANIFEST_FILE: &str = "manifest.json";
+
+/// How a file request was served, stored in the `cache_hit` column of `file_requests`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheStatus {
+    Miss,
+    Hit,
+    /// Not served from the cache, including responses that never read the file.
+    Bypass,
+}
+
+impl CacheStatus {
+    #[inline]
+    pub fn as_i8(self) -> i8 {
+        match self {
+            Self::Miss => 0,
+            Self::Hit => 1,
+            Self::Bypass => 2,
+        }
+    }
+}
 
 #[derive(Clone, Copy, PartialEq, Eq)]
 enum FillStatus {
@@ -32,7 +57,9 @@ struct FillProgress {
 struct CachedFile {
     id: u64,
     size: u64,
+    sha256: [u8; 32],
     ready: bool,
+    verified: bool,
 
     last_access: NaiveDateTime,
     last_access_written: bool,
@@ -42,39 +69,450 @@ struct CachedFile {
 
 type CachedFiles = HashMap<Arc<PathBuf>, Arc<Mutex<CachedFile>>>;
 
+/// Paths that were read from storage in full and matched the sha256 they are mapped to,
+/// ranges of these are served without reading the file from the start again.
+type Verified = Arc<RwLock<HashMap<Arc<PathBuf>, [u8; 32]>>>;
+
+#[inline]
+fn path_components(path: &Path) -> Vec<compact_str::CompactString> {
+    path.components()
+        .filter(|c| c.as_os_str().to_str().is_some_and(|s| !s.is_empty()))
+        .map(|c| c.as_os_str().to_string_lossy().to_compact_string())
+        .collect()
+}
+
+/// Paths whose content did not match their `files` row. They are stored in
+/// `file_quarantine` so every instance refuses them and they stay refused across
+/// restarts, each instance keeps a copy that [`FileCache::process`] refreshes.
+#[derive(Clone)]
+struct Quarantine {
+    paths: Arc<RwLock<HashSet<Arc<PathBuf>>>>,
+    database: Arc<crate::database::Database>,
+}
+
+impl Quarantine {
+    #[inline]
+    async fn contains(&self, path: &Path) -> bool {
+        self.paths.read().await.contains(&path.to_path_buf())
+    }
+
+    async fn insert(&self, key: Arc<PathBuf>) {
+        if let Err(err) = sqlx::query(
+            r#"
+            INSERT INTO file_quarantine (path)
+            VALUES ($1::varchar[])
+            ON CONFLICT (path) DO NOTHING
+            "#,
+        )
+        .bind(path_components(&key))
+        .execute(self.database.write())
+        .await
+        {
+            tracing::error!("failed to quarantine file {}: {:?}", key.display(), err);
+        }
+
+        self.paths.write().await.insert(key);
+    }
+
+    async fn remove(&self, key: &Arc<PathBuf>) {
+        if let Err(err) = sqlx::query(
+            r#"
+            DELETE FROM file_quarantine
+            WHERE file_quarantine.path = $1::varchar[]
+            "#,
+        )
+        .bind(path_components(key))
+        .execute(self.database.write())
+        .await
+        {
+            tracing::error!("failed to release file {}: {:?}", key.display(), err);
+        }
+
+        self.paths.write().await.remove(key);
+    }
+
+    async fn refresh(&self) -> Result<(), anyhow::Error> {
+        let paths = sqlx::query_scalar::<_, Vec<compact_str::CompactString>>(
+            r#"
+            SELECT file_quarantine.path
+            FROM file_quarantine
+            "#,
+        )
+        .fetch_all(self.database.read())
+        .await?
+        .into_iter()
+        .map(|path| {
+            Arc::new(
+                path.into_iter()
+                    .map(|component| component.to_string())
+                    .collect::<PathBuf>(),
+            )
+        })
+        .collect();
+
+        *self.paths.write().await = paths;
+
+        Ok(())
+    }
+}
+
+/// Discards the first `remaining` bytes of `inner`, so a ranged read can still be
+/// verified from the start of the file.
+struct SkipReader<R> {
+    inner: R,
+    remaining: u64,
+    buffer: Box<[u8]>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for SkipReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = &mut *self;
+
+        while this.remaining > 0 {
+            let len = this.remaining.min(this.buffer.len() as u64) as usize;
+            let mut skipped = ReadBuf::new(&mut this.buffer[..len]);
+            std::task::ready!(Pin::new(&mut this.inner).poll_read(cx, &mut skipped))?;
+
+            if skipped.filled().is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            this.remaining -= skipped.filled().len() as u64;
+        }
+
+        Pin::new(&mut this.inner).poll_read(cx, buf)
+    }
+}
+
+/// Logs a source file whose content does not match its `files` row and reports it to Sentry.
+fn report_corruption(path: &Path, expected: (&[u8], u64), actual: (&[u8], u64)) {
+    let err = anyhow::anyhow!(
+        "integrity check failed for {}: expected sha256 {} ({} bytes), got {} ({} bytes)",
+        path.display(),
+        hex::encode(expected.0),
+        expected.1,
+        hex::encode(actual.0),
+        actual.1
+    );
+
+    tracing::error!("{:?}", err);
+    sentry_anyhow::capture_anyhow(&err);
+}
+
+/// Hashes a file read directly from storage while it is streamed. The read that
+/// completes the file fails instead of returning its bytes when the hash does not match,
+/// so clients never receive a complete but corrupt body.
+struct VerifiedReader<R> {
+    inner: R,
+    key: Arc<PathBuf>,
+    quarantine: Quarantine,
+    verified: Verified,
+
+    hasher: sha2::Sha256,
+    expected: [u8; 32],
+    size: u64,
+    read: u64,
+    finished: bool,
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for VerifiedReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled = buf.filled().len();
+        std::task::ready!(Pin::new(&mut self.inner).poll_read(cx, buf))?;
+
+        if self.finished {
+            return Poll::Ready(Ok(()));
+        }
+
+        let chunk = &buf.filled()[filled..];
+        self.hasher.update(chunk);
+        self.read += chunk.len() as u64;
+
+        if chunk.is_empty() || self.read >= self.size {
+            self.finished = true;
+
+            let digest = std::mem::take(&mut self.hasher).finalize();
+            if self.read != self.size || digest.as_slice() != self.expected {
+                report_corruption(
+                    &self.key,
+                    (&self.expected, self.size),
+                    (digest.as_slice(), self.read),
+                );
+
+                let (key, quarantine, verified) = (
+                    self.key.clone(),
+                    self.quarantine.clone(),
+                    self.verified.clone(),
+                );
+                tokio::spawn(async move {
+                    verified.write().await.remove(&key);
+                    quarantine.insert(key).await;
+                });
+
+                return Poll::Ready(Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "file failed integrity verification",
+                )));
+            }
+
+            let (key, expected, verified) =
+                (self.key.clone(), self.expected, self.verified.clone());
+            tokio::spawn(async move {
+                verified.write().await.insert(key, expected);
+            });
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Outcome of re-hashing a source file against its `files` row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    Valid,
+    Mismatch,
+    Missing,
+}
+
+/// Persisted to `files_cache` so completed cache entries survive restarts.
+#[derive(Serialize, Deserialize)]
+struct ManifestEntry {
+    id: u64,
+    path: PathBuf,
+    size: u64,
+    sha256: String,
+    last_access: NaiveDateTime,
+}
+
 pub struct FileCache {
     id: Arc<AtomicU64>,
     total_size: Arc<AtomicU64>,
     max_cache_size: u64,
     cached_files: Arc<RwLock<CachedFiles>>,
+    uncached_requests: Mutex<HashMap<Arc<PathBuf>, (u32, NaiveDateTime)>>,
+    quarantine: Quarantine,
+    verified: Verified,
+    /// Paths evicted to make space while a warm run is active, `None` outside of one.
+    evicted: Mutex<Option<HashSet<Arc<PathBuf>>>>,
 
+    storage: Arc<dyn crate::storage::Storage>,
     database: Arc<crate::database::Database>,
     env: Arc<crate::env::Env>,
 }
 
 impl FileCache {
-    pub async fn new(database: Arc<crate::database::Database>, env: Arc<crate::env::Env>) -> Self {
-        tokio::fs::remove_dir_all(&env.files_cache)
-            .await
-            .unwrap_or_default();
+    pub async fn new(
+        database: Arc<crate::database::Database>,
+        env: Arc<crate::env::Env>,
+        storage: Arc<dyn crate::storage::Storage>,
+    ) -> Self {
         tokio::fs::create_dir_all(&env.files_cache).await.unwrap();
 
+        let (cached_files, total_size, next_id) =
+            Self::restore(Path::new(&env.files_cache)).await.unwrap();
+
+        tracing::info!(
+            "file cache restored ({} files, {} bytes)",
+            cached_files.len(),
+            total_size
+        );
+
+        let quarantine = Quarantine {
+            paths: Arc::new(RwLock::new(HashSet::new())),
+            database: database.clone(),
+        };
+        quarantine.refresh().await.unwrap();
+
         Self {
-            id: Arc::new(AtomicU64::new(0)),
-            total_size: Arc::new(AtomicU64::new(0)),
-            max_cache_size: 5 * 1024 * 1024 * 1024,
-            cached_files: Arc::new(RwLock::new(HashMap::new())),
+            id: Arc::new(AtomicU64::new(next_id)),
+            total_size: Arc::new(AtomicU64::new(total_size)),
+            max_cache_size: env.files_cache_max_size,
+            cached_files: Arc::new(RwLock::new(cached_files)),
+            uncached_requests: Mutex::new(HashMap::new()),
+            quarantine,
+            verified: Arc::new(RwLock::new(HashMap::new())),
+            evicted: Mutex::new(None),
+            storage,
             database,
             env,
         }
     }
 
-    /// Returns whether the file was already resident in the local cache alongside its reader.
+    /// Re-adopts every manifest entry whose cache file is still complete and deletes
+    /// everything else in the cache directory (orphans and truncated fills).
+    async fn restore(files_cache: &Path) -> std::io::Result<(CachedFiles, u64, u64)> {
+        let entries: Vec<ManifestEntry> =
+            match tokio::fs::read(files_cache.join(MANIFEST_FILE)).await {
+                Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|err| {
+                    tracing::warn!("failed to parse file cache manifest: {:?}", err);
+                    Vec::new()
+                }),
+                Err(_) => Vec::new(),
+            };
+
+        let mut cached_files = HashMap::new();
+        let mut adopted = HashSet::new();
+        let mut total_size = 0;
+        let mut next_id = 0;
+
+        for entry in entries {
+            let mut sha256 = [0; 32];
+            if hex::decode_to_slice(&entry.sha256, &mut sha256).is_err()
+                || cached_files.contains_key(&entry.path)
+            {
+                continue;
+            }
+
+            let name = entry.id.to_string();
+            match tokio::fs::metadata(files_cache.join(&name)).await {
+                Ok(metadata) if metadata.is_file() && metadata.len() == entry.size => {}
+                _ => continue,
+            }
+
+            let (_, progress) = watch::channel(FillProgress {
+                written: entry.size,
+                status: FillStatus::Done,
+            });
+
+            cached_files.insert(
+                Arc::new(entry.path),
+                Arc::new(Mutex::new(CachedFile {
+                    id: entry.id,
+                    size: entry.size,
+                    sha256,
+                    ready: true,
+                    verified: false,
+                    last_access: entry.last_access,
+                    last_access_written: true,
+                    progress,
+                })),
+            );
+
+            total_size += entry.size;
+            next_id = next_id.max(entry.id + 1);
+            adopted.insert(name);
+        }
+
+        let mut directory = tokio::fs::read_dir(files_cache).await?;
+        while let Some(entry) = directory.next_entry().await? {
+            let name = entry.file_name();
+            if name == MANIFEST_FILE || name.to_str().is_some_and(|n| adopted.contains(n)) {
+                continue;
+            }
+
+            let removed = if entry.file_type().await?.is_dir() {
+                tokio::fs::remove_dir_all(entry.path()).await
+            } else {
+                tokio::fs::remove_file(entry.path()).await
+            };
+
+            if let Err(err) = removed {
+                tracing::error!(
+                    "failed to remove orphaned cache file {}: {:?}",
+                    entry.path().display(),
+                    err
+                );
+            }
+        }
+
+        Ok((cached_files, total_size, next_id))
+    }
+
+    async fn write_manifest(&self) -> Result<(), anyhow::Error> {
+        let cached_files = self
+            .cached_files
+            .read()
+            .await
+            .iter()
+            .map(|(path, file)| (path.clone(), file.clone()))
+            .collect::<Vec<_>>();
+
+        let mut entries = Vec::with_capacity(cached_files.len());
+        for (path, file) in cached_files {
+            let file = file.lock().await;
+            if !file.ready {
+                continue;
+            }
+
+            entries.push(ManifestEntry {
+                id: file.id,
+                path: path.to_path_buf(),
+                size: file.size,
+                sha256: hex::encode(file.sha256),
+                last_access: file.last_access,
+            });
+        }
+
+        let manifest = Path::new(&self.env.files_cache).join(MANIFEST_FILE);
+        let temporary = manifest.with_extension("json.tmp");
+
+        tokio::fs::write(&temporary, serde_json::to_vec(&entries)?).await?;
+        tokio::fs::rename(&temporary, &manifest).await?;
+
+        Ok(())
+    }
+
+    /// Entries restored from the manifest are only served once their recorded identity
+    /// matches the `files` row, anything else is discarded and filled again.
+    async fn confirm(
+        &self,
+        key: &Arc<PathBuf>,
+        entry: &Arc<Mutex<CachedFile>>,
+        file: &File,
+    ) -> bool {
+        let (id, size) = {
+            let mut cached = entry.lock().await;
+            if cached.verified {
+                return true;
+            }
+
+            if cached.sha256 == file.sha256 && cached.size == file.size as u64 {
+                cached.verified = true;
+                return true;
+            }
+
+            (cached.id, cached.size)
+        };
+
+        let mut cached_files = self.cached_files.write().await;
+        if cached_files
+            .get(key)
+            .is_some_and(|current| Arc::ptr_eq(current, entry))
+        {
+            cached_files.remove(key);
+            self.total_size.fetch_sub(size, Ordering::Relaxed);
+            drop(cached_files);
+
+            tokio::fs::remove_file(Path::new(&self.env.files_cache).join(id.to_string()))
+                .await
+                .unwrap_or_default();
+
+            tracing::warn!(
+                "discarded cached file {} that no longer matches its files row",
+                key.display()
+            );
+        }
+
+        false
+    }
+
+    /// Returns how the file is being served alongside its reader, positioned at `offset`
+    /// bytes into the file. Files that are not admitted to the cache are read directly
+    /// from storage.
     pub async fn get(
         &self,
         path: &Path,
         file: &File,
-    ) -> std::io::Result<(bool, Box<dyn tokio::io::AsyncRead + Send + Unpin>)> {
+        offset: u64,
+    ) -> std::io::Result<(CacheStatus, Box<dyn tokio::io::AsyncRead + Send + Unpin>)> {
         if file.is_directory {
             return Err(std::io::Error::other("cannot get file for directory"));
         }
@@ -82,22 +520,48 @@ impl FileCache {
         let key = Arc::new(path.to_path_buf());
 
         let existing = self.cached_files.read().await.get(&key).cloned();
-        if let Some(entry) = existing {
-            return Ok((true, self.serve(entry).await?));
+        if let Some(entry) = existing
+            && self.confirm(&key, &entry, file).await
+        {
+            return Ok((CacheStatus::Hit, self.serve(entry, offset).await?));
         }
 
         let file_size = file.size as u64;
+        if !self.admit(&key, file_size).await {
+            return Ok((CacheStatus::Bypass, self.bypass(key, file, offset).await?));
+        }
+
         let mut map = self.cached_files.write().await;
 
         if let Some(entry) = map.get(&key).cloned() {
             drop(map);
-            return Ok((true, self.serve(entry).await?));
+            return Ok((CacheStatus::Hit, self.serve(entry, offset).await?));
         }
 
-        if self.total_size.load(Ordering::Relaxed) + file_size > self.max_cache_size {
-            self.make_space_for_file(file_size, &mut map).await?;
+        if self.total_size.load(Ordering::Relaxed) + file_size > self.max_cache_size
+            && let Err(err) = self.make_space_for_file(file_size, &mut map).await
+        {
+            drop(map);
+            tracing::warn!("bypassing cache for {}: {:?}", path.display(), err);
+
+            return Ok((CacheStatus::Bypass, self.bypass(key, file, offset).await?));
         }
 
+        let entry = self.start_fill(&mut map, key, file);
+        drop(map);
+
+        Ok((CacheStatus::Miss, self.serve(entry, offset).await?))
+    }
+
+    /// Reserves space for `file` in `map` and starts copying it into the cache, the
+    /// caller has to make sure enough space is available.
+    fn start_fill(
+        &self,
+        map: &mut CachedFiles,
+        key: Arc<PathBuf>,
+        file: &File,
+    ) -> Arc<Mutex<CachedFile>> {
+        let file_size = file.size as u64;
         let id = self.id.fetch_add(1, Ordering::Relaxed);
         let (tx, rx) = watch::channel(FillProgress {
             written: 0,
@@ -107,7 +571,9 @@ impl FileCache {
         let entry = Arc::new(Mutex::new(CachedFile {
             id,
             size: file_size,
+            sha256: file.sha256,
             ready: false,
+            verified: true,
             last_access: chrono::Utc::now().naive_utc(),
             last_access_written: false,
             progress: rx,
@@ -115,16 +581,223 @@ impl FileCache {
 
         map.insert(key.clone(), entry.clone());
         self.total_size.fetch_add(file_size, Ordering::Relaxed);
-        drop(map);
 
-        self.spawn_fill(key, entry.clone(), path.to_path_buf(), id, file_size, tx);
+        self.spawn_fill(key, entry.clone(), id, file_size, file.sha256, tx);
+
+        entry
+    }
+
+    /// Loads `files` (most popular first) into the cache one at a time until `budget`
+    /// bytes were filled. Admission thresholds are skipped, but paths that had to be
+    /// evicted for space during this run are not loaded again, and the run stops once
+    /// no more space can be freed. Returns the number of files warmed.
+    pub async fn warm(&self, files: Vec<(PathBuf, File)>, budget: u64) -> usize {
+        *self.evicted.lock().await = Some(HashSet::new());
+
+        let mut used = 0;
+        let mut warmed = 0;
+
+        for (path, file) in files {
+            let size = file.size as u64;
+            if file.is_directory
+                || size > self.env.files_cache_max_file_size
+                || used + size > budget
+            {
+                continue;
+            }
+
+            let key = Arc::new(path);
+            if self.quarantine.contains(&key).await
+                || self
+                    .evicted
+                    .lock()
+                    .await
+                    .as_ref()
+                    .is_some_and(|evicted| evicted.contains(&key))
+            {
+                continue;
+            }
+
+            let mut map = self.cached_files.write().await;
+            if map.contains_key(&key) {
+                continue;
+            }
+
+            if self.total_size.load(Ordering::Relaxed) + size > self.max_cache_size
+                && let Err(err) = self.make_space_for_file(size, &mut map).await
+            {
+                tracing::info!("stopping cache warm-up at {}: {:?}", key.display(), err);
+                break;
+            }
+
+            let mut progress = self
+                .start_fill(&mut map, key, &file)
+                .lock()
+                .await
+                .progress
+                .clone();
+            drop(map);
+
+            used += size;
+
+            loop {
+                let current_progress = *progress.borrow_and_update();
+
+                match current_progress.status {
+                    FillStatus::Done => {
+                        warmed += 1;
+                        break;
+                    }
+                    FillStatus::Failed => break,
+                    FillStatus::InProgress => {
+                        if progress.changed().await.is_err() {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+
+        *self.evicted.lock().await = None;
+
+        tracing::info!("warmed file cache with {} files ({} bytes)", warmed, used);
+
+        warmed
+    }
+
+    /// Applies the cache admission policy from `Env`: files above the per-file threshold
+    /// are never cached, everything else only once it was requested often enough.
+    async fn admit(&self, key: &Arc<PathBuf>, size: u64) -> bool {
+        if size > self.env.files_cache_max_file_size || size > self.max_cache_size {
+            return false;
+        }
+
+        if self.env.files_cache_min_requests <= 1 {
+            return true;
+        }
+
+        let mut uncached_requests = self.uncached_requests.lock().await;
+        let (requests, last_request) = uncached_requests
+            .entry(key.clone())
+            .or_insert((0, chrono::Utc::now().naive_utc()));
+
+        *requests += 1;
+        *last_request = chrono::Utc::now().naive_utc();
+
+        if *requests >= self.env.files_cache_min_requests {
+            uncached_requests.remove(key);
+            return true;
+        }
+
+        false
+    }
+
+    /// Reads are verified from the start of the file, ranges of a file that was not
+    /// verified yet read and discard everything before `offset` first.
+    async fn bypass(
+        &self,
+        key: Arc<PathBuf>,
+        file: &File,
+        offset: u64,
+    ) -> std::io::Result<Box<dyn tokio::io::AsyncRead + Send + Unpin>> {
+        if offset > 0 && self.verified.read().await.get(&key) == Some(&file.sha256) {
+            return self.storage.open(&key, offset).await;
+        }
+
+        let reader = VerifiedReader {
+            inner: self.storage.open(&key, 0).await?,
+            key,
+            quarantine: self.quarantine.clone(),
+            verified: self.verified.clone(),
+            hasher: sha2::Sha256::new(),
+            expected: file.sha256,
+            size: file.size as u64,
+            read: 0,
+            finished: false,
+        };
+        if offset == 0 {
+            return Ok(Box::new(reader));
+        }
+
+        Ok(Box::new(SkipReader {
+            inner: reader,
+            remaining: offset,
+            buffer: vec![0; BUFFER_SIZE].into_boxed_slice(),
+        }))
+    }
+
+    /// Files whose content did not match their `files` row are refused until an admin
+    /// re-verifies them successfully.
+    #[inline]
+    pub async fn is_quarantined(&self, path: &Path) -> bool {
+        self.quarantine.contains(path).await
+    }
+
+    /// Re-hashes the source file against its `files` row. Mismatching files are quarantined
+    /// and dropped from the cache, matching files are released from quarantine.
+    pub async fn verify(&self, path: &Path, file: &File) -> std::io::Result<Verification> {
+        let key = Arc::new(path.to_path_buf());
+
+        let mut reader = match self.storage.open(path, 0).await {
+            Ok(reader) => reader,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                self.verified.write().await.remove(&key);
+                self.quarantine.insert(key).await;
+                return Ok(Verification::Missing);
+            }
+            Err(err) => return Err(err),
+        };
+
+        let mut hasher = sha2::Sha256::new();
+        let mut buffer = vec![0; BUFFER_SIZE];
+        let mut read = 0;
+
+        loop {
+            match reader.read(&mut buffer).await? {
+                0 => break,
+                n => {
+                    hasher.update(&buffer[..n]);
+                    read += n as u64;
+                }
+            }
+        }
+
+        let digest = hasher.finalize();
+        if read == file.size as u64 && digest.as_slice() == file.sha256 {
+            self.verified.write().await.insert(key.clone(), file.sha256);
+            self.quarantine.remove(&key).await;
+            return Ok(Verification::Valid);
+        }
+
+        report_corruption(
+            path,
+            (&file.sha256, file.size as u64),
+            (digest.as_slice(), read),
+        );
+
+        self.verified.write().await.remove(&key);
+        self.quarantine.insert(key.clone()).await;
 
-        Ok((false, self.serve(entry).await?))
+        let mut cached_files = self.cached_files.write().await;
+        if let Some(entry) = cached_files.get(&key).cloned() {
+            let entry = entry.lock().await;
+            if entry.ready {
+                cached_files.remove(&key);
+                self.total_size.fetch_sub(entry.size, Ordering::Relaxed);
+
+                tokio::fs::remove_file(Path::new(&self.env.files_cache).join(entry.id.to_string()))
+                    .await
+                    .unwrap_or_default();
+            }
+        }
+
+        Ok(Verification::Mismatch)
     }
 
     async fn serve(
         &self,
         entry: Arc<Mutex<CachedFile>>,
+        offset: u64,
     ) -> std::io::Result<Box<dyn tokio::io::AsyncRead + Send + Unpin>> {
         let (id, ready, progress) = {
             let mut entry = entry.lock().await;
@@ -136,17 +809,29 @@ impl FileCache {
         let path = Path::new(&self.env.files_cache).join(id.to_string());
 
         if ready {
-            return Ok(Box::new(tokio::fs::File::open(path).await?));
+            let mut file = tokio::fs::File::open(path).await?;
+            if offset > 0 {
+                file.seek(std::io::SeekFrom::Start(offset)).await?;
+            }
+
+            return Ok(Box::new(file));
         }
 
-        Ok(Box::new(Self::follow(path, progress)))
+        Ok(Box::new(Self::follow(path, progress, offset)))
+    }
+
+    /// Returns the modification time of the source file, used as `Last-Modified`.
+    #[inline]
+    pub async fn modified(&self, path: &Path) -> Option<chrono::DateTime<chrono::Utc>> {
+        self.storage.modified(path).await
     }
 
     fn follow(
         path: PathBuf,
         mut progress: watch::Receiver<FillProgress>,
+        offset: u64,
     ) -> impl tokio::io::AsyncRead + Send + Unpin {
-        let (reader, mut writer) = tokio::io::duplex(BUFFER_SIZE);
+        let (reader, mut writer) = crate::storage::pipe(BUFFER_SIZE);
 
         tokio::spawn(async move {
             let mut run = async || -> std::io::Result<()> {
@@ -157,27 +842,34 @@ impl FileCache {
                         FillStatus::Failed => {
                             return Err(std::io::Error::other("cache fill failed"));
                         }
-                        _ if current_progress.written > 0
+                        _ if current_progress.written > offset
                             || current_progress.status == FillStatus::Done =>
                         {
                             break tokio::fs::File::open(&path).await?;
                         }
                         _ => {
                             if progress.changed().await.is_err() {
-                                return Ok(());
+                                return Err(std::io::Error::other("cache fill stopped"));
                             }
                         }
                     }
                 };
 
+                if offset > 0 {
+                    file.seek(std::io::SeekFrom::Start(offset)).await?;
+                }
+
                 let mut buffer = vec![0; BUFFER_SIZE];
-                let mut sent = 0;
+                let mut sent = offset;
 
                 loop {
                     let current_progress = *progress.borrow_and_update();
 
+                    // the file on disk may be ahead of what the filler published
                     while sent < current_progress.written {
-                        match file.read(&mut buffer).await? {
+                        let limit = (current_progress.written - sent).min(BUFFER_SIZE as u64);
+
+                        match file.read(&mut buffer[..limit as usize]).await? {
                             0 => break,
                             n => {
                                 writer.write_all(&buffer[..n]).await?;
@@ -193,7 +885,7 @@ impl FileCache {
                         }
                         _ => {
                             if progress.changed().await.is_err() {
-                                return Ok(());
+                                return Err(std::io::Error::other("cache fill stopped"));
                             }
                         }
                     }
@@ -202,32 +894,38 @@ impl FileCache {
 
             if let Err(err) = run().await {
                 tracing::warn!("cache follower stopped early: {err:?}");
+                writer.fail(err);
             }
         });
 
         reader
     }
 
+    /// Copies the source file into the cache while hashing it. Followers are never told
+    /// about the final byte before the sha256 matched, a corrupt fill is failed, removed
+    /// and its path quarantined.
     fn spawn_fill(
         &self,
         key: Arc<PathBuf>,
         entry: Arc<Mutex<CachedFile>>,
-        rel_path: PathBuf,
         id: u64,
         reserved: u64,
+        sha256: [u8; 32],
         tx: watch::Sender<FillProgress>,
     ) {
-        let source = Path::new(&self.env.files_location).join(&rel_path);
+        let storage = self.storage.clone();
         let destination = Path::new(&self.env.files_cache).join(id.to_string());
         let cached_files = self.cached_files.clone();
+        let quarantine = self.quarantine.clone();
         let total_size = self.total_size.clone();
 
         tokio::spawn(async move {
             let mut written = 0;
 
             let run = async {
-                let mut reader = tokio::fs::File::open(&source).await?;
+                let mut reader = storage.open(&key, 0).await?;
                 let mut cache = tokio::fs::File::create(&destination).await?;
+                let mut hasher = sha2::Sha256::new();
                 let mut buffer = vec![0; BUFFER_SIZE];
 
                 loop {
@@ -235,10 +933,11 @@ impl FileCache {
                         0 => break,
                         n => {
                             cache.write_all(&buffer[..n]).await?;
+                            hasher.update(&buffer[..n]);
                             written += n as u64;
 
                             tx.send_replace(FillProgress {
-                                written,
+                                written: written.min(reserved.saturating_sub(1)),
                                 status: FillStatus::InProgress,
                             });
                         }
@@ -246,30 +945,20 @@ impl FileCache {
                 }
 
                 cache.flush().await?;
-                Ok::<_, std::io::Error>(())
+                Ok::<_, std::io::Error>(hasher.finalize())
             }
             .await;
 
             match run {
-                Ok(()) => {
+                Ok(digest) if written == reserved && digest.as_slice() == sha256 => {
                     tx.send_replace(FillProgress {
                         written,
                         status: FillStatus::Done,
                     });
 
-                    {
-                        let mut entry = entry.lock().await;
-                        entry.size = written;
-                        entry.ready = true;
-                    }
-
-                    if written >= reserved {
-                        total_size.fetch_add(written - reserved, Ordering::Relaxed);
-                    } else {
-                        total_size.fetch_sub(reserved - written, Ordering::Relaxed);
-                    }
+                    entry.lock().await.ready = true;
                 }
-                Err(err) => {
+                run => {
                     tx.send_replace(FillProgress {
                         written: 0,
                         status: FillStatus::Failed,
@@ -281,7 +970,20 @@ impl FileCache {
                         .unwrap_or_default();
                     total_size.fetch_sub(reserved, Ordering::Relaxed);
 
-                    tracing::error!("cache fill failed for {}: {err:?}", source.display());
+                    match run {
+                        Ok(digest) => {
+                            report_corruption(
+                                &key,
+                                (&sha256, reserved),
+                                (digest.as_slice(), written),
+                            );
+
+                            quarantine.insert(key).await;
+                        }
+                        Err(err) => {
+                            tracing::error!("cache fill failed for {}: {err:?}", key.display());
+                        }
+                    }
                 }
             }
         });
@@ -351,6 +1053,10 @@ impl FileCache {
                 removed_count += 1;
                 cached_files.remove(&path);
                 self.total_size.fetch_sub(size, Ordering::Relaxed);
+
+                if let Some(evicted) = self.evicted.lock().await.as_mut() {
+                    evicted.insert(path);
+                }
             }
         }
 
@@ -371,6 +1077,10 @@ impl FileCache {
     }
 
     pub async fn process(&self) -> Result<(), anyhow::Error> {
+        if let Err(err) = self.quarantine.refresh().await {
+            tracing::error!("failed to refresh quarantined files: {:?}", err);
+        }
+
         let pending_files = {
             let cached_files = self.cached_files.read().await;
             cached_files
@@ -399,11 +1109,7 @@ impl FileCache {
                 WHERE files.path = $2::varchar[] AND (files.last_access IS NULL OR files.last_access < $1)
                 "#,
                 last_access,
-                &path
-                    .components()
-                    .filter(|c| c.as_os_str().to_str().is_some_and(|s| !s.is_empty()))
-                    .map(|c| c.as_os_str().to_string_lossy().to_compact_string())
-                    .collect::<Vec<_>>() as &[compact_str::CompactString]
+                &path_components(&path) as &[compact_str::CompactString]
             )
             .execute(self.database.write())
             .await
@@ -419,6 +1125,11 @@ impl FileCache {
         let deletion_threshold =
             chrono::Utc::now().naive_utc() - std::time::Duration::from_hours(24);
 
+        self.uncached_requests
+            .lock()
+            .await
+            .retain(|_, (_, last_request)| *last_request >= deletion_threshold);
+
         let mut cached_files = self.cached_files.write().await;
         let deletable = cached_files
             .iter()
@@ -447,6 +1158,9 @@ impl FileCache {
             self.total_size.fetch_sub(size, Ordering::Relaxed);
         }
 
+        drop(cached_files);
+        self.write_manifest().await?;
+
         Ok(())
     }
 }
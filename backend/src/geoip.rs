@@ -0,0 +1,316 @@
+use futures_util::future::BoxFuture;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant, SystemTime},
+};
+use tokio::sync::Mutex;
+
+/// `[continent, country]` as two letter codes.
+pub type Location = [compact_str::CompactString; 2];
+
+/// Resolves IP addresses to the continent and country stored with `requests` and
+/// `file_requests`. Addresses that cannot be resolved are left out of the result.
+pub trait GeoIpResolver: Send + Sync {
+    fn resolve<'a>(
+        &'a self,
+        ips: &'a [IpAddr],
+    ) -> BoxFuture<'a, Result<HashMap<IpAddr, Location>, anyhow::Error>>;
+}
+
+/// Loopback, private and unique local addresses never resolve to a location.
+#[inline]
+pub fn is_public(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !ip.is_private() && !ip.is_loopback() && !ip.is_unspecified(),
+        IpAddr::V6(ip) => !ip.is_loopback() && !ip.is_unique_local() && !ip.is_unspecified(),
+    }
+}
+
+/// The `.mmdb` resolver when `GEOIP_DATABASE` is set, with ip-api.com as fallback for
+/// unresolved addresses only when `GEOIP_IP_API=true`. Without a database ip-api.com is
+/// used on its own unless `GEOIP_IP_API=false`, as it was before databases were supported.
+pub fn from_env(env: &crate::env::Env) -> Arc<dyn GeoIpResolver> {
+    let ip_api = env.geoip_ip_api.then(IpApiResolver::new);
+
+    match (env.geoip_database.as_ref(), ip_api) {
+        (Some(database), Some(ip_api)) => Arc::new(FallbackResolver {
+            primary: Box::new(MaxMindResolver::new(PathBuf::from(database))),
+            fallback: Box::new(ip_api),
+        }),
+        (Some(database), None) => Arc::new(MaxMindResolver::new(PathBuf::from(database))),
+        (None, Some(ip_api)) => Arc::new(ip_api),
+        (None, None) => {
+            tracing::warn!(
+                "GEOIP_IP_API is disabled and no GEOIP_DATABASE is set, requests will have no location"
+            );
+            Arc::new(NoopResolver)
+        }
+    }
+}
+
+pub struct NoopResolver;
+
+impl GeoIpResolver for NoopResolver {
+    fn resolve<'a>(
+        &'a self,
+        _ips: &'a [IpAddr],
+    ) -> BoxFuture<'a, Result<HashMap<IpAddr, Location>, anyhow::Error>> {
+        Box::pin(async { Ok(HashMap::new()) })
+    }
+}
+
+/// Reads a MaxMind or DB-IP country/city `.mmdb` database. The file is reloaded on the
+/// next lookup after its modification time changes, so it can be replaced in place.
+pub struct MaxMindResolver {
+    path: PathBuf,
+    reader: RwLock<Option<Arc<maxminddb::Reader<Vec<u8>>>>>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl MaxMindResolver {
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            reader: RwLock::new(None),
+            modified: Mutex::new(None),
+        }
+    }
+
+    async fn reload(&self) -> Result<Arc<maxminddb::Reader<Vec<u8>>>, anyhow::Error> {
+        let mut modified = self.modified.lock().await;
+        let current = tokio::fs::metadata(&self.path).await?.modified()?;
+
+        if *modified != Some(current) {
+            let reader = maxminddb::Reader::from_source(tokio::fs::read(&self.path).await?)?;
+
+            tracing::info!(
+                "loaded geoip database {} ({}, built {})",
+                self.path.display(),
+                reader.metadata.database_type,
+                reader.metadata.build_epoch
+            );
+
+            *self.reader.write().unwrap() = Some(Arc::new(reader));
+            *modified = Some(current);
+        }
+
+        self.reader
+            .read()
+            .unwrap()
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("geoip database is not loaded"))
+    }
+}
+
+impl GeoIpResolver for MaxMindResolver {
+    fn resolve<'a>(
+        &'a self,
+        ips: &'a [IpAddr],
+    ) -> BoxFuture<'a, Result<HashMap<IpAddr, Location>, anyhow::Error>> {
+        Box::pin(async move {
+            let reader = match self.reload().await {
+                Ok(reader) => reader,
+                Err(err) => match self.reader.read().unwrap().clone() {
+                    Some(reader) => {
+                        tracing::warn!("failed to reload geoip database: {:?}", err);
+                        reader
+                    }
+                    None => return Err(err),
+                },
+            };
+
+            let mut result = HashMap::with_capacity(ips.len());
+
+            for ip in ips {
+                let Ok(country) = reader.lookup::<maxminddb::geoip2::Country>(*ip) else {
+                    continue;
+                };
+
+                let continent = country.continent.and_then(|continent| continent.code);
+                let country = country
+                    .country
+                    .or(country.registered_country)
+                    .and_then(|country| country.iso_code);
+
+                if let (Some(continent), Some(country)) = (continent, country) {
+                    result.insert(*ip, [continent.into(), country.into()]);
+                }
+            }
+
+            Ok(result)
+        })
+    }
+}
+
+/// How long ip-api.com results are kept, the request logger asks for every address
+/// again on each pass over the queues.
+const IP_API_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// Batch lookups against ip-api.com, which only accepts 100 addresses per request and
+/// sends them to a third party over plain http. Once its rate limit is used up, the
+/// remaining addresses are left unresolved until the limit resets.
+pub struct IpApiResolver {
+    client: reqwest::Client,
+    /// `None` for addresses ip-api.com has no location for.
+    cache: moka::sync::Cache<IpAddr, Option<Location>>,
+    blocked_until: std::sync::Mutex<Option<Instant>>,
+}
+
+impl IpApiResolver {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .user_agent("MCJars API https://mcjars.app")
+                .build()
+                .unwrap(),
+            cache: moka::sync::Cache::builder()
+                .max_capacity(100_000)
+                .time_to_live(IP_API_CACHE_TTL)
+                .build(),
+            blocked_until: std::sync::Mutex::new(None),
+        }
+    }
+}
+
+impl Default for IpApiResolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GeoIpResolver for IpApiResolver {
+    fn resolve<'a>(
+        &'a self,
+        ips: &'a [IpAddr],
+    ) -> BoxFuture<'a, Result<HashMap<IpAddr, Location>, anyhow::Error>> {
+        Box::pin(async move {
+            #[derive(Deserialize)]
+            #[serde(rename_all = "camelCase")]
+            struct IpApiResponse {
+                continent_code: Option<compact_str::CompactString>,
+                country_code: Option<compact_str::CompactString>,
+                query: IpAddr,
+            }
+
+            let mut result = HashMap::with_capacity(ips.len());
+            let mut missing = Vec::new();
+
+            for ip in ips.iter().collect::<HashSet<_>>() {
+                match self.cache.get(ip) {
+                    Some(Some(location)) => {
+                        result.insert(*ip, location);
+                    }
+                    Some(None) => {}
+                    None => missing.push(*ip),
+                }
+            }
+
+            for chunk in missing.chunks(100) {
+                if self
+                    .blocked_until
+                    .lock()
+                    .unwrap()
+                    .is_some_and(|until| until > Instant::now())
+                {
+                    tracing::debug!("ip-api.com rate limit reached, ips left unresolved");
+                    break;
+                }
+
+                let response = self
+                    .client
+                    .post("http://ip-api.com/batch")
+                    .header("Content-Type", "application/json")
+                    .json(
+                        &chunk
+                            .iter()
+                            .map(|ip| {
+                                serde_json::json!({
+                                    "query": ip,
+                                    "fields": "continentCode,countryCode,query"
+                                })
+                            })
+                            .collect::<Vec<_>>(),
+                    )
+                    .send()
+                    .await?;
+
+                // requests left in the current window and the seconds until it resets
+                let header = |name: &str| {
+                    response
+                        .headers()
+                        .get(name)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| value.parse::<u64>().ok())
+                };
+                let limited = response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS;
+                if limited || header("X-Rl") == Some(0) {
+                    *self.blocked_until.lock().unwrap() = Some(
+                        Instant::now() + Duration::from_secs(header("X-Ttl").unwrap_or(60).max(1)),
+                    );
+                }
+                if limited {
+                    tracing::warn!("ip-api.com rate limit reached, ips left unresolved");
+                    break;
+                }
+
+                let data = response
+                    .error_for_status()?
+                    .json::<Vec<IpApiResponse>>()
+                    .await?;
+
+                for entry in data {
+                    if let (Some(continent), Some(country)) =
+                        (entry.continent_code, entry.country_code)
+                    {
+                        result.insert(entry.query, [continent, country]);
+                    }
+                }
+
+                for ip in chunk {
+                    self.cache.insert(*ip, result.get(ip).cloned());
+                }
+            }
+
+            Ok(result)
+        })
+    }
+}
+
+/// Asks `fallback` for everything `primary` could not resolve.
+pub struct FallbackResolver {
+    primary: Box<dyn GeoIpResolver>,
+    fallback: Box<dyn GeoIpResolver>,
+}
+
+impl GeoIpResolver for FallbackResolver {
+    fn resolve<'a>(
+        &'a self,
+        ips: &'a [IpAddr],
+    ) -> BoxFuture<'a, Result<HashMap<IpAddr, Location>, anyhow::Error>> {
+        Box::pin(async move {
+            let mut result = match self.primary.resolve(ips).await {
+                Ok(result) => result,
+                Err(err) => {
+                    tracing::warn!("primary geoip lookup failed: {:?}", err);
+                    HashMap::new()
+                }
+            };
+
+            let missing = ips
+                .iter()
+                .filter(|ip| !result.contains_key(ip))
+                .copied()
+                .collect::<Vec<_>>();
+
+            if !missing.is_empty() {
+                result.extend(self.fallback.resolve(&missing).await?);
+            }
+
+            Ok(result)
+        })
+    }
+}
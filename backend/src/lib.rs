@@ -4,13 +4,17 @@ pub mod database;
 pub mod deserialize;
 pub mod env;
 pub mod files;
+pub mod geoip;
 pub mod models;
 pub mod payload;
 pub mod prelude;
+pub mod proxy_protocol;
 pub mod requests;
 pub mod response;
 pub mod routes;
 pub mod s3;
+pub mod storage;
+pub mod user_agent;
 pub mod utils;
 
 pub use payload::Payload;
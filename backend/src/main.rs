@@ -6,7 +6,7 @@ use api::{
 use axum::{
     ServiceExt,
     body::Body,
-    extract::{Path, Request},
+    extract::{ConnectInfo, Path, Request},
     http::{Method, StatusCode},
     middleware::Next,
     response::Response,
@@ -16,7 +16,7 @@ use compact_str::ToCompactString;
 use include_dir::{Dir, include_dir};
 use sentry_tower::{NewSentryLayer, SentryHttpLayer};
 use sha2::Digest;
-use std::{borrow::Cow, sync::Arc, time::Instant};
+use std::{borrow::Cow, net::SocketAddr, sync::Arc, time::Instant};
 use tower::Layer;
 use tower_cookies::CookieManagerLayer;
 use tower_http::{
@@ -95,7 +95,28 @@ fn handle_panic(_err: Box<dyn std::any::Any + Send + 'static>) -> Response<Body>
         .unwrap()
 }
 
-async fn handle_request(req: Request<Body>, next: Next) -> Result<Response, StatusCode> {
+async fn handle_request(
+    state: GetState,
+    mut req: Request<Body>,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| peer.ip());
+
+    // everything past this point reads the client address from x-real-ip
+    match peer {
+        Some(peer) => {
+            let ip = api::utils::client_ip(peer, req.headers(), &state.env.trusted_proxies);
+            req.headers_mut()
+                .insert("x-real-ip", ip.to_string().parse().unwrap());
+        }
+        None => {
+            req.headers_mut().remove("x-real-ip");
+        }
+    }
+
     let ip = api::utils::extract_ip(req.headers())
         .map(|ip| ip.to_string())
         .unwrap_or_else(|| "unknown".to_string());
@@ -226,15 +247,53 @@ async fn main() {
         clickhouse: clickhouse.clone(),
         cache: cache.clone(),
         requests: api::requests::RequestLogger::new(
+            &env,
             database.clone(),
             clickhouse.clone(),
             cache.clone(),
+            api::geoip::from_env(&env),
         ),
-        files: api::files::FileCache::new(database.clone(), env.clone()).await,
+        files: api::files::FileCache::new(
+            database.clone(),
+            env.clone(),
+            api::storage::from_env(&env, s3.clone()),
+        )
+        .await,
         env,
         s3,
     });
 
+    {
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            state.requests.collect().await;
+        });
+    }
+
+    {
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            state.cache.listen().await;
+        });
+    }
+
+    {
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            loop {
+                if let Err(err) = state.requests.insert().await {
+                    tracing::error!("failed to insert requests: {:?}", err);
+                    sentry_anyhow::capture_anyhow(&err);
+                }
+
+                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            }
+        });
+    }
+
     {
         let state = state.clone();
 
@@ -265,6 +324,148 @@ async fn main() {
         });
     }
 
+    if state.env.analytics_retention_days > 0
+        || state.env.session_retention_days > 0
+        || state.env.files_changes_retention_days > 0
+    {
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            loop {
+                if state.env.analytics_retention_days > 0 {
+                    for table in ["requests", "file_requests"] {
+                        match state
+                            .clickhouse
+                            .drop_expired_partitions(table, state.env.analytics_retention_days)
+                            .await
+                        {
+                            Ok(partitions) if !partitions.is_empty() => {
+                                tracing::info!(
+                                    "dropped expired {} partitions {}",
+                                    table,
+                                    partitions.join(", ")
+                                );
+                            }
+                            Ok(_) => {}
+                            Err(err) => {
+                                tracing::error!(
+                                    "failed to drop expired {} partitions: {:?}",
+                                    table,
+                                    err
+                                );
+                                sentry_anyhow::capture_anyhow(&err);
+                            }
+                        }
+                    }
+                }
+
+                if state.env.session_retention_days > 0 {
+                    match api::models::user::UserSession::delete_unused(
+                        &state.database,
+                        state.env.session_retention_days,
+                    )
+                    .await
+                    {
+                        Ok(0) => {}
+                        Ok(deleted) => tracing::info!("deleted {} unused sessions", deleted),
+                        Err(err) => {
+                            tracing::error!("failed to delete unused sessions: {:?}", err);
+                            sentry_anyhow::capture_anyhow(&err);
+                        }
+                    }
+                }
+
+                if state.env.files_changes_retention_days > 0 {
+                    match api::models::file::FileChange::delete_superseded(
+                        &state.database,
+                        state.env.files_changes_retention_days,
+                    )
+                    .await
+                    {
+                        Ok(0) => {}
+                        Ok(deleted) => {
+                            tracing::info!("deleted {} superseded file changes", deleted)
+                        }
+                        Err(err) => {
+                            tracing::error!("failed to delete superseded file changes: {:?}", err);
+                            sentry_anyhow::capture_anyhow(&err);
+                        }
+                    }
+                }
+
+                tokio::time::sleep(std::time::Duration::from_secs(3600)).await;
+            }
+        });
+    }
+
+    if state.env.files_cache_warm_count > 0 {
+        let state = state.clone();
+
+        tokio::spawn(async move {
+            loop {
+                match api::models::file::File::most_requested(
+                    &state.database,
+                    state.env.files_cache_warm_count,
+                )
+                .await
+                {
+                    Ok(files) => {
+                        state
+                            .files
+                            .warm(files, state.env.files_cache_warm_budget)
+                            .await;
+                    }
+                    Err(err) => {
+                        tracing::error!("failed to load popular files: {:?}", err);
+                        sentry_anyhow::capture_anyhow(&err);
+                    }
+                }
+
+                tokio::time::sleep(std::time::Duration::from_secs(
+                    state.env.files_cache_warm_interval,
+                ))
+                .await;
+            }
+        });
+    }
+
+    {
+        let state = state.clone();
+        let client = reqwest::Client::builder()
+            .user_agent("MCJars API https://mcjars.app")
+            .build()
+            .unwrap();
+
+        tokio::spawn(async move {
+            loop {
+                // one instance probes the mirrors each minute
+                let result = match state
+                    .cache
+                    .claim("mirrors::health", std::time::Duration::from_secs(55))
+                    .await
+                {
+                    Ok(true) => {
+                        api::models::mirror::Mirror::check_health(
+                            &state.database,
+                            &state.cache,
+                            &client,
+                        )
+                        .await
+                    }
+                    Ok(false) => Ok(()),
+                    Err(err) => Err(err),
+                };
+
+                if let Err(err) = result {
+                    tracing::error!("failed to check mirror health: {:?}", err);
+                    sentry_anyhow::capture_anyhow(&err);
+                }
+
+                tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+            }
+        });
+    }
+
     let app =
         OpenApiRouter::new()
             .merge(api::routes::router(&state))
@@ -538,7 +739,10 @@ async fn main() {
             })
             .layer(CatchPanicLayer::custom(handle_panic))
             .layer(CorsLayer::permissive().allow_methods([Method::GET, Method::POST]))
-            .layer(axum::middleware::from_fn(handle_request))
+            .layer(axum::middleware::from_fn_with_state(
+                state.clone(),
+                handle_request,
+            ))
             .layer(CookieManagerLayer::new())
             .route_layer(axum::middleware::from_fn(handle_postprocessing))
             .route_layer(SentryHttpLayer::new().enable_transaction())
@@ -593,12 +797,19 @@ async fn main() {
     let openapi = Arc::new(openapi);
     let router = router.route("/openapi.json", get(|| async move { axum::Json(openapi) }));
 
-    axum::serve(
-        listener,
-        ServiceExt::<Request>::into_make_service(
-            NormalizePathLayer::trim_trailing_slash().layer(router),
-        ),
-    )
-    .await
-    .unwrap();
+    let service = ServiceExt::<Request>::into_make_service_with_connect_info::<SocketAddr>(
+        NormalizePathLayer::trim_trailing_slash().layer(router),
+    );
+
+    if state.env.proxy_protocol {
+        let listener = api::proxy_protocol::ProxyProtocolListener::new(
+            listener,
+            state.env.trusted_proxies.clone(),
+        )
+        .unwrap();
+
+        axum::serve(listener, service).await.unwrap();
+    } else {
+        axum::serve(listener, service).await.unwrap();
+    }
 }
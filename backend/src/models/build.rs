@@ -2,7 +2,7 @@ use super::{BaseModel, r#type::ServerType, version::VersionType};
 use crate::prelude::IteratorExtension;
 use serde::{Deserialize, Serialize};
 use sqlx::{Row, postgres::PgRow, types::chrono::NaiveDateTime};
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, sync::Arc};
 use utoipa::ToSchema;
 
 #[derive(ToSchema, Serialize, Deserialize, Clone)]
@@ -188,8 +188,8 @@ impl Build {
     }
 
     pub async fn by_identifier(
-        database: &crate::database::Database,
-        cache: &crate::cache::Cache,
+        database: &Arc<crate::database::Database>,
+        cache: &Arc<crate::cache::Cache>,
         identifier: &str,
     ) -> Result<Option<(Self, Self, super::version::MinifiedVersion)>, anyhow::Error> {
         let hash: Option<&str> = match identifier.len() {
@@ -218,9 +218,11 @@ impl Build {
             return Ok(None);
         }
 
-        let identifier = &identifier.to_ascii_lowercase();
+        let identifier = identifier.to_ascii_lowercase();
+        let options = crate::cache::CacheOptions::new(3600).stale_while_revalidate(600);
+        let database = Arc::clone(database);
 
-        cache.cached(&format!("build::identifier::{identifier}"), 3600, || async {
+        cache.cached_revalidate(&format!("build::identifier::{identifier}"), options, move || async move {
             let query = sqlx::query(sqlx::AssertSqlSafe(format!(
                 r#"
                 WITH spec_build AS (
@@ -290,7 +292,7 @@ impl Build {
                 Self::columns_sql(None, Some("b")),
                 Self::columns_sql(None, Some("b"))
             )))
-            .bind(identifier)
+            .bind(&identifier)
             .fetch_all(database.read())
             .await?;
 
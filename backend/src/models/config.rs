@@ -2,7 +2,10 @@ use super::r#type::ServerType;
 use indexmap::IndexMap;
 use serde::{Deserialize, Serialize};
 use sqlx::{Row, prelude::Type};
-use std::{fmt::Display, sync::LazyLock};
+use std::{
+    fmt::Display,
+    sync::{Arc, LazyLock},
+};
 use utoipa::ToSchema;
 
 #[derive(ToSchema, Serialize, Deserialize, Type, Clone, Copy)]
@@ -49,11 +52,14 @@ pub struct ConfigStats {
 
 impl ConfigStats {
     pub async fn all(
-        database: &crate::database::Database,
-        cache: &crate::cache::Cache,
+        database: &Arc<crate::database::Database>,
+        cache: &Arc<crate::cache::Cache>,
     ) -> Result<Vec<ConfigStats>, anyhow::Error> {
+        let options = crate::cache::CacheOptions::new(3600).stale_while_revalidate(3600);
+        let database = Arc::clone(database);
+
         cache
-            .cached("config_stats_v3", 3600, || async {
+            .cached_revalidate("config_stats_v3", options, move || async move {
                 let rows = sqlx::query(
                     r#"
                     WITH build_data AS (
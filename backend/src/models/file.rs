@@ -3,7 +3,10 @@ use crate::prelude::IteratorExtension;
 use compact_str::ToCompactString;
 use serde::{Deserialize, Serialize};
 use sqlx::{Row, postgres::PgRow, types::chrono::NaiveDateTime};
-use std::{collections::BTreeMap, path::Path};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 use utoipa::ToSchema;
 
 #[derive(ToSchema, Serialize, Deserialize, Clone)]
@@ -196,4 +199,295 @@ impl File {
             })
             .await
     }
+
+    /// Every file below `root` (including `root` itself) with its full path, uncached.
+    pub async fn all_under(
+        database: &crate::database::Database,
+        root: &Path,
+        limit: i64,
+    ) -> Result<Vec<(PathBuf, Self)>, anyhow::Error> {
+        let root = root
+            .components()
+            .filter(|c| c.as_os_str().to_str().is_some_and(|s| !s.is_empty()))
+            .map(|c| c.as_os_str().to_string_lossy().to_compact_string())
+            .collect::<Vec<_>>();
+
+        let rows = sqlx::query(sqlx::AssertSqlSafe(format!(
+            r#"
+            SELECT {}, files.path
+            FROM files
+            WHERE
+                array_length(files.path, 1) >= $2
+                AND files.path[1:$2] = $1::varchar[]
+            ORDER BY files.path
+            LIMIT $3
+            "#,
+            Self::columns_sql(None, None)
+        )))
+        .bind(&root)
+        .bind(root.len() as i32)
+        .bind(limit)
+        .fetch_all(database.read())
+        .await?;
+
+        rows.into_iter().map(Self::map_with_path).try_collect_vec()
+    }
+
+    /// The `limit` most downloaded files, ranked by successful downloads over the last 7
+    /// days and then by all-time downloads, uncached.
+    pub async fn most_requested(
+        database: &crate::database::Database,
+        limit: i64,
+    ) -> Result<Vec<(PathBuf, Self)>, anyhow::Error> {
+        sqlx::query(sqlx::AssertSqlSafe(format!(
+            r#"
+            SELECT {}, files.path
+            FROM (
+                SELECT
+                    stats.path,
+                    SUM(stats.recent_requests) AS recent_requests,
+                    SUM(stats.total_requests) AS total_requests
+                FROM (
+                    SELECT
+                        ch_file_stats_daily.path,
+                        ch_file_stats_daily.total_requests AS recent_requests,
+                        0 AS total_requests
+                    FROM ch_file_stats_daily
+                    WHERE
+                        ch_file_stats_daily.kind = 'file'
+                        AND ch_file_stats_daily.date_only >= CURRENT_DATE - 7
+                    UNION ALL
+                    SELECT ch_file_stats.path, 0, ch_file_stats.total_requests
+                    FROM ch_file_stats
+                    WHERE ch_file_stats.kind = 'file'
+                ) stats
+                GROUP BY stats.path
+            ) popular
+            INNER JOIN files ON files.path = string_to_array(popular.path, '/')::varchar[]
+            ORDER BY popular.recent_requests DESC, popular.total_requests DESC
+            LIMIT $1
+            "#,
+            Self::columns_sql(None, None)
+        )))
+        .bind(limit)
+        .fetch_all(database.read())
+        .await?
+        .into_iter()
+        .map(Self::map_with_path)
+        .try_collect_vec()
+    }
+
+    /// Every file whose md5, sha1, sha224, sha256, sha384 or sha512 matches `hash`, the
+    /// algorithm is picked from the hash length. Invalid hashes match nothing.
+    pub async fn by_hash(
+        database: &crate::database::Database,
+        cache: &crate::cache::Cache,
+        hash: &str,
+    ) -> Result<Vec<(PathBuf, Self)>, anyhow::Error> {
+        let column = match hash.len() {
+            32 => "md5",
+            40 => "sha1",
+            56 => "sha224",
+            64 => "sha256",
+            96 => "sha384",
+            128 => "sha512",
+            _ => return Ok(Vec::new()),
+        };
+
+        if !hash.chars().all(|char| char.is_ascii_hexdigit()) {
+            return Ok(Vec::new());
+        }
+
+        let hash = &hash.to_ascii_lowercase();
+        // file rows are written outside the api, whoever writes them clears `file:{hash}`
+        // through the admin cache route and the short ttl covers a missed invalidation
+        let options = crate::cache::CacheOptions::new(300).tag(format!("file:{hash}"));
+
+        cache
+            .cached_with(&format!("files::hash::{hash}"), options, || async {
+                sqlx::query(sqlx::AssertSqlSafe(format!(
+                    r#"
+                    SELECT {}, files.path
+                    FROM files
+                    WHERE files.{column} = decode($1, 'hex')
+                    ORDER BY files.path
+                    LIMIT 100
+                    "#,
+                    Self::columns_sql(None, None)
+                )))
+                .bind(hash)
+                .fetch_all(database.read())
+                .await?
+                .into_iter()
+                .map(Self::map_with_path)
+                .try_collect_vec()
+            })
+            .await
+    }
+
+    fn map_with_path(row: PgRow) -> Result<(PathBuf, Self), anyhow::Error> {
+        let path = row
+            .try_get::<Vec<compact_str::CompactString>, _>("path")?
+            .into_iter()
+            .map(|component| component.to_string())
+            .collect::<PathBuf>();
+
+        Ok((path, Self::map(None, &row)?))
+    }
+
+    pub fn into_api_v3(self, path: &Path, app_url: &str) -> ApiFileV3 {
+        ApiFileV3 {
+            url: format!("{app_url}/files/{}", path.display()),
+            path: path.display().to_string(),
+            name: self.name,
+            size: self.size,
+            md5: hex::encode(self.md5),
+            sha1: hex::encode(self.sha1),
+            sha224: hex::encode(self.sha224),
+            sha256: hex::encode(self.sha256),
+            sha384: hex::encode(self.sha384),
+            sha512: hex::encode(self.sha512),
+            last_access: self.last_access.map(|dt| dt.and_utc()),
+        }
+    }
+}
+
+#[derive(ToSchema, Serialize, Deserialize)]
+#[schema(title = "FileV3")]
+pub struct ApiFileV3 {
+    pub path: String,
+    pub name: compact_str::CompactString,
+    pub url: String,
+
+    pub size: i64,
+
+    pub md5: String,
+    pub sha1: String,
+    pub sha224: String,
+    pub sha256: String,
+    pub sha384: String,
+    pub sha512: String,
+
+    pub last_access: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// A row of the `file_changes` feed, written by a trigger on `files`. Deletions carry
+/// no size or hashes. The trigger serializes writers until they commit, so ids become
+/// visible in order and a cursor never skips a change.
+pub struct FileChange {
+    pub id: i64,
+    pub path: PathBuf,
+    pub deleted: bool,
+
+    pub size: Option<i64>,
+    pub md5: Option<Vec<u8>>,
+    pub sha1: Option<Vec<u8>>,
+    pub sha224: Option<Vec<u8>>,
+    pub sha256: Option<Vec<u8>>,
+    pub sha384: Option<Vec<u8>>,
+    pub sha512: Option<Vec<u8>>,
+}
+
+impl FileChange {
+    /// Deletes changes older than `days` days that a later change of the same path
+    /// supersedes. The latest change of every path is kept, so a mirror following the
+    /// feed from any cursor still ends up with the current state.
+    pub async fn delete_superseded(
+        database: &crate::database::Database,
+        days: u32,
+    ) -> Result<u64, anyhow::Error> {
+        Ok(sqlx::query(
+            r#"
+            DELETE FROM file_changes
+            WHERE file_changes.created < NOW() - make_interval(days => $1)
+                AND EXISTS (
+                    SELECT 1 FROM file_changes newer
+                    WHERE newer.path = file_changes.path AND newer.id > file_changes.id
+                )
+            "#,
+        )
+        .bind(days as i32)
+        .execute(database.write())
+        .await?
+        .rows_affected())
+    }
+
+    /// Changes with an id greater than `cursor`, oldest first, uncached.
+    pub async fn since(
+        database: &crate::database::Database,
+        cursor: i64,
+        limit: i64,
+    ) -> Result<Vec<Self>, anyhow::Error> {
+        sqlx::query(
+            r#"
+            SELECT
+                file_changes.id, file_changes.path, file_changes.deleted,
+                file_changes.size::int8 AS size, file_changes.md5, file_changes.sha1,
+                file_changes.sha224, file_changes.sha256, file_changes.sha384,
+                file_changes.sha512
+            FROM file_changes
+            WHERE file_changes.id > $1
+            ORDER BY file_changes.id
+            LIMIT $2
+            "#,
+        )
+        .bind(cursor)
+        .bind(limit)
+        .fetch_all(database.read())
+        .await?
+        .into_iter()
+        .map(|row| {
+            Ok::<_, anyhow::Error>(Self {
+                id: row.try_get("id")?,
+                path: row
+                    .try_get::<Vec<compact_str::CompactString>, _>("path")?
+                    .into_iter()
+                    .map(|component| component.to_string())
+                    .collect(),
+                deleted: row.try_get("deleted")?,
+                size: row.try_get("size")?,
+                md5: row.try_get("md5")?,
+                sha1: row.try_get("sha1")?,
+                sha224: row.try_get("sha224")?,
+                sha256: row.try_get("sha256")?,
+                sha384: row.try_get("sha384")?,
+                sha512: row.try_get("sha512")?,
+            })
+        })
+        .try_collect_vec()
+    }
+
+    pub fn into_api_v3(self, app_url: &str) -> ApiFileChangeV3 {
+        ApiFileChangeV3 {
+            id: self.id,
+            url: (!self.deleted).then(|| format!("{app_url}/files/{}", self.path.display())),
+            path: self.path.display().to_string(),
+            deleted: self.deleted,
+            size: self.size,
+            md5: self.md5.map(hex::encode),
+            sha1: self.sha1.map(hex::encode),
+            sha224: self.sha224.map(hex::encode),
+            sha256: self.sha256.map(hex::encode),
+            sha384: self.sha384.map(hex::encode),
+            sha512: self.sha512.map(hex::encode),
+        }
+    }
+}
+
+#[derive(ToSchema, Serialize, Deserialize)]
+#[schema(title = "FileChangeV3")]
+pub struct ApiFileChangeV3 {
+    pub id: i64,
+    pub path: String,
+    pub url: Option<String>,
+    pub deleted: bool,
+
+    pub size: Option<i64>,
+
+    pub md5: Option<String>,
+    pub sha1: Option<String>,
+    pub sha224: Option<String>,
+    pub sha256: Option<String>,
+    pub sha384: Option<String>,
+    pub sha512: Option<String>,
 }
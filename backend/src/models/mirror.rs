@@ -0,0 +1,285 @@
+use super::BaseModel;
+use crate::prelude::IteratorExtension;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, postgres::PgRow, types::chrono::NaiveDateTime};
+use std::{collections::BTreeMap, path::Path};
+use utoipa::ToSchema;
+
+/// Continent codes as resolved by the request logger.
+pub const CONTINENTS: [&str; 7] = ["AF", "AN", "AS", "EU", "NA", "OC", "SA"];
+
+#[derive(ToSchema, Serialize, Deserialize, Clone)]
+pub struct Mirror {
+    pub id: i32,
+
+    pub name: compact_str::CompactString,
+    pub url: compact_str::CompactString,
+    pub continent: compact_str::CompactString,
+    pub country: Option<compact_str::CompactString>,
+
+    pub enabled: bool,
+    pub healthy: bool,
+    pub last_check: Option<NaiveDateTime>,
+
+    pub created: NaiveDateTime,
+}
+
+impl BaseModel for Mirror {
+    fn columns(
+        prefix: Option<&str>,
+        table: Option<&str>,
+    ) -> BTreeMap<compact_str::CompactString, compact_str::CompactString> {
+        let table = table.unwrap_or("mirrors");
+
+        BTreeMap::from([
+            (
+                compact_str::format_compact!("{table}.id"),
+                compact_str::format_compact!("{}id", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.name"),
+                compact_str::format_compact!("{}name", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.url"),
+                compact_str::format_compact!("{}url", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.continent"),
+                compact_str::format_compact!("{}continent", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.country"),
+                compact_str::format_compact!("{}country", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.enabled"),
+                compact_str::format_compact!("{}enabled", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.healthy"),
+                compact_str::format_compact!("{}healthy", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.last_check"),
+                compact_str::format_compact!("{}last_check", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.created"),
+                compact_str::format_compact!("{}created", prefix.unwrap_or_default()),
+            ),
+        ])
+    }
+
+    fn map(prefix: Option<&str>, row: &PgRow) -> Result<Self, anyhow::Error> {
+        let prefix = prefix.unwrap_or_default();
+
+        Ok(Self {
+            id: row.try_get(compact_str::format_compact!("{prefix}id").as_str())?,
+            name: row.try_get(compact_str::format_compact!("{prefix}name").as_str())?,
+            url: row.try_get(compact_str::format_compact!("{prefix}url").as_str())?,
+            continent: row.try_get(compact_str::format_compact!("{prefix}continent").as_str())?,
+            country: row.try_get(compact_str::format_compact!("{prefix}country").as_str())?,
+            enabled: row.try_get(compact_str::format_compact!("{prefix}enabled").as_str())?,
+            healthy: row.try_get(compact_str::format_compact!("{prefix}healthy").as_str())?,
+            last_check: row.try_get(compact_str::format_compact!("{prefix}last_check").as_str())?,
+            created: row.try_get(compact_str::format_compact!("{prefix}created").as_str())?,
+        })
+    }
+}
+
+impl Mirror {
+    #[allow(clippy::new_ret_no_self)]
+    pub async fn new(
+        database: &crate::database::Database,
+        cache: &crate::cache::Cache,
+        name: &str,
+        url: &str,
+        continent: &str,
+        country: Option<&str>,
+    ) -> Result<Option<Self>, anyhow::Error> {
+        let row = sqlx::query(sqlx::AssertSqlSafe(format!(
+            r#"
+            INSERT INTO mirrors (name, url, continent, country)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (url) DO NOTHING
+            RETURNING {}
+            "#,
+            Self::columns_sql(None, None)
+        )))
+        .bind(name)
+        .bind(url.trim_end_matches('/'))
+        .bind(continent)
+        .bind(country)
+        .fetch_optional(database.write())
+        .await?;
+
+        cache.invalidate("mirrors::available").await?;
+
+        row.map(|row| Self::map(None, &row)).transpose()
+    }
+
+    pub async fn all(database: &crate::database::Database) -> Result<Vec<Self>, anyhow::Error> {
+        sqlx::query(sqlx::AssertSqlSafe(format!(
+            "SELECT {} FROM mirrors ORDER BY mirrors.id",
+            Self::columns_sql(None, None)
+        )))
+        .fetch_all(database.read())
+        .await?
+        .into_iter()
+        .map(|row| Self::map(None, &row))
+        .try_collect_vec()
+    }
+
+    /// Enabled mirrors that passed their last health check.
+    pub async fn available(
+        database: &crate::database::Database,
+        cache: &crate::cache::Cache,
+    ) -> Result<Vec<Self>, anyhow::Error> {
+        cache
+            .cached("mirrors::available", 60, || async {
+                sqlx::query(sqlx::AssertSqlSafe(format!(
+                    r#"
+                    SELECT {} FROM mirrors
+                    WHERE mirrors.enabled AND mirrors.healthy
+                    ORDER BY mirrors.id
+                    "#,
+                    Self::columns_sql(None, None)
+                )))
+                .fetch_all(database.read())
+                .await?
+                .into_iter()
+                .map(|row| Self::map(None, &row))
+                .try_collect_vec()
+            })
+            .await
+    }
+
+    pub async fn by_id(
+        database: &crate::database::Database,
+        id: i32,
+    ) -> Result<Option<Self>, anyhow::Error> {
+        if id < 1 {
+            return Ok(None);
+        }
+
+        let data = sqlx::query(sqlx::AssertSqlSafe(format!(
+            "SELECT {} FROM mirrors WHERE mirrors.id = $1",
+            Self::columns_sql(None, None)
+        )))
+        .bind(id)
+        .fetch_optional(database.read())
+        .await?;
+
+        data.map(|row| Self::map(None, &row)).transpose()
+    }
+
+    pub async fn save(
+        &self,
+        database: &crate::database::Database,
+        cache: &crate::cache::Cache,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query(
+            r#"
+            UPDATE mirrors
+            SET name = $2, url = $3, continent = $4, country = $5, enabled = $6
+            WHERE mirrors.id = $1
+            "#,
+        )
+        .bind(self.id)
+        .bind(&self.name)
+        .bind(&self.url)
+        .bind(&self.continent)
+        .bind(&self.country)
+        .bind(self.enabled)
+        .execute(database.write())
+        .await?;
+
+        cache.invalidate("mirrors::available").await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(
+        &self,
+        database: &crate::database::Database,
+        cache: &crate::cache::Cache,
+    ) -> Result<bool, anyhow::Error> {
+        let deleted = sqlx::query(
+            r#"
+            DELETE FROM mirrors
+            WHERE mirrors.id = $1
+            "#,
+        )
+        .bind(self.id)
+        .execute(database.write())
+        .await?
+        .rows_affected()
+            == 1;
+
+        cache.invalidate("mirrors::available").await?;
+
+        Ok(deleted)
+    }
+
+    /// Probes every enabled mirror with a `HEAD` of its base URL, anything that answers
+    /// without a server error within 10 seconds counts as healthy.
+    pub async fn check_health(
+        database: &crate::database::Database,
+        cache: &crate::cache::Cache,
+        client: &reqwest::Client,
+    ) -> Result<(), anyhow::Error> {
+        let mirrors = Self::all(database).await?;
+        let mut changed = false;
+
+        for mirror in mirrors.into_iter().filter(|mirror| mirror.enabled) {
+            let healthy = client
+                .head(format!("{}/", mirror.url))
+                .timeout(std::time::Duration::from_secs(10))
+                .send()
+                .await
+                .is_ok_and(|response| !response.status().is_server_error());
+
+            if healthy != mirror.healthy {
+                tracing::warn!(
+                    "mirror {} ({}) is now {}",
+                    mirror.name,
+                    mirror.url,
+                    if healthy { "healthy" } else { "unhealthy" }
+                );
+                changed = true;
+            }
+
+            sqlx::query(
+                r#"
+                UPDATE mirrors
+                SET healthy = $2, last_check = NOW()
+                WHERE mirrors.id = $1
+                "#,
+            )
+            .bind(mirror.id)
+            .bind(healthy)
+            .execute(database.write())
+            .await?;
+        }
+
+        if changed {
+            cache.invalidate("mirrors::available").await?;
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn file_url(&self, path: &Path) -> String {
+        format!("{}/{}", self.url, path.display())
+    }
+
+    /// Healthy mirrors ordered by proximity: same continent first, then by id.
+    pub fn nearest<'a>(mirrors: &'a [Self], continent: Option<&str>) -> Vec<&'a Self> {
+        let mut mirrors = mirrors.iter().collect::<Vec<_>>();
+        mirrors.sort_by_key(|mirror| (Some(mirror.continent.as_str()) != continent, mirror.id));
+
+        mirrors
+    }
+}
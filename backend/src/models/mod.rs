@@ -8,7 +8,9 @@ use utoipa::ToSchema;
 pub mod build;
 pub mod config;
 pub mod file;
+pub mod mirror;
 pub mod organization;
+pub mod ratelimit;
 pub mod r#type;
 pub mod user;
 pub mod version;
@@ -184,8 +184,10 @@ impl Organization {
             return Ok(None);
         }
 
+        let options = crate::cache::CacheOptions::new(300).tag(format!("org:{id}"));
+
         cache
-            .cached(&format!("organization::{id}"), 300, || async {
+            .cached_with(&format!("organization::{id}"), options, || async {
                 let data = sqlx::query(sqlx::AssertSqlSafe(format!(
                     r#"
                     SELECT {}
@@ -214,12 +216,12 @@ impl Organization {
         };
 
         cache
-            .cached(
+            .cached_with(
                 &format!(
                     "organization::key::{key_id}::{}",
                     crate::utils::cache_key_hash(key)
                 ),
-                300,
+                crate::cache::CacheOptions::new(300).tag(format!("org_key:{key_id}")),
                 || async {
                     let data = sqlx::query(sqlx::AssertSqlSafe(format!(
                         r#"
@@ -252,8 +254,10 @@ impl Organization {
         user_admin: bool,
         organization_id: i32,
     ) -> Result<Option<Self>, anyhow::Error> {
+        let options = crate::cache::CacheOptions::new(60).tag(format!("org:{organization_id}"));
+
         cache
-            .cached(&format!("organization::{organization_id}::user::{user_id}"), 60, || async {
+            .cached_with(&format!("organization::{organization_id}::user::{user_id}"), options, || async {
                 let data = sqlx::query(sqlx::AssertSqlSafe(format!(
                     r#"
                     SELECT {}
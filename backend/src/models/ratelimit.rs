@@ -0,0 +1,446 @@
+use super::BaseModel;
+use crate::prelude::IteratorExtension;
+use serde::{Deserialize, Serialize};
+use sqlx::{Row, postgres::PgRow, types::chrono::NaiveDateTime};
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+/// Route groups as classified by the request logger.
+pub const ROUTE_GROUPS: [&str; 4] = ["regular", "files", "files_download", "files_archive"];
+
+/// Every policy and cost, cached together as both are needed for each request.
+pub async fn rules(
+    database: &crate::database::Database,
+    cache: &crate::cache::Cache,
+) -> Result<(Vec<RateLimitPolicy>, Vec<RateLimitCost>), anyhow::Error> {
+    cache
+        .cached("ratelimit::rules", 60, || async {
+            tokio::try_join!(RateLimitPolicy::all(database), RateLimitCost::all(database))
+        })
+        .await
+}
+
+#[derive(ToSchema, Serialize, Deserialize, Clone)]
+pub struct RateLimitPolicy {
+    pub id: i32,
+
+    pub organization_id: Option<i32>,
+    pub organization_key_id: Option<i32>,
+    /// The public part of the API key, matched against the `Authorization` header.
+    pub key_id: Option<compact_str::CompactString>,
+    pub route_group: Option<compact_str::CompactString>,
+
+    /// Requests that can be made at once with a full bucket.
+    pub burst: i32,
+    /// Requests per `period` seconds the bucket refills at.
+    pub rate: i32,
+    pub period: i32,
+
+    pub created: NaiveDateTime,
+}
+
+impl BaseModel for RateLimitPolicy {
+    fn columns(
+        prefix: Option<&str>,
+        table: Option<&str>,
+    ) -> BTreeMap<compact_str::CompactString, compact_str::CompactString> {
+        let table = table.unwrap_or("ratelimit_policies");
+
+        BTreeMap::from([
+            (
+                compact_str::format_compact!("{table}.id"),
+                compact_str::format_compact!("{}id", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.organization_id"),
+                compact_str::format_compact!("{}organization_id", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.organization_key_id"),
+                compact_str::format_compact!("{}organization_key_id", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("organization_keys.key_id"),
+                compact_str::format_compact!("{}key_id", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.route_group"),
+                compact_str::format_compact!("{}route_group", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.burst"),
+                compact_str::format_compact!("{}burst", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.rate"),
+                compact_str::format_compact!("{}rate", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.period"),
+                compact_str::format_compact!("{}period", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.created"),
+                compact_str::format_compact!("{}created", prefix.unwrap_or_default()),
+            ),
+        ])
+    }
+
+    fn map(prefix: Option<&str>, row: &PgRow) -> Result<Self, anyhow::Error> {
+        let prefix = prefix.unwrap_or_default();
+
+        Ok(Self {
+            id: row.try_get(compact_str::format_compact!("{prefix}id").as_str())?,
+            organization_id: row
+                .try_get(compact_str::format_compact!("{prefix}organization_id").as_str())?,
+            organization_key_id: row
+                .try_get(compact_str::format_compact!("{prefix}organization_key_id").as_str())?,
+            key_id: row.try_get(compact_str::format_compact!("{prefix}key_id").as_str())?,
+            route_group: row
+                .try_get(compact_str::format_compact!("{prefix}route_group").as_str())?,
+            burst: row.try_get(compact_str::format_compact!("{prefix}burst").as_str())?,
+            rate: row.try_get(compact_str::format_compact!("{prefix}rate").as_str())?,
+            period: row.try_get(compact_str::format_compact!("{prefix}period").as_str())?,
+            created: row.try_get(compact_str::format_compact!("{prefix}created").as_str())?,
+        })
+    }
+}
+
+impl RateLimitPolicy {
+    /// Policies for an API key always apply to the organization owning the key,
+    /// `organization_id` is ignored when `organization_key_id` is set. Returns `None`
+    /// when the organization or key does not exist.
+    #[allow(clippy::new_ret_no_self, clippy::too_many_arguments)]
+    pub async fn new(
+        database: &crate::database::Database,
+        cache: &crate::cache::Cache,
+        organization_id: Option<i32>,
+        organization_key_id: Option<i32>,
+        route_group: Option<&str>,
+        burst: i32,
+        rate: i32,
+        period: i32,
+    ) -> Result<Option<Self>, anyhow::Error> {
+        let row = sqlx::query(sqlx::AssertSqlSafe(format!(
+            r#"
+            WITH ratelimit_policies AS (
+                INSERT INTO ratelimit_policies (organization_id, organization_key_id, route_group, burst, rate, period)
+                SELECT COALESCE(organization_keys.organization_id, organizations.id), organization_keys.id, $3, $4, $5, $6
+                FROM (SELECT 1) AS scope
+                LEFT JOIN organizations ON organizations.id = $1
+                LEFT JOIN organization_keys ON organization_keys.id = $2
+                WHERE ($1::int IS NULL OR $2::int IS NOT NULL OR organizations.id IS NOT NULL)
+                    AND ($2::int IS NULL OR organization_keys.id IS NOT NULL)
+                RETURNING *
+            )
+            SELECT {}
+            FROM ratelimit_policies
+            LEFT JOIN organization_keys ON organization_keys.id = ratelimit_policies.organization_key_id
+            "#,
+            Self::columns_sql(None, None)
+        )))
+        .bind(organization_id)
+        .bind(organization_key_id)
+        .bind(route_group)
+        .bind(burst)
+        .bind(rate)
+        .bind(period)
+        .fetch_optional(database.write())
+        .await?;
+
+        cache.invalidate("ratelimit::rules").await?;
+
+        row.map(|row| Self::map(None, &row)).transpose()
+    }
+
+    pub async fn all(database: &crate::database::Database) -> Result<Vec<Self>, anyhow::Error> {
+        sqlx::query(sqlx::AssertSqlSafe(format!(
+            r#"
+            SELECT {}
+            FROM ratelimit_policies
+            LEFT JOIN organization_keys ON organization_keys.id = ratelimit_policies.organization_key_id
+            ORDER BY ratelimit_policies.id
+            "#,
+            Self::columns_sql(None, None)
+        )))
+        .fetch_all(database.read())
+        .await?
+        .into_iter()
+        .map(|row| Self::map(None, &row))
+        .try_collect_vec()
+    }
+
+    pub async fn by_id(
+        database: &crate::database::Database,
+        id: i32,
+    ) -> Result<Option<Self>, anyhow::Error> {
+        if id < 1 {
+            return Ok(None);
+        }
+
+        let data = sqlx::query(sqlx::AssertSqlSafe(format!(
+            r#"
+            SELECT {}
+            FROM ratelimit_policies
+            LEFT JOIN organization_keys ON organization_keys.id = ratelimit_policies.organization_key_id
+            WHERE ratelimit_policies.id = $1
+            "#,
+            Self::columns_sql(None, None)
+        )))
+        .bind(id)
+        .fetch_optional(database.read())
+        .await?;
+
+        data.map(|row| Self::map(None, &row)).transpose()
+    }
+
+    pub async fn save(
+        &self,
+        database: &crate::database::Database,
+        cache: &crate::cache::Cache,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query(
+            r#"
+            UPDATE ratelimit_policies
+            SET route_group = $2, burst = $3, rate = $4, period = $5
+            WHERE ratelimit_policies.id = $1
+            "#,
+        )
+        .bind(self.id)
+        .bind(&self.route_group)
+        .bind(self.burst)
+        .bind(self.rate)
+        .bind(self.period)
+        .execute(database.write())
+        .await?;
+
+        cache.invalidate("ratelimit::rules").await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(
+        &self,
+        database: &crate::database::Database,
+        cache: &crate::cache::Cache,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM ratelimit_policies
+            WHERE ratelimit_policies.id = $1
+            "#,
+        )
+        .bind(self.id)
+        .execute(database.write())
+        .await?;
+
+        cache.invalidate("ratelimit::rules").await?;
+
+        Ok(())
+    }
+
+    /// The most specific policy for a request: API key before organization before
+    /// global, and within each of those a matching route group before none.
+    pub fn resolve<'a>(
+        policies: &'a [Self],
+        organization_id: Option<i32>,
+        key_id: Option<&str>,
+        route_group: &str,
+    ) -> Option<&'a Self> {
+        policies
+            .iter()
+            .filter_map(|policy| {
+                let scope = match (&policy.key_id, policy.organization_id) {
+                    (Some(policy_key_id), _) if Some(policy_key_id.as_str()) == key_id => 2,
+                    (Some(_), _) => return None,
+                    (None, Some(id)) if Some(id) == organization_id => 1,
+                    (None, Some(_)) => return None,
+                    (None, None) => 0,
+                };
+
+                match policy.route_group.as_deref() {
+                    Some(group) if group == route_group => Some(((scope, 1), policy)),
+                    Some(_) => None,
+                    None => Some(((scope, 0), policy)),
+                }
+            })
+            .max_by_key(|(specificity, policy)| (*specificity, -policy.id))
+            .map(|(_, policy)| policy)
+    }
+
+    /// Whether the policy targets an organization or one of its keys rather than
+    /// every client.
+    #[inline]
+    pub fn is_scoped(&self) -> bool {
+        self.organization_id.is_some() || self.organization_key_id.is_some()
+    }
+}
+
+#[derive(ToSchema, Serialize, Deserialize, Clone)]
+pub struct RateLimitCost {
+    pub id: i32,
+
+    pub route_group: compact_str::CompactString,
+    /// Path prefix the cost applies to, the longest matching prefix wins.
+    pub path: compact_str::CompactString,
+    pub cost: i32,
+
+    pub created: NaiveDateTime,
+}
+
+impl BaseModel for RateLimitCost {
+    fn columns(
+        prefix: Option<&str>,
+        table: Option<&str>,
+    ) -> BTreeMap<compact_str::CompactString, compact_str::CompactString> {
+        let table = table.unwrap_or("ratelimit_costs");
+
+        BTreeMap::from([
+            (
+                compact_str::format_compact!("{table}.id"),
+                compact_str::format_compact!("{}id", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.route_group"),
+                compact_str::format_compact!("{}route_group", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.path"),
+                compact_str::format_compact!("{}path", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.cost"),
+                compact_str::format_compact!("{}cost", prefix.unwrap_or_default()),
+            ),
+            (
+                compact_str::format_compact!("{table}.created"),
+                compact_str::format_compact!("{}created", prefix.unwrap_or_default()),
+            ),
+        ])
+    }
+
+    fn map(prefix: Option<&str>, row: &PgRow) -> Result<Self, anyhow::Error> {
+        let prefix = prefix.unwrap_or_default();
+
+        Ok(Self {
+            id: row.try_get(compact_str::format_compact!("{prefix}id").as_str())?,
+            route_group: row
+                .try_get(compact_str::format_compact!("{prefix}route_group").as_str())?,
+            path: row.try_get(compact_str::format_compact!("{prefix}path").as_str())?,
+            cost: row.try_get(compact_str::format_compact!("{prefix}cost").as_str())?,
+            created: row.try_get(compact_str::format_compact!("{prefix}created").as_str())?,
+        })
+    }
+}
+
+impl RateLimitCost {
+    #[allow(clippy::new_ret_no_self)]
+    pub async fn new(
+        database: &crate::database::Database,
+        cache: &crate::cache::Cache,
+        route_group: &str,
+        path: &str,
+        cost: i32,
+    ) -> Result<Option<Self>, anyhow::Error> {
+        let row = sqlx::query(sqlx::AssertSqlSafe(format!(
+            r#"
+            INSERT INTO ratelimit_costs (route_group, path, cost)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (route_group, path) DO NOTHING
+            RETURNING {}
+            "#,
+            Self::columns_sql(None, None)
+        )))
+        .bind(route_group)
+        .bind(path)
+        .bind(cost)
+        .fetch_optional(database.write())
+        .await?;
+
+        cache.invalidate("ratelimit::rules").await?;
+
+        row.map(|row| Self::map(None, &row)).transpose()
+    }
+
+    pub async fn all(database: &crate::database::Database) -> Result<Vec<Self>, anyhow::Error> {
+        sqlx::query(sqlx::AssertSqlSafe(format!(
+            "SELECT {} FROM ratelimit_costs ORDER BY ratelimit_costs.id",
+            Self::columns_sql(None, None)
+        )))
+        .fetch_all(database.read())
+        .await?
+        .into_iter()
+        .map(|row| Self::map(None, &row))
+        .try_collect_vec()
+    }
+
+    pub async fn by_id(
+        database: &crate::database::Database,
+        id: i32,
+    ) -> Result<Option<Self>, anyhow::Error> {
+        if id < 1 {
+            return Ok(None);
+        }
+
+        let data = sqlx::query(sqlx::AssertSqlSafe(format!(
+            "SELECT {} FROM ratelimit_costs WHERE ratelimit_costs.id = $1",
+            Self::columns_sql(None, None)
+        )))
+        .bind(id)
+        .fetch_optional(database.read())
+        .await?;
+
+        data.map(|row| Self::map(None, &row)).transpose()
+    }
+
+    pub async fn save(
+        &self,
+        database: &crate::database::Database,
+        cache: &crate::cache::Cache,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query(
+            r#"
+            UPDATE ratelimit_costs
+            SET cost = $2
+            WHERE ratelimit_costs.id = $1
+            "#,
+        )
+        .bind(self.id)
+        .bind(self.cost)
+        .execute(database.write())
+        .await?;
+
+        cache.invalidate("ratelimit::rules").await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(
+        &self,
+        database: &crate::database::Database,
+        cache: &crate::cache::Cache,
+    ) -> Result<(), anyhow::Error> {
+        sqlx::query(
+            r#"
+            DELETE FROM ratelimit_costs
+            WHERE ratelimit_costs.id = $1
+            "#,
+        )
+        .bind(self.id)
+        .execute(database.write())
+        .await?;
+
+        cache.invalidate("ratelimit::rules").await?;
+
+        Ok(())
+    }
+
+    /// Cost of a request to `path`, 1 unless a configured prefix matches.
+    pub fn resolve(costs: &[Self], route_group: &str, path: &str) -> i32 {
+        costs
+            .iter()
+            .filter(|cost| cost.route_group == route_group && path.starts_with(cost.path.as_str()))
+            .max_by_key(|cost| cost.path.len())
+            .map_or(1, |cost| cost.cost)
+    }
+}
@@ -356,4 +356,73 @@ impl UserSession {
 
         Ok(())
     }
+
+    /// Deletes sessions that have not been used for `days` days.
+    #[inline]
+    pub async fn delete_unused(
+        database: &crate::database::Database,
+        days: u32,
+    ) -> Result<u64, anyhow::Error> {
+        Ok(sqlx::query(
+            r#"
+            DELETE FROM user_sessions
+            WHERE user_sessions.last_used < NOW() - make_interval(days => $1)
+            "#,
+        )
+        .bind(days as i32)
+        .execute(database.write())
+        .await?
+        .rows_affected())
+    }
+
+    /// Last known address of every session of `user_id`.
+    #[inline]
+    pub async fn ips_by_user(
+        database: &crate::database::Database,
+        user_id: i32,
+    ) -> Result<Vec<sqlx::types::ipnetwork::IpNetwork>, anyhow::Error> {
+        sqlx::query(
+            r#"
+            SELECT DISTINCT user_sessions.ip
+            FROM user_sessions
+            WHERE user_sessions.user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(database.read())
+        .await?
+        .into_iter()
+        .map(|row| Ok(row.try_get("ip")?))
+        .collect()
+    }
+
+    /// Deletes, or clears the address of, every session of `user_id` or from one of
+    /// `ips`. Deleted sessions stay usable until their cached lookup expires.
+    #[inline]
+    pub async fn erase(
+        database: &crate::database::Database,
+        user_id: Option<i32>,
+        ips: &[sqlx::types::ipnetwork::IpNetwork],
+        anonymize: bool,
+    ) -> Result<u64, anyhow::Error> {
+        let query = if anonymize {
+            r#"
+            UPDATE user_sessions
+            SET ip = '::'
+            WHERE user_sessions.user_id = $1 OR user_sessions.ip = ANY($2)
+            "#
+        } else {
+            r#"
+            DELETE FROM user_sessions
+            WHERE user_sessions.user_id = $1 OR user_sessions.ip = ANY($2)
+            "#
+        };
+
+        Ok(sqlx::query(query)
+            .bind(user_id)
+            .bind(ips)
+            .execute(database.write())
+            .await?
+            .rows_affected())
+    }
 }
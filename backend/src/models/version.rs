@@ -154,9 +154,9 @@ impl Version {
     ) -> Result<Option<(compact_str::CompactString, compact_str::CompactString)>, anyhow::Error>
     {
         cache
-            .cached(
+            .cached_with(
                 &format!("version_location::{type}::{id}"),
-                86400,
+                crate::cache::CacheOptions::new(86400).tag(format!("type:{type}")),
                 || async {
                     if id == "latest" || id == "latest-snapshot" {
                         if SERVER_TYPES_WITH_PROJECT_AS_IDENTIFIER.contains(&r#type) {
@@ -251,8 +251,10 @@ impl Version {
         cache: &crate::cache::Cache,
         r#type: ServerType,
     ) -> Result<IndexMap<compact_str::CompactString, Self>, anyhow::Error> {
+        let options = crate::cache::CacheOptions::new(1800).tag(format!("type:{type}"));
+
         cache
-            .cached(&format!("versions::{type}"), 1800, || async {
+            .cached_with(&format!("versions::{type}"), options, || async {
                 let mut versions = IndexMap::new();
 
                 if SERVER_TYPES_WITH_PROJECT_AS_IDENTIFIER.contains(&r#type) {
@@ -0,0 +1,193 @@
+use ipnetwork::IpNetwork;
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::AsyncReadExt,
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+const V1_MAX_LENGTH: usize = 107;
+const HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Accepts connections that start with a PROXY protocol (v1 or v2) header, as sent by
+/// HAProxy and most cloud load balancers. The address from the header is used as the
+/// peer address when the connection comes from a trusted proxy, connections without a
+/// valid header are closed. Headers are read off the accept loop so a slow client
+/// cannot hold up others.
+pub struct ProxyProtocolListener {
+    local_addr: SocketAddr,
+    receiver: mpsc::Receiver<(TcpStream, SocketAddr)>,
+}
+
+impl ProxyProtocolListener {
+    pub fn new(listener: TcpListener, trusted_proxies: Vec<IpNetwork>) -> std::io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let trusted_proxies = Arc::new(trusted_proxies);
+        let (sender, receiver) = mpsc::channel(1024);
+
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, peer) = match listener.accept().await {
+                    Ok(accepted) => accepted,
+                    Err(err) => {
+                        tracing::error!("failed to accept connection: {:?}", err);
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+
+                let trusted_proxies = trusted_proxies.clone();
+                let sender = sender.clone();
+
+                tokio::spawn(async move {
+                    let source = match tokio::time::timeout(
+                        HEADER_TIMEOUT,
+                        read_header(&mut stream),
+                    )
+                    .await
+                    {
+                        Ok(Ok(source)) => source,
+                        Ok(Err(err)) => {
+                            tracing::debug!("invalid proxy protocol header from {peer}: {err}");
+                            return;
+                        }
+                        Err(_) => {
+                            tracing::debug!("timed out reading proxy protocol header from {peer}");
+                            return;
+                        }
+                    };
+
+                    let peer_ip = peer.ip().to_canonical();
+                    let addr = match source {
+                        Some(source)
+                            if trusted_proxies
+                                .iter()
+                                .any(|network| network.contains(peer_ip)) =>
+                        {
+                            source
+                        }
+                        _ => peer,
+                    };
+
+                    sender.send((stream, addr)).await.ok();
+                });
+            }
+        });
+
+        Ok(Self {
+            local_addr,
+            receiver,
+        })
+    }
+}
+
+impl axum::serve::Listener for ProxyProtocolListener {
+    type Io = TcpStream;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.receiver.recv().await {
+            Some(accepted) => accepted,
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+fn invalid(message: &'static str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Reads exactly the header off `stream`, returning the source address it carries.
+/// `LOCAL` (v2) and `UNKNOWN` (v1) connections have none.
+async fn read_header(stream: &mut TcpStream) -> std::io::Result<Option<SocketAddr>> {
+    // the shortest v1 header, "PROXY UNKNOWN\r\n", is longer than this
+    let mut start = [0u8; 12];
+    stream.read_exact(&mut start).await?;
+
+    if start == V2_SIGNATURE {
+        return read_v2(stream).await;
+    }
+
+    if !start.starts_with(b"PROXY ") {
+        return Err(invalid("missing proxy protocol header"));
+    }
+
+    let mut line = start.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LENGTH {
+            return Err(invalid("proxy protocol v1 header too long"));
+        }
+
+        line.push(stream.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| invalid("proxy protocol v1 header is not ascii"))?;
+    let mut parts = line.split(' ').skip(1);
+
+    match parts.next() {
+        Some("TCP4") | Some("TCP6") => {
+            let (Some(source), Some(_), Some(port), Some(_)) =
+                (parts.next(), parts.next(), parts.next(), parts.next())
+            else {
+                return Err(invalid("incomplete proxy protocol v1 header"));
+            };
+
+            let ip: IpAddr = source
+                .parse()
+                .map_err(|_| invalid("invalid proxy protocol v1 source address"))?;
+            let port: u16 = port
+                .parse()
+                .map_err(|_| invalid("invalid proxy protocol v1 source port"))?;
+
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        Some("UNKNOWN") => Ok(None),
+        _ => Err(invalid("unsupported proxy protocol v1 transport")),
+    }
+}
+
+async fn read_v2(stream: &mut TcpStream) -> std::io::Result<Option<SocketAddr>> {
+    let version_command = stream.read_u8().await?;
+    let family = stream.read_u8().await?;
+    let length = stream.read_u16().await? as usize;
+
+    let mut addresses = vec![0u8; length];
+    stream.read_exact(&mut addresses).await?;
+
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported proxy protocol version"));
+    }
+
+    // LOCAL, sent by the proxy for its own health checks
+    if version_command & 0x0f == 0 {
+        return Ok(None);
+    }
+
+    match family >> 4 {
+        1 if addresses.len() >= 12 => {
+            let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        2 if addresses.len() >= 36 => {
+            let ip: [u8; 16] = addresses[0..16].try_into().unwrap();
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(ip).into(), port)))
+        }
+        // AF_UNIX or unspecified, there is no usable client address
+        0 | 3 => Ok(None),
+        _ => Err(invalid("invalid proxy protocol v2 address block")),
+    }
+}
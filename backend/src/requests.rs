@@ -1,24 +1,35 @@
-use crate::{models::organization::Organization, routes::State};
+use crate::{
+    files::CacheStatus,
+    models::{
+        organization::Organization,
+        ratelimit::{RateLimitCost, RateLimitPolicy},
+    },
+    routes::State,
+};
 use axum::{
     body::Bytes,
-    http::{Method, request::Parts},
+    http::{Method, Uri, request::Parts},
 };
 use chrono::NaiveDateTime;
-use compact_str::ToCompactString;
-use futures_util::Stream;
+use futures_util::{Stream, StreamExt};
 use rand::distr::SampleString;
 use rustis::commands::ScriptingCommands;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+use sha2::Digest;
 use sqlx::types::ipnetwork::IpNetwork;
 use std::{
     collections::{HashMap, HashSet},
-    net::Ipv6Addr,
+    net::{IpAddr, Ipv6Addr},
     pin::Pin,
-    sync::Arc,
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
     task::{Context, Poll},
-    time::Instant,
+    time::{Duration, Instant},
 };
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, mpsc};
+use utoipa::ToSchema;
 
 pub struct Request {
     id: String,
@@ -41,7 +52,7 @@ pub struct Request {
     created: NaiveDateTime,
 }
 
-#[derive(Debug, Serialize, clickhouse::Row)]
+#[derive(Debug, Serialize, Deserialize, clickhouse::Row)]
 pub struct ClickhouseRequest {
     id: [u8; 12],
     organization_id: Option<i32>,
@@ -61,6 +72,12 @@ pub struct ClickhouseRequest {
     country: Option<[u8; 2]>,
 
     user_agent: String,
+    #[serde(default)]
+    client_category: i8,
+    #[serde(default)]
+    client_family: String,
+    #[serde(default)]
+    client_version: String,
 
     #[serde(with = "clickhouse::serde::chrono::datetime64::millis")]
     created: chrono::DateTime<chrono::Utc>,
@@ -68,6 +85,8 @@ pub struct ClickhouseRequest {
 
 impl From<Request> for ClickhouseRequest {
     fn from(req: Request) -> Self {
+        let client = crate::user_agent::classify(&req.user_agent);
+
         Self {
             id: req.id.as_bytes().try_into().unwrap_or([0u8; 12]),
             organization_id: req.organization_id,
@@ -98,6 +117,9 @@ impl From<Request> for ClickhouseRequest {
             continent: req.continent.and_then(|c| c.as_bytes().try_into().ok()),
             country: req.country.and_then(|c| c.as_bytes().try_into().ok()),
             user_agent: req.user_agent,
+            client_category: client.category.as_i8(),
+            client_family: client.family.to_string(),
+            client_version: client.version,
             created: req.created.and_utc(),
         }
     }
@@ -108,6 +130,7 @@ pub enum FileRequestKind {
     Index,
     File,
     Checksums,
+    Archive,
 }
 
 impl FileRequestKind {
@@ -117,6 +140,7 @@ impl FileRequestKind {
             Self::Index => 1,
             Self::File => 2,
             Self::Checksums => 3,
+            Self::Archive => 4,
         }
     }
 }
@@ -133,7 +157,7 @@ pub struct FileRequest {
     extension: String,
     size: i64,
     bytes_sent: i64,
-    cache_hit: bool,
+    cache_hit: CacheStatus,
     time: i32,
     status: i16,
 
@@ -145,7 +169,7 @@ pub struct FileRequest {
     created: NaiveDateTime,
 }
 
-#[derive(Debug, Serialize, clickhouse::Row)]
+#[derive(Debug, Serialize, Deserialize, clickhouse::Row)]
 pub struct ClickhouseFileRequest {
     id: [u8; 12],
     organization_id: Option<i32>,
@@ -158,7 +182,7 @@ pub struct ClickhouseFileRequest {
     extension: String,
     size: i64,
     bytes_sent: i64,
-    cache_hit: bool,
+    cache_hit: i8,
     time: i32,
     status: i16,
 
@@ -168,6 +192,12 @@ pub struct ClickhouseFileRequest {
     country: Option<[u8; 2]>,
 
     user_agent: String,
+    #[serde(default)]
+    client_category: i8,
+    #[serde(default)]
+    client_family: String,
+    #[serde(default)]
+    client_version: String,
 
     #[serde(with = "clickhouse::serde::chrono::datetime64::millis")]
     created: chrono::DateTime<chrono::Utc>,
@@ -175,6 +205,8 @@ pub struct ClickhouseFileRequest {
 
 impl From<FileRequest> for ClickhouseFileRequest {
     fn from(req: FileRequest) -> Self {
+        let client = crate::user_agent::classify(&req.user_agent);
+
         Self {
             id: req.id.as_bytes().try_into().unwrap_or([0u8; 12]),
             organization_id: req.organization_id,
@@ -199,7 +231,7 @@ impl From<FileRequest> for ClickhouseFileRequest {
             extension: req.extension,
             size: req.size,
             bytes_sent: req.bytes_sent,
-            cache_hit: req.cache_hit,
+            cache_hit: req.cache_hit.as_i8(),
             time: req.time,
             status: req.status,
             ip: match req.ip {
@@ -209,6 +241,9 @@ impl From<FileRequest> for ClickhouseFileRequest {
             continent: req.continent.and_then(|c| c.as_bytes().try_into().ok()),
             country: req.country.and_then(|c| c.as_bytes().try_into().ok()),
             user_agent: req.user_agent,
+            client_category: client.category.as_i8(),
+            client_family: client.family.to_string(),
+            client_version: client.version,
             created: req.created.and_utc(),
         }
     }
@@ -224,9 +259,38 @@ const ACCEPTED_METHODS: &[Method] = &[
 
 #[derive(Debug, Clone, Copy)]
 pub struct RateLimitData {
+    /// The route group, used as the policy name in headers.
+    pub name: &'static str,
+    pub allowed: bool,
     pub limit: i64,
-    pub hits: i64,
+    pub rate: i64,
+    pub period: i64,
+    pub remaining: i64,
     pub reset: i64,
+    pub retry_after: i64,
+}
+
+impl RateLimitData {
+    /// `RateLimit-Policy` and `RateLimit` structured fields, followed by the
+    /// `X-RateLimit-*` headers older clients read.
+    pub fn headers(&self) -> [(&'static str, String); 5] {
+        [
+            (
+                "RateLimit-Policy",
+                format!(
+                    "\"{}\";q={};w={};burst={}",
+                    self.name, self.rate, self.period, self.limit
+                ),
+            ),
+            (
+                "RateLimit",
+                format!("\"{}\";r={};t={}", self.name, self.remaining, self.reset),
+            ),
+            ("X-RateLimit-Limit", self.limit.to_string()),
+            ("X-RateLimit-Remaining", self.remaining.to_string()),
+            ("X-RateLimit-Reset", self.reset.to_string()),
+        ]
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -234,33 +298,48 @@ enum RateLimitBucket {
     Regular,
     FilesBrowse,
     FilesDownload,
+    FilesArchive,
 }
 
 impl RateLimitBucket {
-    fn from_request(path: &str, method: &Method) -> Self {
+    fn from_request(uri: &Uri, method: &Method) -> Self {
+        let path = uri.path();
         if path != "/files" && !path.starts_with("/files/") {
             return Self::Regular;
         }
 
-        if method != Method::HEAD && (path.ends_with(".jar") || path.ends_with(".zip")) {
-            Self::FilesDownload
+        // only directories are archived, files ignore the parameter
+        if path.ends_with(".jar") || path.ends_with(".zip") {
+            if method != Method::HEAD {
+                Self::FilesDownload
+            } else {
+                Self::FilesBrowse
+            }
+        } else if matches!(
+            crate::routes::files::archive::Format::from_uri(uri),
+            Ok(Some(_))
+        ) {
+            Self::FilesArchive
         } else {
             Self::FilesBrowse
         }
     }
 
-    fn suffix(self) -> &'static str {
+    fn name(self) -> &'static str {
         match self {
             Self::Regular => "regular",
             Self::FilesBrowse => "files",
             Self::FilesDownload => "files_download",
+            Self::FilesArchive => "files_archive",
         }
     }
 
-    fn limit(self, organization: Option<&Organization>) -> i64 {
+    /// Requests per minute when no policy applies.
+    fn default_limit(self, organization: Option<&Organization>) -> i32 {
         let base = match self {
             Self::Regular | Self::FilesBrowse => 120,
             Self::FilesDownload => 30,
+            Self::FilesArchive => 5,
         };
 
         if organization.is_some() {
@@ -271,89 +350,497 @@ impl RateLimitBucket {
     }
 }
 
-const RATELIMIT_WINDOW: i64 = 60;
+/// GCRA: the stored value is the theoretical arrival time of the next request in
+/// milliseconds, a request is allowed while it is at most `burst` intervals ahead.
 const RATELIMIT_SCRIPT: &str = r#"
-local hits = redis.call('INCR', KEYS[1])
-local ttl = redis.call('TTL', KEYS[1])
-if ttl < 0 then
-  redis.call('EXPIRE', KEYS[1], ARGV[1])
-  ttl = tonumber(ARGV[1])
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local interval = tonumber(ARGV[1])
+local burst = tonumber(ARGV[2])
+local cost = tonumber(ARGV[3])
+local tolerance = interval * burst
+
+local tat = tonumber(redis.call('GET', KEYS[1])) or now
+if tat < now then
+  tat = now
+end
+
+local new_tat = tat + interval * cost
+local allow_at = new_tat - tolerance
+if allow_at > now then
+  return {0, math.floor((tolerance - (tat - now)) / interval), math.ceil((tat - now) / 1000), math.ceil((allow_at - now) / 1000)}
+end
+
+redis.call('SET', KEYS[1], new_tat, 'PX', math.max(new_tat - now, 1))
+return {1, math.floor((tolerance - (new_tat - now)) / interval), math.ceil((new_tat - now) / 1000), 0}
+"#;
+
+const REQUESTS_QUEUE: &str = "mcjars_api::analytics::requests";
+const FILE_REQUESTS_QUEUE: &str = "mcjars_api::analytics::file_requests";
+const QUEUE_GROUP: &str = "clickhouse";
+/// Entries delivered to a consumer that did not acknowledge them within this time are
+/// handed out again, which is how rows are replayed after a failed ClickHouse insert
+/// or a crash mid-batch.
+const QUEUE_CLAIM_IDLE_MS: u64 = 60_000;
+/// Rows kept in memory while Redis is unreachable, the oldest are dropped beyond this.
+const UNQUEUED_LIMIT: usize = 100_000;
+/// Events waiting for the collector, further events are dropped while it is full.
+const EVENTS_CAPACITY: usize = 65_536;
+const EVENTS_BATCH: usize = 1024;
+
+const ENQUEUE_SCRIPT: &str = r#"
+for i = 2, #ARGV do
+  if ARGV[1] == '0' then
+    redis.call('XADD', KEYS[1], '*', 'row', ARGV[i])
+  else
+    redis.call('XADD', KEYS[1], 'MAXLEN', '~', ARGV[1], '*', 'row', ARGV[i])
+  end
+end
+return #ARGV - 1
+"#;
+const CONSUME_SCRIPT: &str = r#"
+pcall(redis.call, 'XGROUP', 'CREATE', KEYS[1], ARGV[1], '0', 'MKSTREAM')
+
+local count = tonumber(ARGV[4])
+local result = {}
+
+local claimed = redis.call('XAUTOCLAIM', KEYS[1], ARGV[1], ARGV[2], ARGV[3], '0-0', 'COUNT', count)
+for _, entry in ipairs(claimed[2]) do
+  if entry and entry[2] then
+    table.insert(result, entry[1])
+    table.insert(result, entry[2][2])
+  end
 end
-return {hits, ttl}
+
+local remaining = count - #result / 2
+if remaining > 0 then
+  local read = redis.call('XREADGROUP', 'GROUP', ARGV[1], ARGV[2], 'COUNT', remaining, 'STREAMS', KEYS[1], '>')
+  if read then
+    for _, entry in ipairs(read[1][2]) do
+      table.insert(result, entry[1])
+      table.insert(result, entry[2][2])
+    end
+  end
+end
+
+return result
+"#;
+const ACK_SCRIPT: &str = r#"
+local acked = 0
+for i = 2, #ARGV, 1000 do
+  local last = math.min(i + 999, #ARGV)
+  acked = acked + redis.call('XACK', KEYS[1], ARGV[1], unpack(ARGV, i, last))
+  redis.call('XDEL', KEYS[1], unpack(ARGV, i, last))
+end
+return acked
+"#;
+const QUEUE_STATS_SCRIPT: &str = r#"
+local length = redis.call('XLEN', KEYS[1])
+local pending = 0
+local ok, summary = pcall(redis.call, 'XPENDING', KEYS[1], ARGV[1])
+if ok then
+  pending = summary[1]
+end
+
+local oldest = 0
+local first = redis.call('XRANGE', KEYS[1], '-', '+', 'COUNT', 1)
+if first[1] then
+  oldest = tonumber(string.match(first[1][1], '^(%d+)'))
+end
+
+return {length, pending, oldest}
+"#;
+
+#[derive(ToSchema, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+#[schema(rename_all = "camelCase")]
+pub struct AnalyticsQueueStats {
+    /// Entries in the stream, both unread and delivered but not yet acknowledged.
+    pub length: i64,
+    /// Entries delivered to a processor and not yet acknowledged.
+    pub pending: i64,
+    /// Age of the oldest entry in seconds.
+    pub oldest_age: i64,
+    /// Rows held in memory because they could not be written to Redis.
+    pub buffered: usize,
+}
+
+#[derive(ToSchema, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+#[schema(rename_all = "camelCase")]
+pub struct AnalyticsStats {
+    #[schema(inline)]
+    pub requests: AnalyticsQueueStats,
+    #[schema(inline)]
+    pub file_requests: AnalyticsQueueStats,
+
+    /// Events waiting for the collector.
+    pub events: usize,
+    pub max_length: u64,
+    pub enqueue_failures: u64,
+    pub dropped: u64,
+}
+
+#[derive(Deserialize, clickhouse::Row)]
+struct RequestTraceRow {
+    id: String,
+    origin: Option<String>,
+    method: String,
+    path: String,
+    time: i32,
+    status: i16,
+    body: Option<String>,
+    data: Option<String>,
+    continent: Option<String>,
+    country: Option<String>,
+    user_agent: String,
+    client_family: String,
+    client_version: String,
+
+    #[serde(with = "clickhouse::serde::chrono::datetime64::millis")]
+    created: chrono::DateTime<chrono::Utc>,
+}
+
+const REQUEST_TRACE_COLUMNS: &str = r#"
+    toString(requests.id) AS id,
+    requests.origin AS origin,
+    toString(requests.method) AS method,
+    requests.path AS path,
+    requests.time AS time,
+    requests.status AS status,
+    requests.body AS body,
+    requests.data AS data,
+    toString(requests.continent) AS continent,
+    toString(requests.country) AS country,
+    requests.user_agent AS user_agent,
+    requests.client_family AS client_family,
+    requests.client_version AS client_version,
+    requests.created AS created
 "#;
 
+/// A logged request as shown to the organization that made it.
+#[derive(ToSchema, Serialize)]
+#[serde(rename_all = "camelCase")]
+#[schema(rename_all = "camelCase")]
+pub struct RequestTrace {
+    pub id: String,
+    pub origin: Option<String>,
+    pub method: String,
+    pub path: String,
+    /// Milliseconds until the response was sent.
+    pub time: i32,
+    pub status: i16,
+
+    pub continent: Option<String>,
+    pub country: Option<String>,
+    pub user_agent: String,
+    pub client_family: String,
+    pub client_version: String,
+
+    /// What the route recorded about the request, such as the build it resolved to.
+    pub data: Option<serde_json::Value>,
+    /// Only present for routes that capture their body, see `REQUEST_BODY_CAPTURE`.
+    pub body: Option<serde_json::Value>,
+
+    pub created: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<RequestTraceRow> for RequestTrace {
+    fn from(row: RequestTraceRow) -> Self {
+        Self {
+            id: row.id,
+            origin: row.origin,
+            method: row.method,
+            path: row.path,
+            time: row.time,
+            status: row.status,
+            continent: row.continent,
+            country: row.country,
+            user_agent: row.user_agent,
+            client_family: row.client_family,
+            client_version: row.client_version,
+            data: row.data.and_then(|data| serde_json::from_str(&data).ok()),
+            body: row.body.and_then(|body| serde_json::from_str(&body).ok()),
+            created: row.created,
+        }
+    }
+}
+
+/// Filters of the failed requests routes. `hours` is clamped to 1..=720 and `limit` to
+/// 1..=100 when they are used.
+#[derive(ToSchema, Deserialize)]
+pub struct FailedRequestsParams {
+    status: Option<i16>,
+    #[serde(default)]
+    path: String,
+    #[serde(default = "default_failed_hours")]
+    hours: u32,
+    #[serde(default = "default_failed_limit")]
+    limit: u32,
+}
+
+#[inline]
+fn default_failed_hours() -> u32 {
+    24
+}
+
+#[inline]
+fn default_failed_limit() -> u32 {
+    50
+}
+
+/// Rows read from a queue, with the stream ids to acknowledge once they are inserted.
+struct QueueBatch<T> {
+    ids: Vec<String>,
+    rows: Vec<T>,
+}
+
+enum RequestEvent {
+    Start(Request),
+    Finish {
+        id: String,
+        status: i16,
+        time: i32,
+        data: Option<serde_json::Value>,
+        body: Option<serde_json::Value>,
+    },
+    StartFile(FileRequest),
+    FinishFile {
+        id: String,
+        status: i16,
+        time: i32,
+        bytes_sent: i64,
+    },
+}
+
+/// A long-lived ClickHouse inserter together with the stream ids of the rows it has
+/// buffered, which are acknowledged once the inserter flushes them.
+struct QueueInserter<T: clickhouse::Row> {
+    queue: &'static str,
+    inserter: clickhouse::inserter::Inserter<T>,
+    ids: Vec<String>,
+    buffered: u64,
+}
+
+/// Request and file request events go through a channel to [`RequestLogger::collect`],
+/// which pairs starts with finishes and appends finished rows to a Redis stream.
+/// [`RequestLogger::insert`] reads the streams into long-lived ClickHouse inserters and
+/// acknowledges entries once they are flushed, so rows survive a restart of any
+/// instance as well as a ClickHouse outage.
 pub struct RequestLogger {
-    pending: Mutex<Vec<Request>>,
-    processing: Mutex<Vec<Request>>,
-    pending_files: Mutex<Vec<FileRequest>>,
-    processing_files: Mutex<Vec<FileRequest>>,
+    events: mpsc::Sender<RequestEvent>,
+    receiver: Mutex<Option<mpsc::Receiver<RequestEvent>>>,
+    unqueued_requests: Mutex<Vec<String>>,
+    unqueued_file_requests: Mutex<Vec<String>>,
     uncounted_requests: Mutex<i64>,
+    enqueue_failures: AtomicU64,
+    dropped: AtomicU64,
+    consumer: String,
+    max_length: u64,
+    batch_size: usize,
+    flush_rows: u64,
+    flush_bytes: u64,
+    flush_interval: Duration,
+    ratelimit_ipv6_prefix: u8,
+    ip_anonymization: crate::env::IpAnonymization,
     database: Arc<crate::database::Database>,
     clickhouse: Arc<crate::clickhouse::Clickhouse>,
     cache: Arc<crate::cache::Cache>,
-
-    client: reqwest::Client,
+    geoip: Arc<dyn crate::geoip::GeoIpResolver>,
 }
 
 impl RequestLogger {
     pub fn new(
+        env: &crate::env::Env,
         database: Arc<crate::database::Database>,
         clickhouse: Arc<crate::clickhouse::Clickhouse>,
         cache: Arc<crate::cache::Cache>,
+        geoip: Arc<dyn crate::geoip::GeoIpResolver>,
     ) -> Self {
+        let (events, receiver) = mpsc::channel(EVENTS_CAPACITY);
+
         Self {
-            pending: Mutex::new(Vec::new()),
-            processing: Mutex::new(Vec::new()),
-            pending_files: Mutex::new(Vec::new()),
-            processing_files: Mutex::new(Vec::new()),
+            events,
+            receiver: Mutex::new(Some(receiver)),
+            unqueued_requests: Mutex::new(Vec::new()),
+            unqueued_file_requests: Mutex::new(Vec::new()),
             uncounted_requests: Mutex::new(0),
+            enqueue_failures: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            consumer: env
+                .server_name
+                .clone()
+                .unwrap_or_else(|| rand::distr::Alphanumeric.sample_string(&mut rand::rng(), 12)),
+            max_length: env.analytics_queue_max_length,
+            batch_size: env.analytics_batch_size.max(1),
+            flush_rows: env.analytics_flush_rows,
+            flush_bytes: env.analytics_flush_bytes,
+            // buffered entries must be flushed well before they can be claimed again
+            flush_interval: Duration::from_secs(
+                env.analytics_flush_interval
+                    .clamp(1, QUEUE_CLAIM_IDLE_MS / 1000 / 4),
+            ),
+            ratelimit_ipv6_prefix: env.ratelimit_ipv6_prefix,
+            ip_anonymization: env.ip_anonymization.clone(),
             database,
             clickhouse,
             cache,
+            geoip,
+        }
+    }
+
+    /// Appends `rows` to `queue`, keeping them in `unqueued` to retry on the next
+    /// [`Self::process`] when Redis is unavailable.
+    async fn enqueue(&self, queue: &str, unqueued: &Mutex<Vec<String>>, rows: Vec<String>) {
+        if rows.is_empty() {
+            return;
+        }
+
+        let mut args = Vec::with_capacity(rows.len() + 1);
+        args.push(self.max_length.to_string());
+        args.extend(rows);
+
+        if let Err(err) = self
+            .cache
+            .client
+            .eval::<i64>(ENQUEUE_SCRIPT, [queue], &args)
+            .await
+        {
+            self.enqueue_failures.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(
+                "failed to enqueue {} rows to {queue}, buffering in memory: {:?}",
+                args.len() - 1,
+                err
+            );
+
+            let mut unqueued = unqueued.lock().await;
+            unqueued.extend(args.into_iter().skip(1));
+
+            if unqueued.len() > UNQUEUED_LIMIT {
+                let overflow = unqueued.len() - UNQUEUED_LIMIT;
+                unqueued.drain(..overflow);
+                self.dropped.fetch_add(overflow as u64, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn send(&self, event: RequestEvent) {
+        if self.events.try_send(event).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    async fn consume<T: DeserializeOwned>(
+        &self,
+        queue: &str,
+    ) -> Result<QueueBatch<T>, anyhow::Error> {
+        let entries: Vec<String> = self
+            .cache
+            .client
+            .eval(
+                CONSUME_SCRIPT,
+                [queue],
+                [
+                    QUEUE_GROUP.to_string(),
+                    self.consumer.clone(),
+                    QUEUE_CLAIM_IDLE_MS.to_string(),
+                    self.batch_size.to_string(),
+                ],
+            )
+            .await?;
+
+        let mut batch = QueueBatch {
+            ids: Vec::with_capacity(entries.len() / 2),
+            rows: Vec::with_capacity(entries.len() / 2),
+        };
+
+        for entry in entries.chunks_exact(2) {
+            match serde_json::from_str(&entry[1]) {
+                Ok(row) => batch.rows.push(row),
+                Err(err) => {
+                    tracing::warn!("dropping malformed entry {} in {queue}: {}", entry[0], err)
+                }
+            }
+
+            batch.ids.push(entry[0].clone());
+        }
+
+        Ok(batch)
+    }
 
-            client: reqwest::Client::builder()
-                .user_agent("MCJars API https://mcjars.app")
-                .build()
-                .unwrap(),
+    async fn acknowledge(&self, queue: &str, ids: Vec<String>) -> Result<(), anyhow::Error> {
+        if ids.is_empty() {
+            return Ok(());
         }
+
+        let mut args = Vec::with_capacity(ids.len() + 1);
+        args.push(QUEUE_GROUP.to_string());
+        args.extend(ids);
+
+        self.cache
+            .client
+            .eval::<i64>(ACK_SCRIPT, [queue], &args)
+            .await?;
+
+        Ok(())
+    }
+
+    async fn queue_stats(
+        &self,
+        queue: &str,
+        unqueued: &Mutex<Vec<String>>,
+    ) -> Result<AnalyticsQueueStats, anyhow::Error> {
+        let (length, pending, oldest): (i64, i64, i64) = self
+            .cache
+            .client
+            .eval(QUEUE_STATS_SCRIPT, [queue], [QUEUE_GROUP])
+            .await?;
+
+        Ok(AnalyticsQueueStats {
+            length,
+            pending,
+            oldest_age: if oldest > 0 {
+                (chrono::Utc::now().timestamp_millis() - oldest).max(0) / 1000
+            } else {
+                0
+            },
+            buffered: unqueued.lock().await.len(),
+        })
+    }
+
+    pub async fn stats(&self) -> Result<AnalyticsStats, anyhow::Error> {
+        let (requests, file_requests) = tokio::try_join!(
+            self.queue_stats(REQUESTS_QUEUE, &self.unqueued_requests),
+            self.queue_stats(FILE_REQUESTS_QUEUE, &self.unqueued_file_requests),
+        )?;
+
+        Ok(AnalyticsStats {
+            requests,
+            file_requests,
+            events: self.events.max_capacity() - self.events.capacity(),
+            max_length: self.max_length,
+            enqueue_failures: self.enqueue_failures.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        })
     }
 
     pub async fn log(
         &self,
         request: &Parts,
         organization: Option<&Organization>,
+        key_id: Option<&str>,
+        signed: bool,
     ) -> Result<(Option<String>, Option<RateLimitData>), Option<RateLimitData>> {
         let ip = match crate::utils::extract_ip(&request.headers) {
             Some(ip) => ip,
             None => std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST),
         };
 
-        let mut ratelimit: Option<RateLimitData> = None;
-        if organization.is_none_or(|o| !o.verified) {
-            let bucket = RateLimitBucket::from_request(request.uri.path(), &request.method);
-            let ratelimit_key = format!("mcjars_api::ratelimit::{ip}::{}", bucket.suffix());
-
-            let (hits, reset): (i64, i64) = self
-                .cache
-                .client
-                .eval(
-                    RATELIMIT_SCRIPT,
-                    [ratelimit_key.as_str()],
-                    [RATELIMIT_WINDOW],
-                )
-                .await
-                .unwrap_or((0, RATELIMIT_WINDOW));
-
-            let data = RateLimitData {
-                limit: bucket.limit(organization),
-                hits,
-                reset,
-            };
-            ratelimit = Some(data);
-
-            if hits > data.limit {
-                return Err(ratelimit);
-            }
+        let ratelimit = self
+            .ratelimit(request, organization, key_id, signed, ip)
+            .await;
+        if ratelimit.is_some_and(|ratelimit| !ratelimit.allowed) {
+            return Err(ratelimit);
         }
 
         *self.uncounted_requests.lock().await += 1;
@@ -409,12 +896,12 @@ impl RequestLogger {
         };
 
         let id = data.id.clone();
-        self.pending.lock().await.push(data);
+        self.send(RequestEvent::Start(data));
 
         Ok((Some(id), ratelimit))
     }
 
-    pub async fn finish(
+    pub fn finish(
         &self,
         id: String,
         status: i16,
@@ -422,19 +909,96 @@ impl RequestLogger {
         data: Option<serde_json::Value>,
         body: Option<serde_json::Value>,
     ) {
-        let mut pending = self.pending.lock().await;
+        self.send(RequestEvent::Finish {
+            id,
+            status,
+            time,
+            data,
+            body,
+        });
+    }
 
-        if let Some(index) = pending.iter().position(|r| r.id == id) {
-            let mut request = pending.remove(index);
+    /// Applies the most specific policy for the request, or the defaults of its route
+    /// group. Verified organizations and signed URLs are only limited by policies that
+    /// name their organization or key.
+    async fn ratelimit(
+        &self,
+        request: &Parts,
+        organization: Option<&Organization>,
+        key_id: Option<&str>,
+        signed: bool,
+        ip: std::net::IpAddr,
+    ) -> Option<RateLimitData> {
+        let bucket = RateLimitBucket::from_request(&request.uri, &request.method);
+        let subject = crate::utils::ratelimit_subject(ip, self.ratelimit_ipv6_prefix);
+        let exempt = signed || organization.is_some_and(|o| o.verified);
+
+        let (policies, costs) = crate::models::ratelimit::rules(&self.database, &self.cache)
+            .await
+            .unwrap_or_else(|err| {
+                tracing::error!("failed to load rate limit rules: {:?}", err);
+                (Vec::new(), Vec::new())
+            });
+
+        let policy =
+            RateLimitPolicy::resolve(&policies, organization.map(|o| o.id), key_id, bucket.name());
+
+        let (key, burst, rate, period) = match policy {
+            Some(policy) if !exempt || policy.is_scoped() => (
+                match (&policy.key_id, policy.organization_id) {
+                    (Some(key_id), _) => {
+                        format!("mcjars_api::ratelimit::key::{key_id}::{}", bucket.name())
+                    }
+                    (None, Some(organization_id)) => format!(
+                        "mcjars_api::ratelimit::organization::{organization_id}::{}",
+                        bucket.name()
+                    ),
+                    (None, None) => format!("mcjars_api::ratelimit::{subject}::{}", bucket.name()),
+                },
+                policy.burst,
+                policy.rate,
+                policy.period,
+            ),
+            None if !exempt => {
+                let limit = bucket.default_limit(organization);
+
+                (
+                    format!("mcjars_api::ratelimit::{subject}::{}", bucket.name()),
+                    limit,
+                    limit,
+                    60,
+                )
+            }
+            _ => return None,
+        };
 
-            request.end = true;
-            request.status = status;
-            request.time = time;
-            request.data = data;
-            request.body = body;
+        let (burst, rate, period) = (
+            burst.max(1) as i64,
+            rate.max(1) as i64,
+            period.max(1) as i64,
+        );
+        let interval = (period * 1000 / rate).max(1);
+        let cost = RateLimitCost::resolve(&costs, bucket.name(), request.uri.path()).max(1) as i64;
+        // a cost above the burst could never pass, it takes the whole burst instead
+        let cost = cost.min(burst);
 
-            self.processing.lock().await.push(request);
-        }
+        let (allowed, remaining, reset, retry_after): (i64, i64, i64, i64) = self
+            .cache
+            .client
+            .eval(RATELIMIT_SCRIPT, [key.as_str()], [interval, burst, cost])
+            .await
+            .unwrap_or((1, burst, 0, 0));
+
+        Some(RateLimitData {
+            name: bucket.name(),
+            allowed: allowed == 1,
+            limit: burst,
+            rate,
+            period,
+            remaining: remaining.max(0),
+            reset,
+            retry_after,
+        })
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -445,7 +1009,7 @@ impl RequestLogger {
         kind: FileRequestKind,
         path: &std::path::Path,
         size: i64,
-        cache_hit: bool,
+        cache_hit: CacheStatus,
     ) -> String {
         let ip = match crate::utils::extract_ip(&request.headers) {
             Some(ip) => ip,
@@ -460,7 +1024,7 @@ impl RequestLogger {
             .to_string();
         // Path::extension reports "4" for a directory like `vanilla/1.21.4`.
         let extension = match kind {
-            FileRequestKind::Index => String::new(),
+            FileRequestKind::Index | FileRequestKind::Archive => String::new(),
             _ => path
                 .extension()
                 .and_then(|e| e.to_str())
@@ -503,160 +1067,372 @@ impl RequestLogger {
         };
 
         let id = data.id.clone();
-        self.pending_files.lock().await.push(data);
+        self.send(RequestEvent::StartFile(data));
 
         id
     }
 
-    pub async fn finish_file(&self, id: String, status: i16, time: i32, bytes_sent: i64) {
-        let mut pending = self.pending_files.lock().await;
+    pub fn finish_file(&self, id: String, status: i16, time: i32, bytes_sent: i64) {
+        self.send(RequestEvent::FinishFile {
+            id,
+            status,
+            time,
+            bytes_sent,
+        });
+    }
 
-        if let Some(index) = pending.iter().position(|r| r.id == id) {
-            let mut request = pending.remove(index);
+    /// Continent code of `ip` for routing decisions, resolved through the same lookup as
+    /// the analytics and cached for a day under its `IP_ANONYMIZATION` form, so truncated
+    /// addresses share the continent of their network. Private and loopback addresses
+    /// resolve to `None`.
+    pub async fn continent(&self, ip: std::net::IpAddr) -> Option<compact_str::CompactString> {
+        if !crate::geoip::is_public(&ip) {
+            return None;
+        }
 
-            request.status = status;
-            request.time = time;
-            request.bytes_sent = bytes_sent;
+        let key = crate::utils::anonymize_ip(ip, &self.ip_anonymization);
 
-            self.processing_files.lock().await.push(request);
-        }
+        self.cache
+            .cached(&format!("ip::{key}::continent"), 86400, || async {
+                self.geoip
+                    .resolve(&[ip])
+                    .await
+                    .map(|mut ips| ips.remove(&ip).map(|[continent, _]| continent))
+            })
+            .await
+            .ok()
+            .flatten()
     }
 
-    #[inline]
-    async fn lookup_ips(
-        &self,
-        ips: Vec<compact_str::CompactString>,
-    ) -> Result<HashMap<compact_str::CompactString, [compact_str::CompactString; 2]>, reqwest::Error>
-    {
-        let mut result = HashMap::new();
+    /// Pairs request starts with their finishes and appends finished rows to the
+    /// queues. Requests that never finish are forgotten after a minute, file requests
+    /// after an hour. Runs until the logger is dropped.
+    pub async fn collect(&self) {
+        let Some(mut receiver) = self.receiver.lock().await.take() else {
+            return;
+        };
 
-        let data = self
-            .client
-            .post("http://ip-api.com/batch")
-            .header("Content-Type", "application/json")
-            .json(
-                &ips.into_iter()
-                    .map(|ip| {
-                        serde_json::json!({
-                            "query": ip,
-                            "fields": "continentCode,countryCode,query"
-                        })
-                    })
-                    .collect::<HashSet<_>>(),
+        let mut pending: HashMap<String, Request> = HashMap::new();
+        let mut pending_files: HashMap<String, FileRequest> = HashMap::new();
+        let mut events = Vec::with_capacity(EVENTS_BATCH);
+        let mut last_expiry = Instant::now();
+
+        while receiver.recv_many(&mut events, EVENTS_BATCH).await > 0 {
+            let mut requests = Vec::new();
+            let mut file_requests = Vec::new();
+
+            for event in events.drain(..) {
+                match event {
+                    RequestEvent::Start(request) => {
+                        pending.insert(request.id.clone(), request);
+                    }
+                    RequestEvent::Finish {
+                        id,
+                        status,
+                        time,
+                        data,
+                        body,
+                    } => {
+                        if let Some(mut request) = pending.remove(&id) {
+                            request.end = true;
+                            request.status = status;
+                            request.time = time;
+                            request.data = data;
+                            request.body = body;
+
+                            requests.push(ClickhouseRequest::from(request));
+                        }
+                    }
+                    RequestEvent::StartFile(request) => {
+                        pending_files.insert(request.id.clone(), request);
+                    }
+                    RequestEvent::FinishFile {
+                        id,
+                        status,
+                        time,
+                        bytes_sent,
+                    } => {
+                        if let Some(mut request) = pending_files.remove(&id) {
+                            request.status = status;
+                            request.time = time;
+                            request.bytes_sent = bytes_sent;
+
+                            file_requests.push(ClickhouseFileRequest::from(request));
+                        }
+                    }
+                }
+            }
+
+            self.enqueue(
+                REQUESTS_QUEUE,
+                &self.unqueued_requests,
+                serialize_rows(REQUESTS_QUEUE, &requests),
             )
-            .send()
-            .await?
-            .json::<Vec<IpApiResponse>>()
-            .await?;
+            .await;
+            self.enqueue(
+                FILE_REQUESTS_QUEUE,
+                &self.unqueued_file_requests,
+                serialize_rows(FILE_REQUESTS_QUEUE, &file_requests),
+            )
+            .await;
 
-        #[derive(Deserialize)]
-        #[serde(rename_all = "camelCase")]
-        struct IpApiResponse {
-            continent_code: compact_str::CompactString,
-            country_code: compact_str::CompactString,
-            query: compact_str::CompactString,
-        }
+            if last_expiry.elapsed() >= Duration::from_secs(60) {
+                let now = chrono::Utc::now().naive_utc();
 
-        for entry in data {
-            result.insert(entry.query, [entry.continent_code, entry.country_code]);
+                pending.retain(|_, r| r.created > now - chrono::Duration::seconds(60));
+                pending_files.retain(|_, r| r.created > now - chrono::Duration::seconds(3600));
+                last_expiry = Instant::now();
+            }
         }
-
-        Ok(result)
     }
 
-    pub async fn process(&self) -> Result<(), anyhow::Error> {
-        let now = chrono::Utc::now().naive_utc();
-
-        let mut processing = self.processing.lock().await;
-        let length = processing.len();
+    fn inserter<T: clickhouse::Row>(&self, queue: &'static str, table: &str) -> QueueInserter<T> {
+        QueueInserter {
+            queue,
+            inserter: self
+                .clickhouse
+                .client()
+                .inserter::<T>(table)
+                .with_max_rows(self.flush_rows)
+                .with_max_bytes(self.flush_bytes)
+                .with_period(Some(self.flush_interval)),
+            ids: Vec::new(),
+            buffered: 0,
+        }
+    }
 
-        self.pending
-            .lock()
-            .await
-            .retain(|r| r.created > now - chrono::Duration::seconds(60));
+    /// Ends the current insert of `inserter` when one of its thresholds is reached and
+    /// acknowledges the entries it contained.
+    async fn commit<T: clickhouse::Row>(
+        &self,
+        inserter: &mut QueueInserter<T>,
+    ) -> Result<(), anyhow::Error> {
+        let quantities = inserter.inserter.commit().await?;
+
+        if quantities.rows > 0 {
+            tracing::info!(
+                "inserted {} rows ({} bytes) from {}",
+                quantities.rows,
+                quantities.bytes,
+                inserter.queue
+            );
+
+            inserter.buffered = inserter.buffered.saturating_sub(quantities.rows);
+        }
 
-        let mut requests = processing
-            .splice(0..std::cmp::min(30, length), Vec::new())
-            .collect::<Vec<_>>();
-        processing.retain(|r| r.created > now - chrono::Duration::seconds(300));
+        if inserter.buffered == 0 {
+            self.acknowledge(inserter.queue, std::mem::take(&mut inserter.ids))
+                .await?;
+        }
 
-        drop(processing);
+        Ok(())
+    }
 
-        let mut processing_files = self.processing_files.lock().await;
-        let files_length = processing_files.len();
+    /// Reads both queues into long-lived inserters that flush on row count, size and
+    /// time. Entries are acknowledged only after the insert containing them has ended;
+    /// when this fails the inserters are dropped and whatever they buffered is claimed
+    /// again once [`QUEUE_CLAIM_IDLE_MS`] has passed.
+    pub async fn insert(&self) -> Result<(), anyhow::Error> {
+        let mut requests_inserter = self.inserter::<ClickhouseRequest>(REQUESTS_QUEUE, "requests");
+        let mut file_requests_inserter =
+            self.inserter::<ClickhouseFileRequest>(FILE_REQUESTS_QUEUE, "file_requests");
+
+        loop {
+            let (mut requests, mut file_requests) = tokio::try_join!(
+                self.consume::<ClickhouseRequest>(REQUESTS_QUEUE),
+                self.consume::<ClickhouseFileRequest>(FILE_REQUESTS_QUEUE),
+            )?;
+
+            let idle =
+                requests.ids.len() < self.batch_size && file_requests.ids.len() < self.batch_size;
+
+            self.locate(&mut requests.rows, &mut file_requests.rows)
+                .await;
+            self.anonymize(&mut requests.rows, &mut file_requests.rows);
 
-        self.pending_files
-            .lock()
-            .await
-            .retain(|r| r.created > now - chrono::Duration::seconds(3600));
+            for r in requests.rows.iter() {
+                requests_inserter.inserter.write(r).await?;
+            }
+            requests_inserter.buffered += requests.rows.len() as u64;
+            requests_inserter.ids.extend(requests.ids);
 
-        let mut file_requests = processing_files
-            .splice(0..std::cmp::min(30, files_length), Vec::new())
-            .collect::<Vec<_>>();
-        processing_files.retain(|r| r.created > now - chrono::Duration::seconds(3600));
+            for r in file_requests.rows.iter() {
+                file_requests_inserter.inserter.write(r).await?;
+            }
+            file_requests_inserter.buffered += file_requests.rows.len() as u64;
+            file_requests_inserter.ids.extend(file_requests.ids);
 
-        drop(processing_files);
+            self.commit(&mut requests_inserter).await?;
+            self.commit(&mut file_requests_inserter).await?;
 
-        if requests.is_empty() && file_requests.is_empty() {
-            return Ok(());
+            if idle {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
         }
+    }
 
-        let ips = self
-            .lookup_ips(
-                requests
+    /// Fills in the location of every row that has none with a single lookup.
+    async fn locate(
+        &self,
+        requests: &mut [ClickhouseRequest],
+        file_requests: &mut [ClickhouseFileRequest],
+    ) {
+        let ips = requests
+            .iter()
+            .filter(|r| r.continent.is_none())
+            .map(|r| r.ip.to_canonical())
+            .chain(
+                file_requests
                     .iter()
-                    .map(|t| t.ip.to_compact_string())
-                    .chain(file_requests.iter().map(|t| t.ip.to_compact_string()))
-                    .collect::<Vec<_>>(),
+                    .filter(|r| r.continent.is_none())
+                    .map(|r| r.ip.to_canonical()),
             )
-            .await
-            .unwrap_or_default();
+            .filter(crate::geoip::is_public)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
 
-        for r in requests.iter_mut() {
-            if let Some([continent, country]) = ips.get(&r.ip.to_compact_string()) {
-                r.continent = Some(continent.clone());
-                r.country = Some(country.clone());
+        if ips.is_empty() {
+            return;
+        }
+
+        let locations = match self.geoip.resolve(&ips).await {
+            Ok(locations) => locations,
+            Err(err) => {
+                tracing::error!("failed to resolve {} ips: {:?}", ips.len(), err);
+                return;
+            }
+        };
+
+        let location = |ip: &Ipv6Addr| -> Option<([u8; 2], [u8; 2])> {
+            let [continent, country] = locations.get(&ip.to_canonical())?;
+
+            Some((
+                continent.as_bytes().try_into().ok()?,
+                country.as_bytes().try_into().ok()?,
+            ))
+        };
+
+        for r in requests.iter_mut().filter(|r| r.continent.is_none()) {
+            if let Some((continent, country)) = location(&r.ip) {
+                r.continent = Some(continent);
+                r.country = Some(country);
             }
         }
 
-        for r in file_requests.iter_mut() {
-            if let Some([continent, country]) = ips.get(&r.ip.to_compact_string()) {
-                r.continent = Some(continent.clone());
-                r.country = Some(country.clone());
+        for r in file_requests.iter_mut().filter(|r| r.continent.is_none()) {
+            if let Some((continent, country)) = location(&r.ip) {
+                r.continent = Some(continent);
+                r.country = Some(country);
             }
         }
+    }
 
-        let requests_len = requests.len();
-        let file_requests_len = file_requests.len();
+    /// Applies `IP_ANONYMIZATION` to every row. Queued rows keep the full address until
+    /// here so their location can still be resolved.
+    fn anonymize(
+        &self,
+        requests: &mut [ClickhouseRequest],
+        file_requests: &mut [ClickhouseFileRequest],
+    ) {
+        if matches!(self.ip_anonymization, crate::env::IpAnonymization::None) {
+            return;
+        }
 
-        if !requests.is_empty() {
-            let mut insert = self
-                .clickhouse
-                .client()
-                .insert::<ClickhouseRequest>("requests")
-                .await?;
-            for r in requests {
-                let ch_request: ClickhouseRequest = r.into();
+        let anonymize = |ip: &mut Ipv6Addr| {
+            *ip = match crate::utils::anonymize_ip(IpAddr::V6(*ip), &self.ip_anonymization) {
+                IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                IpAddr::V6(ip) => ip,
+            };
+        };
 
-                insert.write(&ch_request).await?;
-            }
-            insert.end().await?;
+        for r in requests.iter_mut() {
+            anonymize(&mut r.ip);
         }
 
-        if !file_requests.is_empty() {
-            let mut insert = self
-                .clickhouse
-                .client()
-                .insert::<ClickhouseFileRequest>("file_requests")
-                .await?;
-            for r in file_requests {
-                let ch_request: ClickhouseFileRequest = r.into();
+        for r in file_requests.iter_mut() {
+            anonymize(&mut r.ip);
+        }
+    }
 
-                insert.write(&ch_request).await?;
-            }
-            insert.end().await?;
+    /// The request `id` made by `organization_id`. Requests become visible once their
+    /// insert has been flushed, so only after `ANALYTICS_FLUSH_INTERVAL`. Anything that
+    /// is not a 12 character `X-Request-ID` is never found.
+    pub async fn trace(
+        &self,
+        organization_id: i32,
+        id: &str,
+    ) -> Result<Option<RequestTrace>, anyhow::Error> {
+        if id.len() != 12 || !id.bytes().all(|b| b.is_ascii_alphanumeric()) {
+            return Ok(None);
         }
 
+        let row = self
+            .clickhouse
+            .client()
+            .query(&format!(
+                r#"
+                SELECT {REQUEST_TRACE_COLUMNS}
+                FROM requests
+                WHERE requests.organization_id = ? AND requests.id = ?
+                LIMIT 1
+                "#
+            ))
+            .bind(organization_id)
+            .bind(id)
+            .fetch_optional::<RequestTraceRow>()
+            .await?;
+
+        Ok(row.map(RequestTrace::from))
+    }
+
+    /// The most recent requests of `organization_id` that failed with a status of at
+    /// least 400 within the last `hours`, optionally narrowed to one `status` and to paths
+    /// starting with `path`.
+    pub async fn failed_requests(
+        &self,
+        organization_id: i32,
+        params: &FailedRequestsParams,
+    ) -> Result<Vec<RequestTrace>, anyhow::Error> {
+        let status = params.status;
+        let rows = self
+            .clickhouse
+            .client()
+            .query(&format!(
+                r#"
+                SELECT {REQUEST_TRACE_COLUMNS}
+                FROM requests
+                WHERE requests.organization_id = ?
+                    AND requests.created > NOW() - INTERVAL ? HOUR
+                    AND requests.status >= 400
+                    AND (? = 0 OR requests.status = ?)
+                    AND startsWith(requests.path, ?)
+                ORDER BY requests.created DESC
+                LIMIT ?
+                "#
+            ))
+            .bind(organization_id)
+            .bind(params.hours.clamp(1, 720))
+            .bind(status.unwrap_or_default())
+            .bind(status.unwrap_or_default())
+            .bind(&params.path)
+            .bind(params.limit.clamp(1, 100))
+            .fetch_all::<RequestTraceRow>()
+            .await?;
+
+        Ok(rows.into_iter().map(RequestTrace::from).collect())
+    }
+
+    /// Retries rows that could not be queued, updates the request counter and warns
+    /// when a queue approaches its maximum length.
+    pub async fn process(&self) -> Result<(), anyhow::Error> {
+        let unqueued = std::mem::take(&mut *self.unqueued_requests.lock().await);
+        self.enqueue(REQUESTS_QUEUE, &self.unqueued_requests, unqueued)
+            .await;
+        let unqueued = std::mem::take(&mut *self.unqueued_file_requests.lock().await);
+        self.enqueue(FILE_REQUESTS_QUEUE, &self.unqueued_file_requests, unqueued)
+            .await;
+
         let mut uncounted_requests = self.uncounted_requests.lock().await;
         if *uncounted_requests > 0 {
             let count = *uncounted_requests;
@@ -668,16 +1444,46 @@ impl RequestLogger {
             }
         }
 
-        tracing::info!(
-            "processed {} requests, {} file requests",
-            requests_len,
-            file_requests_len
-        );
+        let stats = self.stats().await?;
+        for (queue, stats) in [
+            (REQUESTS_QUEUE, &stats.requests),
+            (FILE_REQUESTS_QUEUE, &stats.file_requests),
+        ] {
+            if self.max_length > 0 && stats.length as u64 > self.max_length / 4 * 3 {
+                tracing::warn!(
+                    "{queue} holds {} of at most {} entries ({} pending, oldest {}s), older entries will be trimmed",
+                    stats.length,
+                    self.max_length,
+                    stats.pending,
+                    stats.oldest_age
+                );
+            }
+        }
+
+        if stats.events > EVENTS_CAPACITY / 4 * 3 {
+            tracing::warn!(
+                "{} of at most {} request events are waiting for the collector",
+                stats.events,
+                EVENTS_CAPACITY
+            );
+        }
 
         Ok(())
     }
 }
 
+fn serialize_rows<T: Serialize>(queue: &str, rows: &[T]) -> Vec<String> {
+    rows.iter()
+        .filter_map(|row| match serde_json::to_string(row) {
+            Ok(row) => Some(row),
+            Err(err) => {
+                tracing::error!("failed to serialize row for {queue}: {:?}", err);
+                None
+            }
+        })
+        .collect()
+}
+
 /// Counts the bytes of a file download that actually reach the client and finalises
 /// the request on drop, so an aborted download is distinguishable from a completed one.
 pub struct TrackedFileStream<S> {
@@ -725,16 +1531,157 @@ impl<S> Drop for TrackedFileStream<S> {
             return;
         };
 
-        let state = self.state.clone();
-        let status = self.status;
-        let bytes_sent = self.bytes_sent as i64;
-        let time = self.started.elapsed().as_millis() as i32;
+        self.state.requests.finish_file(
+            id,
+            self.status,
+            self.started.elapsed().as_millis() as i32,
+            self.bytes_sent as i64,
+        );
+    }
+}
+
+#[derive(Default)]
+struct BodyBuffer {
+    /// Set by [`capture_body`], `None` while the route has not opted in.
+    redact: Option<&'static [&'static str]>,
+    content_type: Option<mime::Mime>,
+    max_size: usize,
+    bytes: Vec<u8>,
+    size: usize,
+    hasher: sha2::Sha256,
+}
 
-        tokio::spawn(async move {
-            state
-                .requests
-                .finish_file(id, status, time, bytes_sent)
-                .await;
+/// Request body of a route that opted into capturing it through [`capture_body`], shared
+/// between the route layer copying it and the request logger storing it.
+#[derive(Clone)]
+pub struct CapturedBody(Arc<std::sync::Mutex<BodyBuffer>>);
+
+impl CapturedBody {
+    pub fn new(max_size: usize) -> Self {
+        Self(Arc::new(std::sync::Mutex::new(BodyBuffer {
+            max_size,
+            ..Default::default()
+        })))
+    }
+
+    fn push(&self, chunk: &[u8]) {
+        let mut buffer = self.0.lock().unwrap();
+
+        let remaining = buffer.max_size.saturating_sub(buffer.bytes.len());
+        buffer
+            .bytes
+            .extend_from_slice(&chunk[..chunk.len().min(remaining)]);
+        buffer.size += chunk.len();
+        buffer.hasher.update(chunk);
+    }
+
+    /// The stored form of the body, `None` when the route did not opt in or received no
+    /// body. Always has the `size` and `sha256` of the whole body, `content` holds the
+    /// redacted body itself when `mode` is full and it fits in `REQUEST_BODY_MAX_SIZE_KB`.
+    pub fn finish(&self, mode: crate::env::RequestBodyCapture) -> Option<serde_json::Value> {
+        let buffer = std::mem::take(&mut *self.0.lock().unwrap());
+        let redact = buffer.redact?;
+
+        if buffer.size == 0 || mode == crate::env::RequestBodyCapture::None {
+            return None;
+        }
+
+        let mut body = serde_json::json!({
+            "size": buffer.size,
+            "sha256": hex::encode(buffer.hasher.finalize()),
         });
+
+        if mode == crate::env::RequestBodyCapture::Full {
+            if buffer.size > buffer.max_size {
+                body["truncated"] = serde_json::Value::Bool(true);
+            } else if let Some(mut content) = match buffer.content_type {
+                Some(content_type)
+                    if content_type.essence_str() == mime::APPLICATION_JSON.essence_str() =>
+                {
+                    serde_json::from_slice::<serde_json::Value>(&buffer.bytes).ok()
+                }
+                Some(content_type)
+                    if content_type.essence_str() == mime::APPLICATION_MSGPACK.essence_str() =>
+                {
+                    rmp_serde::from_slice::<serde_json::Value>(&buffer.bytes).ok()
+                }
+                _ => None,
+            } {
+                redact_body(&mut content, redact);
+                body["content"] = content;
+            }
+        }
+
+        Some(body)
     }
 }
+
+/// Replaces the value of every key in `redact`, at any depth, with its size and hash.
+fn redact_body(value: &mut serde_json::Value, redact: &[&str]) {
+    match value {
+        serde_json::Value::Object(object) => {
+            for (key, value) in object.iter_mut() {
+                if !redact.contains(&key.as_str()) {
+                    redact_body(value, redact);
+                    continue;
+                }
+
+                let bytes = match value {
+                    serde_json::Value::String(value) => value.as_bytes().to_vec(),
+                    value => value.to_string().into_bytes(),
+                };
+
+                *value = serde_json::json!({
+                    "size": bytes.len(),
+                    "sha256": hex::encode(sha2::Sha256::digest(&bytes)),
+                });
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for value in values.iter_mut() {
+                redact_body(value, redact);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Options of [`capture_body`] for one route.
+#[derive(Clone, Copy, Default)]
+pub struct CaptureBody {
+    /// Keys whose values are stored as their size and hash only, at any depth.
+    pub redact: &'static [&'static str],
+}
+
+/// Route layer that opts a route into storing its request body with the request. The
+/// body is copied while the handler reads it, so it is never buffered twice and the
+/// handler's limits still apply.
+pub async fn capture_body(
+    axum::extract::State(options): axum::extract::State<CaptureBody>,
+    req: axum::extract::Request,
+    next: axum::middleware::Next,
+) -> axum::response::Response {
+    let Some(captured) = req.extensions().get::<CapturedBody>().cloned() else {
+        return next.run(req).await;
+    };
+
+    {
+        let mut buffer = captured.0.lock().unwrap();
+        buffer.redact = Some(options.redact);
+        buffer.content_type = req
+            .headers()
+            .get(axum::http::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+    }
+
+    let (parts, body) = req.into_parts();
+    let body = axum::body::Body::from_stream(body.into_data_stream().inspect(move |chunk| {
+        if let Ok(chunk) = chunk {
+            captured.push(chunk);
+        }
+    }));
+
+    next.run(axum::extract::Request::from_parts(parts, body))
+        .await
+}
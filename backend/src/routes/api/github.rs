@@ -152,7 +152,10 @@ pub fn router(state: &State) -> OpenApiRouter<State> {
                 let (_, key) = UserSession::new(
                     &state.database,
                     user.id,
-                    crate::utils::extract_ip(&headers).unwrap_or([127, 0, 0, 1].into()).into(),
+                    crate::utils::anonymize_ip(
+                        crate::utils::extract_ip(&headers).unwrap_or([127, 0, 0, 1].into()),
+                        &state.env.ip_anonymization,
+                    ).into(),
                     headers
                         .get("User-Agent")
                         .map(|ua| crate::utils::slice_up_to(ua.to_str().unwrap_or("unknown"), 255))
@@ -1,6 +1,8 @@
 use crate::routes::State;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
+mod requests;
+mod sign_download;
 mod types;
 
 mod get {
@@ -8,6 +10,7 @@ mod get {
         models::r#type::ServerType,
         response::{ApiResponse, ApiResponseResult},
         routes::{GetState, api::organization::GetOrganization},
+        user_agent::ClientStats,
     };
     use serde::{Deserialize, Serialize};
     use utoipa::ToSchema;
@@ -26,6 +29,7 @@ mod get {
         requests: u64,
         user_agents: Vec<String>,
         origins: Vec<String>,
+        clients: Vec<ClientStats>,
     }
 
     #[derive(ToSchema, Serialize)]
@@ -46,11 +50,11 @@ mod get {
 
         let stats = state
             .cache
-            .cached(
+            .cached_with(
                 &format!("organization::{}::stats", organization.id),
-                300,
+                crate::cache::CacheOptions::new(300).tag(format!("org:{}", organization.id)),
                 || async {
-                    let (requests, user_agents, origins) = tokio::try_join!(
+                    let (requests, user_agents, origins, clients) = tokio::try_join!(
                         state
                             .clickhouse
                             .client()
@@ -89,12 +93,30 @@ mod get {
                             )
                             .bind(organization.id)
                             .fetch_all::<String>(),
+                        state
+                            .clickhouse
+                            .client()
+                            .query(
+                                r#"
+                                SELECT requests.client_category, requests.client_family, COUNT(*) AS total
+                                FROM requests
+                                WHERE requests.organization_id = ?
+                                GROUP BY requests.client_category, requests.client_family
+                                ORDER BY total DESC
+                                "#,
+                            )
+                            .bind(organization.id)
+                            .fetch_all::<(i8, String, u64)>(),
                     )?;
 
                     Ok::<_, anyhow::Error>(Stats {
                         requests,
                         user_agents,
                         origins,
+                        clients: clients
+                            .into_iter()
+                            .map(ClientStats::from)
+                            .collect(),
                     })
                 },
             )
@@ -117,5 +139,7 @@ pub fn router(state: &State) -> OpenApiRouter<State> {
     OpenApiRouter::new()
         .routes(routes!(get::route))
         .nest("/types", types::router(state))
+        .nest("/sign-download", sign_download::router(state))
+        .nest("/requests", requests::router(state))
         .with_state(state.clone())
 }
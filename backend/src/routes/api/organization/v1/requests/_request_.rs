@@ -0,0 +1,54 @@
+use crate::routes::State;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+mod get {
+    use crate::{
+        requests::RequestTrace,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiError, GetState, api::organization::GetOrganization},
+    };
+    use axum::{extract::Path, http::StatusCode};
+    use serde::Serialize;
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+        request: RequestTrace,
+    }
+
+    #[utoipa::path(get, path = "/", responses(
+        (status = OK, body = inline(Response)),
+        (status = NOT_FOUND, body = inline(ApiError)),
+    ), params(
+        (
+            "request" = String,
+            description = "The X-Request-ID of the request",
+            example = "Jd8eKq2Lm0Zx",
+        ),
+    ))]
+    pub async fn route(
+        state: GetState,
+        organization: GetOrganization,
+        Path(request): Path<String>,
+    ) -> ApiResponseResult {
+        let organization = organization.as_ref().unwrap();
+
+        match state.requests.trace(organization.id, &request).await? {
+            Some(request) => ApiResponse::new_serialized(Response {
+                success: true,
+                request,
+            })
+            .ok(),
+            None => ApiResponse::error("request not found")
+                .with_status(StatusCode::NOT_FOUND)
+                .ok(),
+        }
+    }
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .routes(routes!(get::route))
+        .with_state(state.clone())
+}
@@ -0,0 +1,72 @@
+use crate::routes::State;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+mod get {
+    use crate::{
+        requests::{FailedRequestsParams, RequestTrace},
+        response::{ApiResponse, ApiResponseResult},
+        routes::{GetState, api::organization::GetOrganization},
+    };
+    use axum::extract::Query;
+    use serde::Serialize;
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+        requests: Vec<RequestTrace>,
+    }
+
+    #[utoipa::path(get, path = "/", responses(
+        (status = OK, body = inline(Response)),
+    ), params(
+        (
+            "status" = Option<i16>,
+            Query,
+            description = "Only include requests that failed with this status",
+            example = 404,
+        ),
+        (
+            "path" = Option<String>,
+            Query,
+            description = "Only include requests to paths starting with this",
+            example = "/api/v2/build",
+        ),
+        (
+            "hours" = Option<u32>,
+            Query,
+            description = "How many hours to look back, at most 720",
+            example = 24,
+        ),
+        (
+            "limit" = Option<u32>,
+            Query,
+            description = "The maximum number of requests to return, at most 100",
+            example = 50,
+        ),
+    ))]
+    pub async fn route(
+        state: GetState,
+        organization: GetOrganization,
+        Query(params): Query<FailedRequestsParams>,
+    ) -> ApiResponseResult {
+        let organization = organization.as_ref().unwrap();
+
+        let requests = state
+            .requests
+            .failed_requests(organization.id, &params)
+            .await?;
+
+        ApiResponse::new_serialized(Response {
+            success: true,
+            requests,
+        })
+        .ok()
+    }
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .routes(routes!(get::route))
+        .with_state(state.clone())
+}
@@ -0,0 +1,91 @@
+use crate::routes::State;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+mod post {
+    use crate::{
+        models::file::File,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiError, GetState, api::organization::GetOrganization, files::signed},
+    };
+    use axum::http::StatusCode;
+    use serde::{Deserialize, Serialize};
+    use std::{net::IpAddr, path::Path};
+    use utoipa::ToSchema;
+
+    fn default_expires_in() -> i64 {
+        3600
+    }
+
+    #[derive(ToSchema, Deserialize)]
+    pub struct Payload {
+        path: String,
+        #[serde(default = "default_expires_in")]
+        expires_in: i64,
+        #[schema(value_type = Option<String>)]
+        ip: Option<IpAddr>,
+    }
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+
+        url: String,
+        expires: i64,
+    }
+
+    #[utoipa::path(post, path = "/", responses(
+        (status = OK, body = inline(Response)),
+        (status = BAD_REQUEST, body = inline(ApiError)),
+        (status = NOT_FOUND, body = inline(ApiError)),
+    ), request_body = inline(Payload))]
+    pub async fn route(
+        state: GetState,
+        organization: GetOrganization,
+        crate::Payload(data): crate::Payload<Payload>,
+    ) -> ApiResponseResult {
+        let organization = organization.as_ref().unwrap();
+
+        if !(60..=60 * 60 * 24 * 7).contains(&data.expires_in) {
+            return ApiResponse::error("expires_in must be between 60 and 604800 seconds")
+                .with_status(StatusCode::BAD_REQUEST)
+                .ok();
+        }
+
+        let path = data.path.trim_matches('/');
+        if path.split('/').any(|c| c == "..")
+            || File::by_path(&state.database, &state.cache, Path::new(path))
+                .await?
+                .is_none()
+        {
+            return ApiResponse::error("file not found")
+                .with_status(StatusCode::NOT_FOUND)
+                .ok();
+        }
+
+        let path = format!("/files/{path}");
+        let expires = chrono::Utc::now().timestamp() + data.expires_in;
+
+        let Some(query) = signed::sign(&state.env, organization.id, &path, expires, data.ip) else {
+            return ApiResponse::error("signed downloads are not configured")
+                .with_status(StatusCode::BAD_REQUEST)
+                .ok();
+        };
+
+        ApiResponse::new_serialized(Response {
+            success: true,
+            url: format!(
+                "{}{}?{query}",
+                state.env.app_url,
+                signed::encode_path(&path)
+            ),
+            expires,
+        })
+        .ok()
+    }
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .routes(routes!(post::route))
+        .with_state(state.clone())
+}
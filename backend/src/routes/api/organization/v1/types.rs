@@ -42,7 +42,6 @@ mod patch {
         response::{ApiResponse, ApiResponseResult},
         routes::{GetState, api::organization::GetOrganization},
     };
-    use rustis::commands::GenericCommands;
     use serde::{Deserialize, Serialize};
     use utoipa::ToSchema;
 
@@ -69,14 +68,7 @@ mod patch {
         organization.types = data.types;
         organization.save(&state.database).await?;
 
-        let keys: Vec<String> = state
-            .cache
-            .client
-            .keys(format!("organization::{}*", organization.id))
-            .await?;
-        if !keys.is_empty() {
-            state.cache.client.del(keys).await?;
-        }
+        state.cache.clear_organization(organization.id).await?;
 
         ApiResponse::new_serialized(Response { success: true }).ok()
     }
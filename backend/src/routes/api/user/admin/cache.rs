@@ -0,0 +1,51 @@
+use super::State;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+mod delete {
+    use crate::{
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiError, GetState},
+    };
+    use axum::http::StatusCode;
+    use serde::{Deserialize, Serialize};
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Deserialize)]
+    pub struct Payload {
+        /// Tags such as `type:PAPER`, `build:1` or `file:<hash>` (one per hash of a
+        /// changed file row), for catalog updates made outside the api.
+        tags: Vec<String>,
+    }
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+    }
+
+    #[utoipa::path(delete, path = "/", responses(
+        (status = OK, body = inline(Response)),
+        (status = BAD_REQUEST, body = inline(ApiError)),
+    ), request_body = inline(Payload))]
+    pub async fn route(
+        state: GetState,
+        crate::Payload(data): crate::Payload<Payload>,
+    ) -> ApiResponseResult {
+        if data.tags.is_empty() || data.tags.len() > 100 {
+            return ApiResponse::error("between 1 and 100 tags are required")
+                .with_status(StatusCode::BAD_REQUEST)
+                .ok();
+        }
+
+        for tag in &data.tags {
+            state.cache.invalidate_tag(tag).await?;
+        }
+
+        ApiResponse::new_serialized(Response { success: true }).ok()
+    }
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .routes(routes!(delete::route))
+        .with_state(state.clone())
+}
@@ -0,0 +1,119 @@
+use super::State;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+mod post {
+    use crate::{
+        env::IpAnonymization,
+        models::user::UserSession,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiError, GetState},
+    };
+    use axum::http::StatusCode;
+    use serde::{Deserialize, Serialize};
+    use std::net::IpAddr;
+    use utoipa::ToSchema;
+
+    /// With `IP_ANONYMIZATION=truncate` stored addresses are shared by a whole network,
+    /// so analytics rows cannot be attributed to one person. Only the sessions of
+    /// `user_id` and the records of exactly `ip` are erased in that mode.
+    #[derive(ToSchema, Deserialize)]
+    pub struct Payload {
+        #[schema(value_type = Option<String>, example = "203.0.113.7")]
+        ip: Option<IpAddr>,
+        /// Also covers the last known address of every session of the user.
+        user_id: Option<i32>,
+
+        /// Keep the records but clear their address instead of deleting them.
+        #[serde(default)]
+        anonymize: bool,
+    }
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+
+        ips: Vec<String>,
+        sessions: u64,
+    }
+
+    #[utoipa::path(post, path = "/", responses(
+        (status = OK, body = inline(Response)),
+        (status = BAD_REQUEST, body = inline(ApiError)),
+    ), request_body = inline(Payload))]
+    pub async fn route(
+        state: GetState,
+        crate::Payload(data): crate::Payload<Payload>,
+    ) -> ApiResponseResult {
+        if data.ip.is_none() && data.user_id.is_none() {
+            return ApiResponse::error("ip or user_id is required")
+                .with_status(StatusCode::BAD_REQUEST)
+                .ok();
+        }
+
+        let mut ips = Vec::new();
+        if let Some(ip) = data.ip {
+            ips.push(ip.to_canonical());
+        }
+        let truncated = matches!(state.env.ip_anonymization, IpAnonymization::Truncate { .. });
+        if let Some(user_id) = data.user_id
+            && !truncated
+        {
+            ips.extend(
+                UserSession::ips_by_user(&state.database, user_id)
+                    .await?
+                    .into_iter()
+                    .map(|ip| ip.ip().to_canonical()),
+            );
+        }
+
+        // hashed addresses are unique per address, so their stored form can be
+        // matched as well, a truncated one would cover the whole network
+        let mut ips = ips
+            .into_iter()
+            .flat_map(|ip| match &state.env.ip_anonymization {
+                IpAnonymization::Hash { .. } => vec![
+                    ip,
+                    crate::utils::anonymize_ip(ip, &state.env.ip_anonymization),
+                ],
+                _ => vec![ip],
+            })
+            .filter(|ip| !ip.is_unspecified())
+            .collect::<Vec<_>>();
+        ips.sort();
+        ips.dedup();
+
+        let sessions = UserSession::erase(
+            &state.database,
+            data.user_id,
+            &ips.iter().map(|ip| (*ip).into()).collect::<Vec<_>>(),
+            data.anonymize,
+        )
+        .await?;
+
+        state
+            .clickhouse
+            .erase_ips(
+                &ips.iter()
+                    .map(|ip| match ip {
+                        IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+                        IpAddr::V6(ip) => *ip,
+                    })
+                    .collect::<Vec<_>>(),
+                data.anonymize,
+            )
+            .await?;
+
+        ApiResponse::new_serialized(Response {
+            success: true,
+            ips: ips.iter().map(|ip| ip.to_string()).collect(),
+            sessions,
+        })
+        .ok()
+    }
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .routes(routes!(post::route))
+        .with_state(state.clone())
+}
@@ -0,0 +1,132 @@
+use super::State;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+mod patch {
+    use crate::{
+        models::mirror::Mirror,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiError, GetState},
+    };
+    use axum::{extract::Path, http::StatusCode};
+    use serde::{Deserialize, Serialize};
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Deserialize)]
+    pub struct Payload {
+        name: Option<String>,
+        url: Option<String>,
+        continent: Option<String>,
+        /// An empty string clears the country.
+        country: Option<String>,
+        enabled: Option<bool>,
+    }
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+        mirror: Mirror,
+    }
+
+    #[utoipa::path(patch, path = "/", responses(
+        (status = OK, body = inline(Response)),
+        (status = BAD_REQUEST, body = inline(ApiError)),
+        (status = NOT_FOUND, body = inline(ApiError)),
+    ), params(
+        (
+            "mirror" = i32,
+            description = "The mirror ID",
+            example = 1,
+        ),
+    ), request_body = inline(Payload))]
+    pub async fn route(
+        state: GetState,
+        Path(mirror): Path<i32>,
+        crate::Payload(data): crate::Payload<Payload>,
+    ) -> ApiResponseResult {
+        let Some(mut mirror) = Mirror::by_id(&state.database, mirror).await? else {
+            return ApiResponse::error("mirror not found")
+                .with_status(StatusCode::NOT_FOUND)
+                .ok();
+        };
+
+        if let Some(name) = data.name {
+            mirror.name = name.into();
+        }
+        if let Some(url) = data.url {
+            mirror.url = url.trim_end_matches('/').into();
+        }
+        if let Some(continent) = data.continent {
+            mirror.continent = continent.into();
+        }
+        if let Some(country) = data.country {
+            mirror.country = (!country.is_empty()).then(|| country.into());
+        }
+        if let Some(enabled) = data.enabled {
+            mirror.enabled = enabled;
+        }
+
+        if let Err(error) = super::super::validate(
+            &mirror.name,
+            &mirror.url,
+            &mirror.continent,
+            mirror.country.as_deref(),
+        ) {
+            return ApiResponse::error(error)
+                .with_status(StatusCode::BAD_REQUEST)
+                .ok();
+        }
+
+        mirror.save(&state.database, &state.cache).await?;
+
+        ApiResponse::new_serialized(Response {
+            success: true,
+            mirror,
+        })
+        .ok()
+    }
+}
+
+mod delete {
+    use crate::{
+        models::mirror::Mirror,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiError, GetState},
+    };
+    use axum::{extract::Path, http::StatusCode};
+    use serde::Serialize;
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+    }
+
+    #[utoipa::path(delete, path = "/", responses(
+        (status = OK, body = inline(Response)),
+        (status = NOT_FOUND, body = inline(ApiError)),
+    ), params(
+        (
+            "mirror" = i32,
+            description = "The mirror ID",
+            example = 1,
+        ),
+    ))]
+    pub async fn route(state: GetState, Path(mirror): Path<i32>) -> ApiResponseResult {
+        let Some(mirror) = Mirror::by_id(&state.database, mirror).await? else {
+            return ApiResponse::error("mirror not found")
+                .with_status(StatusCode::NOT_FOUND)
+                .ok();
+        };
+
+        mirror.delete(&state.database, &state.cache).await?;
+
+        ApiResponse::new_serialized(Response { success: true }).ok()
+    }
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .routes(routes!(patch::route))
+        .routes(routes!(delete::route))
+        .with_state(state.clone())
+}
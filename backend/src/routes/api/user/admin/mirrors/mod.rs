@@ -0,0 +1,134 @@
+use super::State;
+use crate::models::mirror::CONTINENTS;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+mod _mirror_;
+
+mod get {
+    use crate::{
+        models::mirror::Mirror,
+        response::{ApiResponse, ApiResponseResult},
+        routes::GetState,
+    };
+    use serde::Serialize;
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+        mirrors: Vec<Mirror>,
+    }
+
+    #[utoipa::path(get, path = "/", responses(
+        (status = OK, body = inline(Response)),
+    ))]
+    pub async fn route(state: GetState) -> ApiResponseResult {
+        ApiResponse::new_serialized(Response {
+            success: true,
+            mirrors: Mirror::all(&state.database).await?,
+        })
+        .ok()
+    }
+}
+
+mod post {
+    use crate::{
+        models::mirror::Mirror,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiError, GetState},
+    };
+    use axum::http::StatusCode;
+    use serde::{Deserialize, Serialize};
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Deserialize)]
+    pub struct Payload {
+        name: String,
+        url: String,
+        continent: String,
+        country: Option<String>,
+    }
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+        mirror: Mirror,
+    }
+
+    #[utoipa::path(post, path = "/", responses(
+        (status = CREATED, body = inline(Response)),
+        (status = BAD_REQUEST, body = inline(ApiError)),
+        (status = CONFLICT, body = inline(ApiError)),
+    ), request_body = inline(Payload))]
+    pub async fn route(
+        state: GetState,
+        crate::Payload(data): crate::Payload<Payload>,
+    ) -> ApiResponseResult {
+        if let Err(error) = super::validate(
+            &data.name,
+            &data.url,
+            &data.continent,
+            data.country.as_deref(),
+        ) {
+            return ApiResponse::error(error)
+                .with_status(StatusCode::BAD_REQUEST)
+                .ok();
+        }
+
+        match Mirror::new(
+            &state.database,
+            &state.cache,
+            &data.name,
+            &data.url,
+            &data.continent,
+            data.country.as_deref(),
+        )
+        .await?
+        {
+            Some(mirror) => ApiResponse::new_serialized(Response {
+                success: true,
+                mirror,
+            })
+            .with_status(StatusCode::CREATED)
+            .ok(),
+            None => ApiResponse::error("mirror with this url already exists")
+                .with_status(StatusCode::CONFLICT)
+                .ok(),
+        }
+    }
+}
+
+pub(super) fn validate(
+    name: &str,
+    url: &str,
+    continent: &str,
+    country: Option<&str>,
+) -> Result<(), &'static str> {
+    if !(1..=63).contains(&name.len()) {
+        return Err("name must be between 1 and 63 characters");
+    }
+
+    if url.len() > 255 || !(url.starts_with("https://") || url.starts_with("http://")) {
+        return Err("url must be an http(s) url of at most 255 characters");
+    }
+
+    if !CONTINENTS.contains(&continent) {
+        return Err("continent must be one of AF, AN, AS, EU, NA, OC or SA");
+    }
+
+    if country.is_some_and(|country| {
+        country.len() != 2 || !country.chars().all(|c| c.is_ascii_uppercase())
+    }) {
+        return Err("country must be an uppercase ISO 3166-1 alpha-2 code");
+    }
+
+    Ok(())
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .routes(routes!(get::route))
+        .routes(routes!(post::route))
+        .nest("/{mirror}", _mirror_::router(state))
+        .with_state(state.clone())
+}
@@ -2,7 +2,12 @@ use crate::routes::{ApiError, State, api::user::GetUser};
 use axum::{body::Body, extract::Request, http::StatusCode, middleware::Next, response::Response};
 use utoipa_axum::router::OpenApiRouter;
 
+mod cache;
+mod erase;
+mod mirrors;
+mod ratelimits;
 mod stats;
+mod verify_files;
 
 async fn auth(user: GetUser, req: Request, next: Next) -> Result<Response, StatusCode> {
     if !user.admin {
@@ -20,7 +25,12 @@ async fn auth(user: GetUser, req: Request, next: Next) -> Result<Response, Statu
 
 pub fn router(state: &State) -> OpenApiRouter<State> {
     OpenApiRouter::new()
+        .nest("/cache", cache::router(state))
+        .nest("/erase", erase::router(state))
+        .nest("/mirrors", mirrors::router(state))
+        .nest("/ratelimits", ratelimits::router(state))
         .nest("/stats", stats::router(state))
+        .nest("/verify-files", verify_files::router(state))
         .route_layer(axum::middleware::from_fn_with_state(state.clone(), auth))
         .with_state(state.clone())
 }
@@ -0,0 +1,108 @@
+use super::State;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+mod patch {
+    use crate::{
+        models::ratelimit::RateLimitCost,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiError, GetState},
+    };
+    use axum::{extract::Path, http::StatusCode};
+    use serde::{Deserialize, Serialize};
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Deserialize)]
+    pub struct Payload {
+        cost: i32,
+    }
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+        cost: RateLimitCost,
+    }
+
+    #[utoipa::path(patch, path = "/", responses(
+        (status = OK, body = inline(Response)),
+        (status = BAD_REQUEST, body = inline(ApiError)),
+        (status = NOT_FOUND, body = inline(ApiError)),
+    ), params(
+        (
+            "cost" = i32,
+            description = "The rate limit cost ID",
+            example = 1,
+        ),
+    ), request_body = inline(Payload))]
+    pub async fn route(
+        state: GetState,
+        Path(cost): Path<i32>,
+        crate::Payload(data): crate::Payload<Payload>,
+    ) -> ApiResponseResult {
+        let Some(mut cost) = RateLimitCost::by_id(&state.database, cost).await? else {
+            return ApiResponse::error("cost not found")
+                .with_status(StatusCode::NOT_FOUND)
+                .ok();
+        };
+
+        cost.cost = data.cost;
+
+        if let Err(error) = super::super::validate(&cost.route_group, &cost.path, cost.cost) {
+            return ApiResponse::error(error)
+                .with_status(StatusCode::BAD_REQUEST)
+                .ok();
+        }
+
+        cost.save(&state.database, &state.cache).await?;
+
+        ApiResponse::new_serialized(Response {
+            success: true,
+            cost,
+        })
+        .ok()
+    }
+}
+
+mod delete {
+    use crate::{
+        models::ratelimit::RateLimitCost,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiError, GetState},
+    };
+    use axum::{extract::Path, http::StatusCode};
+    use serde::Serialize;
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+    }
+
+    #[utoipa::path(delete, path = "/", responses(
+        (status = OK, body = inline(Response)),
+        (status = NOT_FOUND, body = inline(ApiError)),
+    ), params(
+        (
+            "cost" = i32,
+            description = "The rate limit cost ID",
+            example = 1,
+        ),
+    ))]
+    pub async fn route(state: GetState, Path(cost): Path<i32>) -> ApiResponseResult {
+        let Some(cost) = RateLimitCost::by_id(&state.database, cost).await? else {
+            return ApiResponse::error("cost not found")
+                .with_status(StatusCode::NOT_FOUND)
+                .ok();
+        };
+
+        cost.delete(&state.database, &state.cache).await?;
+
+        ApiResponse::new_serialized(Response { success: true }).ok()
+    }
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .routes(routes!(patch::route))
+        .routes(routes!(delete::route))
+        .with_state(state.clone())
+}
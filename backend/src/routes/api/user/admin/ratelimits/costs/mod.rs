@@ -0,0 +1,117 @@
+use super::State;
+use crate::models::ratelimit::ROUTE_GROUPS;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+mod _cost_;
+
+mod get {
+    use crate::{
+        models::ratelimit::RateLimitCost,
+        response::{ApiResponse, ApiResponseResult},
+        routes::GetState,
+    };
+    use serde::Serialize;
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+        costs: Vec<RateLimitCost>,
+    }
+
+    #[utoipa::path(get, path = "/", responses(
+        (status = OK, body = inline(Response)),
+    ))]
+    pub async fn route(state: GetState) -> ApiResponseResult {
+        ApiResponse::new_serialized(Response {
+            success: true,
+            costs: RateLimitCost::all(&state.database).await?,
+        })
+        .ok()
+    }
+}
+
+mod post {
+    use crate::{
+        models::ratelimit::RateLimitCost,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiError, GetState},
+    };
+    use axum::http::StatusCode;
+    use serde::{Deserialize, Serialize};
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Deserialize)]
+    pub struct Payload {
+        route_group: String,
+        /// Path prefix, for example `/api/v2/builds`.
+        path: String,
+        cost: i32,
+    }
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+        cost: RateLimitCost,
+    }
+
+    #[utoipa::path(post, path = "/", responses(
+        (status = CREATED, body = inline(Response)),
+        (status = BAD_REQUEST, body = inline(ApiError)),
+        (status = CONFLICT, body = inline(ApiError)),
+    ), request_body = inline(Payload))]
+    pub async fn route(
+        state: GetState,
+        crate::Payload(data): crate::Payload<Payload>,
+    ) -> ApiResponseResult {
+        if let Err(error) = super::validate(&data.route_group, &data.path, data.cost) {
+            return ApiResponse::error(error)
+                .with_status(StatusCode::BAD_REQUEST)
+                .ok();
+        }
+
+        match RateLimitCost::new(
+            &state.database,
+            &state.cache,
+            &data.route_group,
+            &data.path,
+            data.cost,
+        )
+        .await?
+        {
+            Some(cost) => ApiResponse::new_serialized(Response {
+                success: true,
+                cost,
+            })
+            .with_status(StatusCode::CREATED)
+            .ok(),
+            None => ApiResponse::error("cost for this route group and path already exists")
+                .with_status(StatusCode::CONFLICT)
+                .ok(),
+        }
+    }
+}
+
+pub(super) fn validate(route_group: &str, path: &str, cost: i32) -> Result<(), &'static str> {
+    if !ROUTE_GROUPS.contains(&route_group) {
+        return Err("route group must be one of regular, files, files_download or files_archive");
+    }
+
+    if path.len() > 255 || !path.starts_with('/') {
+        return Err("path must start with / and be at most 255 characters");
+    }
+
+    if !(1..=1_000_000).contains(&cost) {
+        return Err("cost must be between 1 and 1000000");
+    }
+
+    Ok(())
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .routes(routes!(get::route))
+        .routes(routes!(post::route))
+        .nest("/{cost}", _cost_::router(state))
+        .with_state(state.clone())
+}
@@ -0,0 +1,12 @@
+use super::State;
+use utoipa_axum::router::OpenApiRouter;
+
+mod costs;
+mod policies;
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .nest("/policies", policies::router(state))
+        .nest("/costs", costs::router(state))
+        .with_state(state.clone())
+}
@@ -0,0 +1,128 @@
+use super::State;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+mod patch {
+    use crate::{
+        models::ratelimit::RateLimitPolicy,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiError, GetState},
+    };
+    use axum::{extract::Path, http::StatusCode};
+    use serde::{Deserialize, Serialize};
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Deserialize)]
+    pub struct Payload {
+        /// An empty string applies the policy to every route group.
+        route_group: Option<String>,
+        burst: Option<i32>,
+        rate: Option<i32>,
+        period: Option<i32>,
+    }
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+        policy: RateLimitPolicy,
+    }
+
+    #[utoipa::path(patch, path = "/", responses(
+        (status = OK, body = inline(Response)),
+        (status = BAD_REQUEST, body = inline(ApiError)),
+        (status = NOT_FOUND, body = inline(ApiError)),
+    ), params(
+        (
+            "policy" = i32,
+            description = "The rate limit policy ID",
+            example = 1,
+        ),
+    ), request_body = inline(Payload))]
+    pub async fn route(
+        state: GetState,
+        Path(policy): Path<i32>,
+        crate::Payload(data): crate::Payload<Payload>,
+    ) -> ApiResponseResult {
+        let Some(mut policy) = RateLimitPolicy::by_id(&state.database, policy).await? else {
+            return ApiResponse::error("policy not found")
+                .with_status(StatusCode::NOT_FOUND)
+                .ok();
+        };
+
+        if let Some(route_group) = data.route_group {
+            policy.route_group = (!route_group.is_empty()).then(|| route_group.into());
+        }
+        if let Some(burst) = data.burst {
+            policy.burst = burst;
+        }
+        if let Some(rate) = data.rate {
+            policy.rate = rate;
+        }
+        if let Some(period) = data.period {
+            policy.period = period;
+        }
+
+        if let Err(error) = super::super::validate(
+            policy.route_group.as_deref(),
+            policy.burst,
+            policy.rate,
+            policy.period,
+        ) {
+            return ApiResponse::error(error)
+                .with_status(StatusCode::BAD_REQUEST)
+                .ok();
+        }
+
+        policy.save(&state.database, &state.cache).await?;
+
+        ApiResponse::new_serialized(Response {
+            success: true,
+            policy,
+        })
+        .ok()
+    }
+}
+
+mod delete {
+    use crate::{
+        models::ratelimit::RateLimitPolicy,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiError, GetState},
+    };
+    use axum::{extract::Path, http::StatusCode};
+    use serde::Serialize;
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+    }
+
+    #[utoipa::path(delete, path = "/", responses(
+        (status = OK, body = inline(Response)),
+        (status = NOT_FOUND, body = inline(ApiError)),
+    ), params(
+        (
+            "policy" = i32,
+            description = "The rate limit policy ID",
+            example = 1,
+        ),
+    ))]
+    pub async fn route(state: GetState, Path(policy): Path<i32>) -> ApiResponseResult {
+        let Some(policy) = RateLimitPolicy::by_id(&state.database, policy).await? else {
+            return ApiResponse::error("policy not found")
+                .with_status(StatusCode::NOT_FOUND)
+                .ok();
+        };
+
+        policy.delete(&state.database, &state.cache).await?;
+
+        ApiResponse::new_serialized(Response { success: true }).ok()
+    }
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .routes(routes!(patch::route))
+        .routes(routes!(delete::route))
+        .with_state(state.clone())
+}
@@ -0,0 +1,140 @@
+use super::State;
+use crate::models::ratelimit::ROUTE_GROUPS;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+mod _policy_;
+
+mod get {
+    use crate::{
+        models::ratelimit::RateLimitPolicy,
+        response::{ApiResponse, ApiResponseResult},
+        routes::GetState,
+    };
+    use serde::Serialize;
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+        policies: Vec<RateLimitPolicy>,
+    }
+
+    #[utoipa::path(get, path = "/", responses(
+        (status = OK, body = inline(Response)),
+    ))]
+    pub async fn route(state: GetState) -> ApiResponseResult {
+        ApiResponse::new_serialized(Response {
+            success: true,
+            policies: RateLimitPolicy::all(&state.database).await?,
+        })
+        .ok()
+    }
+}
+
+mod post {
+    use crate::{
+        models::ratelimit::RateLimitPolicy,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiError, GetState},
+    };
+    use axum::http::StatusCode;
+    use serde::{Deserialize, Serialize};
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Deserialize)]
+    pub struct Payload {
+        organization_id: Option<i32>,
+        /// Takes precedence over `organization_id`, the policy applies to the key's organization.
+        organization_key_id: Option<i32>,
+        route_group: Option<String>,
+
+        burst: i32,
+        rate: i32,
+        #[serde(default = "default_period")]
+        period: i32,
+    }
+
+    #[inline]
+    fn default_period() -> i32 {
+        60
+    }
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+        policy: RateLimitPolicy,
+    }
+
+    #[utoipa::path(post, path = "/", responses(
+        (status = CREATED, body = inline(Response)),
+        (status = BAD_REQUEST, body = inline(ApiError)),
+        (status = NOT_FOUND, body = inline(ApiError)),
+    ), request_body = inline(Payload))]
+    pub async fn route(
+        state: GetState,
+        crate::Payload(data): crate::Payload<Payload>,
+    ) -> ApiResponseResult {
+        if let Err(error) = super::validate(
+            data.route_group.as_deref(),
+            data.burst,
+            data.rate,
+            data.period,
+        ) {
+            return ApiResponse::error(error)
+                .with_status(StatusCode::BAD_REQUEST)
+                .ok();
+        }
+
+        match RateLimitPolicy::new(
+            &state.database,
+            &state.cache,
+            data.organization_id,
+            data.organization_key_id,
+            data.route_group.as_deref(),
+            data.burst,
+            data.rate,
+            data.period,
+        )
+        .await?
+        {
+            Some(policy) => ApiResponse::new_serialized(Response {
+                success: true,
+                policy,
+            })
+            .with_status(StatusCode::CREATED)
+            .ok(),
+            None => ApiResponse::error("organization or key not found")
+                .with_status(StatusCode::NOT_FOUND)
+                .ok(),
+        }
+    }
+}
+
+pub(super) fn validate(
+    route_group: Option<&str>,
+    burst: i32,
+    rate: i32,
+    period: i32,
+) -> Result<(), &'static str> {
+    if route_group.is_some_and(|route_group| !ROUTE_GROUPS.contains(&route_group)) {
+        return Err("route group must be one of regular, files, files_download or files_archive");
+    }
+
+    if !(1..=1_000_000).contains(&burst) || !(1..=1_000_000).contains(&rate) {
+        return Err("burst and rate must be between 1 and 1000000");
+    }
+
+    if !(1..=86400).contains(&period) {
+        return Err("period must be between 1 and 86400 seconds");
+    }
+
+    Ok(())
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .routes(routes!(get::route))
+        .routes(routes!(post::route))
+        .nest("/{policy}", _policy_::router(state))
+        .with_state(state.clone())
+}
@@ -3,8 +3,11 @@ use utoipa_axum::{router::OpenApiRouter, routes};
 
 mod get {
     use crate::{
+        cache::CacheStats,
+        requests::AnalyticsStats,
         response::{ApiResponse, ApiResponseResult},
         routes::GetState,
+        user_agent::ClientStats,
     };
     use serde::{Deserialize, Serialize};
     use sqlx::Row;
@@ -29,8 +32,11 @@ mod get {
         idle_read_connections: usize,
         idle_write_connections: usize,
 
-        cache_hits: usize,
-        cache_misses: usize,
+        #[schema(inline)]
+        cache: CacheStats,
+
+        #[schema(inline)]
+        analytics: AnalyticsStats,
     }
 
     #[derive(ToSchema, Serialize, Deserialize)]
@@ -42,6 +48,8 @@ mod get {
 
         #[schema(inline)]
         requests: StatsRequests,
+        /// File downloads of the last 30 days by client family.
+        clients: Vec<ClientStats>,
         #[schema(inline)]
         internal: StatsInternal,
     }
@@ -61,7 +69,7 @@ mod get {
         let stats = state
             .cache
             .cached("stats::admin::all", 60, || async {
-                let (data, requests_data) = tokio::try_join!(
+                let (data, requests_data, clients) = tokio::try_join!(
                     async {
                         let data = sqlx::query(
                             r#"
@@ -104,6 +112,25 @@ mod get {
 
                         Ok(requests_data)
                     },
+                    async {
+                        let clients = state
+                            .clickhouse
+                            .client()
+                            .query(
+                                r#"
+                                SELECT file_requests.client_category, file_requests.client_family, COUNT(*) AS total
+                                FROM file_requests
+                                WHERE file_requests.created > NOW() - INTERVAL 30 DAY
+                                    AND file_requests.kind IN ('file', 'archive')
+                                GROUP BY file_requests.client_category, file_requests.client_family
+                                ORDER BY total DESC
+                                "#,
+                            )
+                            .fetch_all::<(i8, String, u64)>()
+                            .await?;
+
+                        Ok(clients)
+                    },
                 )?;
 
                 Ok::<_, anyhow::Error>(Stats {
@@ -121,12 +148,17 @@ mod get {
                         month: requests_data.5,
                         year: requests_data.6,
                     },
+                    clients: clients
+                        .into_iter()
+                        .map(ClientStats::from)
+                        .collect(),
                     internal: StatsInternal {
                         idle_read_connections: 0,
                         idle_write_connections: 0,
 
-                        cache_hits: 0,
-                        cache_misses: 0,
+                        cache: CacheStats::default(),
+
+                        analytics: AnalyticsStats::default(),
                     },
                 })
             })
@@ -139,8 +171,9 @@ mod get {
                     idle_read_connections: state.database.read().num_idle(),
                     idle_write_connections: state.database.write().num_idle(),
 
-                    cache_hits: state.cache.cache_hits(),
-                    cache_misses: state.cache.cache_misses(),
+                    cache: state.cache.stats(),
+
+                    analytics: state.requests.stats().await?,
                 },
                 ..stats
             },
@@ -0,0 +1,77 @@
+use super::State;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+mod post {
+    use crate::{
+        files::Verification,
+        models::file::File,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiError, GetState},
+    };
+    use axum::http::StatusCode;
+    use serde::{Deserialize, Serialize};
+    use std::path::Path;
+    use utoipa::ToSchema;
+
+    const MAX_FILES: i64 = 1000;
+
+    #[derive(ToSchema, Deserialize)]
+    pub struct Payload {
+        #[schema(example = "vanilla/1.21.4")]
+        path: String,
+    }
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+
+        checked: usize,
+        valid: usize,
+        mismatched: Vec<String>,
+        missing: Vec<String>,
+    }
+
+    #[utoipa::path(post, path = "/", responses(
+        (status = OK, body = inline(Response)),
+        (status = BAD_REQUEST, body = inline(ApiError)),
+    ), request_body = inline(Payload))]
+    pub async fn route(
+        state: GetState,
+        crate::Payload(data): crate::Payload<Payload>,
+    ) -> ApiResponseResult {
+        let root = Path::new(data.path.trim_matches('/'));
+
+        let files = File::all_under(&state.database, root, MAX_FILES + 1).await?;
+        if files.len() as i64 > MAX_FILES {
+            return ApiResponse::error(&format!(
+                "path contains more than {MAX_FILES} files, verify a smaller subtree"
+            ))
+            .with_status(StatusCode::BAD_REQUEST)
+            .ok();
+        }
+
+        let mut response = Response {
+            success: true,
+            checked: files.len(),
+            valid: 0,
+            mismatched: Vec::new(),
+            missing: Vec::new(),
+        };
+
+        for (path, file) in files {
+            match state.files.verify(&path, &file).await? {
+                Verification::Valid => response.valid += 1,
+                Verification::Mismatch => response.mismatched.push(path.display().to_string()),
+                Verification::Missing => response.missing.push(path.display().to_string()),
+            }
+        }
+
+        ApiResponse::new_serialized(response).ok()
+    }
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .routes(routes!(post::route))
+        .with_state(state.clone())
+}
@@ -56,7 +56,7 @@ async fn auth(
     };
 
     if let Some(ip) = crate::utils::extract_ip(req.headers()) {
-        session.ip = ip.into();
+        session.ip = crate::utils::anonymize_ip(ip, &state.env.ip_anonymization).into();
     }
     session.user_agent = req
         .headers()
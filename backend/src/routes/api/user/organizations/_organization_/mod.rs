@@ -13,6 +13,7 @@ use utoipa_axum::{router::OpenApiRouter, routes};
 
 mod api_keys;
 mod icon;
+mod requests;
 mod stats;
 mod subusers;
 mod update_build_data;
@@ -266,6 +267,7 @@ pub fn router(state: &State) -> OpenApiRouter<State> {
         .nest("/api-keys", api_keys::router(state))
         .nest("/update-build-data", update_build_data::router(state))
         .nest("/subusers", subusers::router(state))
+        .nest("/requests", requests::router(state))
         .route_layer(axum::middleware::from_fn_with_state(state.clone(), auth))
         .with_state(state.clone())
 }
@@ -0,0 +1,57 @@
+use super::State;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+mod get {
+    use crate::{
+        requests::RequestTrace,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiError, GetState, api::user::organizations::_organization_::GetOrganization},
+    };
+    use axum::{extract::Path, http::StatusCode};
+    use serde::Serialize;
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        success: bool,
+        request: RequestTrace,
+    }
+
+    #[utoipa::path(get, path = "/", responses(
+        (status = OK, body = inline(Response)),
+        (status = NOT_FOUND, body = inline(ApiError)),
+    ), params(
+        (
+            "organization" = i32,
+            description = "The organization ID",
+            example = 1,
+        ),
+        (
+            "request" = String,
+            description = "The X-Request-ID of the request",
+            example = "Jd8eKq2Lm0Zx",
+        ),
+    ))]
+    pub async fn route(
+        state: GetState,
+        organization: GetOrganization,
+        Path((_organization, request)): Path<(i32, String)>,
+    ) -> ApiResponseResult {
+        match state.requests.trace(organization.id, &request).await? {
+            Some(request) => ApiResponse::new_serialized(Response {
+                success: true,
+                request,
+            })
+            .ok(),
+            None => ApiResponse::error("request not found")
+                .with_status(StatusCode::NOT_FOUND)
+                .ok(),
+        }
+    }
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .routes(routes!(get::route))
+        .with_state(state.clone())
+}
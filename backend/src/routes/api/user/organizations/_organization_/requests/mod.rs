@@ -0,0 +1,12 @@
+use super::State;
+use utoipa_axum::router::OpenApiRouter;
+
+mod _request_;
+mod failed;
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .nest("/failed", failed::router(state))
+        .nest("/{request}", _request_::router(state))
+        .with_state(state.clone())
+}
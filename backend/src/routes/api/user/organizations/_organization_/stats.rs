@@ -5,6 +5,7 @@ mod get {
     use crate::{
         response::{ApiResponse, ApiResponseResult},
         routes::{GetState, api::user::organizations::_organization_::GetOrganization},
+        user_agent::ClientStats,
     };
     use serde::{Deserialize, Serialize};
     use utoipa::ToSchema;
@@ -19,6 +20,7 @@ mod get {
         origins: u64,
         continents: u64,
         countries: u64,
+        clients: Vec<ClientStats>,
     }
 
     #[derive(ToSchema, Serialize)]
@@ -41,29 +43,44 @@ mod get {
     pub async fn route(state: GetState, organization: GetOrganization) -> ApiResponseResult {
         let stats = state
             .cache
-            .cached(
+            .cached_with(
                 &format!("organization::stats::{}", organization.id),
-                300,
+                crate::cache::CacheOptions::new(300).tag(format!("org:{}", organization.id)),
                 || async {
-                    let data = state
-                        .clickhouse
-                        .client()
-                        .query(
-                            r#"
-                            SELECT
-                                COUNT(*),
-                                uniqExact(requests.user_agent),
-                                uniqExact(requests.ip),
-                                uniqExact(requests.origin),
-                                uniqExact(requests.continent),
-                                uniqExact(requests.country)
-                            FROM requests
-                            WHERE requests.organization_id = ?
-                            "#,
-                        )
-                        .bind(organization.id)
-                        .fetch_one::<(u64, u64, u64, u64, u64, u64)>()
-                        .await?;
+                    let (data, clients) = tokio::try_join!(
+                        state
+                            .clickhouse
+                            .client()
+                            .query(
+                                r#"
+                                SELECT
+                                    COUNT(*),
+                                    uniqExact(requests.user_agent),
+                                    uniqExact(requests.ip),
+                                    uniqExact(requests.origin),
+                                    uniqExact(requests.continent),
+                                    uniqExact(requests.country)
+                                FROM requests
+                                WHERE requests.organization_id = ?
+                                "#,
+                            )
+                            .bind(organization.id)
+                            .fetch_one::<(u64, u64, u64, u64, u64, u64)>(),
+                        state
+                            .clickhouse
+                            .client()
+                            .query(
+                                r#"
+                                SELECT requests.client_category, requests.client_family, COUNT(*) AS total
+                                FROM requests
+                                WHERE requests.organization_id = ?
+                                GROUP BY requests.client_category, requests.client_family
+                                ORDER BY total DESC
+                                "#,
+                            )
+                            .bind(organization.id)
+                            .fetch_all::<(i8, String, u64)>(),
+                    )?;
 
                     Ok::<_, anyhow::Error>(Stats {
                         requests: data.0,
@@ -72,6 +89,10 @@ mod get {
                         origins: data.3,
                         continents: data.4,
                         countries: data.5,
+                        clients: clients
+                            .into_iter()
+                            .map(ClientStats::from)
+                            .collect(),
                     })
                 },
             )
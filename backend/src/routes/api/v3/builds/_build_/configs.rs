@@ -65,9 +65,11 @@ mod get {
             }
         });
 
+        let options = crate::cache::CacheOptions::new(3600).tag(format!("build:{}", build.id));
+
         let configs = state
             .cache
-            .cached(&format!("build_configs::{}", build.id), 3600, || async {
+            .cached_with(&format!("build_configs::{}", build.id), options, || async {
                 let rows = sqlx::query(
                     r#"
                     SELECT
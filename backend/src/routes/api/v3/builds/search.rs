@@ -366,5 +366,9 @@ mod post {
 pub fn router(state: &State) -> OpenApiRouter<State> {
     OpenApiRouter::new()
         .routes(routes!(post::route))
+        .route_layer(axum::middleware::from_fn_with_state(
+            crate::requests::CaptureBody::default(),
+            crate::requests::capture_body,
+        ))
         .with_state(state.clone())
 }
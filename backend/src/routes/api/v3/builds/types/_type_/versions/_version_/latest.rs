@@ -67,9 +67,9 @@ mod get {
 
         let data = state
             .cache
-            .cached(
+            .cached_with(
                 &format!("build::{}::{}::latest", r#type, version,),
-                3600,
+                crate::cache::CacheOptions::new(3600).tag(format!("type:{type}")),
                 || Build::by_build_number(&state.database, r#type, &location, &version, None),
             )
             .await?;
@@ -82,14 +82,14 @@ mod get {
 
         let data = state
             .cache
-            .cached(
+            .cached_with(
                 &format!(
                     "builds::{type}::{version}::pagination::{}::{}::{}",
                     params.page,
                     params.per_page,
                     params.search.as_deref().unwrap_or("")
                 ),
-                900,
+                crate::cache::CacheOptions::new(900).tag(format!("type:{type}")),
                 || async {
                     Build::all_by_version_with_pagination(
                         &state.database,
@@ -151,5 +151,11 @@ mod post {
 pub fn router(state: &State) -> OpenApiRouter<State> {
     OpenApiRouter::new()
         .routes(routes!(post::route))
+        .route_layer(axum::middleware::from_fn_with_state(
+            crate::requests::CaptureBody {
+                redact: &["config"],
+            },
+            crate::requests::capture_body,
+        ))
         .with_state(state.clone())
 }
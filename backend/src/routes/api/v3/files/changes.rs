@@ -0,0 +1,80 @@
+use super::State;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+mod get {
+    use crate::{
+        models::file::FileChange,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiErrorV3, GetState},
+    };
+    use axum::http::StatusCode;
+    use axum_extra::extract::Query;
+    use garde::Validate;
+    use serde::{Deserialize, Serialize};
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Validate, Deserialize)]
+    pub struct Params {
+        #[garde(range(min = 0))]
+        #[schema(minimum = 0)]
+        #[serde(default)]
+        pub cursor: i64,
+        #[garde(range(min = 1, max = 1000))]
+        #[schema(minimum = 1, maximum = 1000)]
+        #[serde(default = "default_limit")]
+        pub limit: i64,
+    }
+
+    #[inline]
+    fn default_limit() -> i64 {
+        500
+    }
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        changes: Vec<crate::models::file::ApiFileChangeV3>,
+        cursor: i64,
+        has_more: bool,
+    }
+
+    #[utoipa::path(get, path = "/", responses(
+        (status = OK, body = inline(Response)),
+        (status = BAD_REQUEST, body = inline(ApiErrorV3)),
+    ), params(
+        (
+            "cursor" = i64, Query,
+            description = "The cursor returned by the previous call, 0 to start from the beginning",
+            example = 0,
+        ),
+        (
+            "limit" = i64, Query,
+            description = "The maximum amount of changes to return",
+            example = 500,
+        ),
+    ))]
+    pub async fn route(state: GetState, params: Query<Params>) -> ApiResponseResult {
+        if let Err(errors) = crate::utils::validate_data(&params.0) {
+            return ApiResponse::new_serialized(ApiErrorV3::new_strings_value(errors))
+                .with_status(StatusCode::BAD_REQUEST)
+                .ok();
+        }
+
+        let changes = FileChange::since(&state.database, params.cursor, params.limit).await?;
+
+        ApiResponse::new_serialized(Response {
+            cursor: changes.last().map_or(params.cursor, |change| change.id),
+            has_more: changes.len() as i64 == params.limit,
+            changes: changes
+                .into_iter()
+                .map(|change| change.into_api_v3(&state.env.app_url))
+                .collect(),
+        })
+        .ok()
+    }
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .routes(routes!(get::route))
+        .with_state(state.clone())
+}
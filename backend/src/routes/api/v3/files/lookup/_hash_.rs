@@ -0,0 +1,51 @@
+use super::State;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+mod get {
+    use crate::{
+        models::file::File,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiErrorV3, GetState},
+    };
+    use axum::{extract::Path, http::StatusCode};
+    use serde::Serialize;
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        files: Vec<crate::models::file::ApiFileV3>,
+    }
+
+    #[utoipa::path(get, path = "/", responses(
+        (status = OK, body = inline(Response)),
+        (status = NOT_FOUND, body = inline(ApiErrorV3)),
+    ), params(
+        (
+            "hash",
+            description = "The md5, sha1, sha224, sha256, sha384 or sha512 hash of the file",
+            example = "f8b5d1d1c4a8d1e0b9d0a7b5d6a4b8c1",
+        ),
+    ))]
+    pub async fn route(state: GetState, Path(hash): Path<String>) -> ApiResponseResult {
+        let files = File::by_hash(&state.database, &state.cache, &hash).await?;
+        if files.is_empty() {
+            return ApiResponse::error("file not found")
+                .with_status(StatusCode::NOT_FOUND)
+                .ok();
+        }
+
+        ApiResponse::new_serialized(Response {
+            files: files
+                .into_iter()
+                .map(|(path, file)| file.into_api_v3(&path, &state.env.app_url))
+                .collect(),
+        })
+        .ok()
+    }
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .routes(routes!(get::route))
+        .with_state(state.clone())
+}
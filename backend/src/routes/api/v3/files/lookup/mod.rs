@@ -0,0 +1,66 @@
+use super::State;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+mod _hash_;
+
+mod post {
+    use crate::{
+        models::file::File,
+        response::{ApiResponse, ApiResponseResult},
+        routes::{ApiErrorV3, GetState},
+    };
+    use axum::http::StatusCode;
+    use indexmap::IndexMap;
+    use serde::{Deserialize, Serialize};
+    use utoipa::ToSchema;
+
+    #[derive(ToSchema, Deserialize)]
+    pub struct Payload {
+        hashes: Vec<compact_str::CompactString>,
+    }
+
+    #[derive(ToSchema, Serialize)]
+    struct Response {
+        results: IndexMap<compact_str::CompactString, Vec<crate::models::file::ApiFileV3>>,
+    }
+
+    #[utoipa::path(post, path = "/", responses(
+        (status = OK, body = inline(Response)),
+        (status = BAD_REQUEST, body = inline(ApiErrorV3)),
+    ), request_body = inline(Payload))]
+    pub async fn route(
+        state: GetState,
+        crate::Payload(data): crate::Payload<Payload>,
+    ) -> ApiResponseResult {
+        if !(1..=50).contains(&data.hashes.len()) {
+            return ApiResponse::error("hashes must contain between 1 and 50 entries")
+                .with_status(StatusCode::BAD_REQUEST)
+                .ok();
+        }
+
+        let mut results = IndexMap::with_capacity(data.hashes.len());
+        for hash in data.hashes {
+            if results.contains_key(&hash) {
+                continue;
+            }
+
+            let files = File::by_hash(&state.database, &state.cache, &hash).await?;
+            results.insert(
+                hash,
+                files
+                    .into_iter()
+                    .map(|(path, file)| file.into_api_v3(&path, &state.env.app_url))
+                    .collect(),
+            );
+        }
+
+        ApiResponse::new_serialized(Response { results }).ok()
+    }
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .routes(routes!(post::route))
+        .nest("/{hash}", _hash_::router(state))
+        .with_state(state.clone())
+}
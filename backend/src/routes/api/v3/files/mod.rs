@@ -0,0 +1,12 @@
+use super::State;
+use utoipa_axum::router::OpenApiRouter;
+
+mod changes;
+mod lookup;
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .nest("/changes", changes::router(state))
+        .nest("/lookup", lookup::router(state))
+        .with_state(state.clone())
+}
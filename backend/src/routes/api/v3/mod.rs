@@ -3,10 +3,12 @@ use utoipa_axum::router::OpenApiRouter;
 
 mod builds;
 mod configs;
+mod files;
 
 pub fn router(state: &State) -> OpenApiRouter<State> {
     OpenApiRouter::new()
         .nest("/builds", builds::router(state))
         .nest("/configs", configs::router(state))
+        .nest("/files", files::router(state))
         .with_state(state.clone())
 }
@@ -0,0 +1,363 @@
+use crate::{models::file::File, routes::State};
+use axum::{body::Bytes, extract::rejection::QueryRejection, http::Uri};
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use futures_util::{Stream, StreamExt};
+use serde::Deserialize;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    pin::Pin,
+};
+use tokio::{io::AsyncReadExt, sync::mpsc};
+
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// Zip archives are written without zip64 records, so offsets have to stay below 4 GiB
+/// regardless of `FILES_ARCHIVE_MAX_SIZE_MB`.
+const ZIP_MAX_SIZE: u64 = 3 * 1024 * 1024 * 1024;
+
+#[derive(Deserialize, Clone, Copy)]
+pub enum Format {
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "tar.gz")]
+    TarGz,
+}
+
+#[derive(Deserialize)]
+struct Params {
+    archive: Option<Format>,
+}
+
+impl Format {
+    /// `Ok(None)` when no archive was requested.
+    pub fn from_uri(uri: &Uri) -> Result<Option<Self>, QueryRejection> {
+        axum::extract::Query::<Params>::try_from_uri(uri).map(|params| params.0.archive)
+    }
+
+    #[inline]
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Zip => "zip",
+            Self::TarGz => "tar.gz",
+        }
+    }
+
+    #[inline]
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Zip => "application/zip",
+            Self::TarGz => "application/gzip",
+        }
+    }
+
+    #[inline]
+    pub fn max_size(self, configured: u64) -> u64 {
+        match self {
+            Self::Zip => configured.min(ZIP_MAX_SIZE),
+            Self::TarGz => configured,
+        }
+    }
+
+    /// Whether every file in `files` gets its full path in the archive of `root`.
+    pub fn names_fit(self, root: &Path, files: &[File]) -> bool {
+        let directory = directory_name(root);
+
+        files
+            .iter()
+            .filter(|file| !file.is_directory)
+            .all(|file| match self {
+                Self::Zip => directory.len() + 1 + file.name.len() <= u16::MAX as usize,
+                Self::TarGz => ustar_path(&format!("{directory}/{}", file.name)).is_some(),
+            })
+    }
+}
+
+/// The top level directory of the archive of `root`.
+fn directory_name(root: &Path) -> String {
+    root.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "files".to_string())
+}
+
+/// Splits `path` at a slash into the 155 byte ustar prefix and 100 byte name fields,
+/// `None` when it does not fit either way.
+fn ustar_path(path: &str) -> Option<(&str, &str)> {
+    if path.len() <= 100 {
+        return Some(("", path));
+    }
+
+    path.match_indices('/')
+        .map(|(index, _)| index)
+        .find(|index| *index <= 155 && path.len() - index - 1 <= 100)
+        .map(|index| (&path[..index], &path[index + 1..]))
+}
+
+/// Forwards archive bytes to the response, gzip-compressing them on the fly for tarballs.
+struct Output {
+    tx: mpsc::Sender<std::io::Result<Bytes>>,
+    gzip: Option<flate2::write::GzEncoder<Vec<u8>>>,
+    written: u64,
+}
+
+impl Output {
+    async fn send(&self, chunk: Vec<u8>) -> std::io::Result<()> {
+        if chunk.is_empty() {
+            return Ok(());
+        }
+
+        self.tx
+            .send(Ok(Bytes::from(chunk)))
+            .await
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))
+    }
+
+    async fn write(&mut self, data: &[u8]) -> std::io::Result<()> {
+        self.written += data.len() as u64;
+
+        let chunk = match &mut self.gzip {
+            Some(gzip) => {
+                gzip.write_all(data)?;
+                std::mem::take(gzip.get_mut())
+            }
+            None => data.to_vec(),
+        };
+
+        self.send(chunk).await
+    }
+
+    async fn finish(mut self) -> std::io::Result<()> {
+        if let Some(gzip) = self.gzip.take() {
+            let chunk = gzip.finish()?;
+            self.send(chunk).await?;
+        }
+
+        Ok(())
+    }
+}
+
+struct ZipEntry {
+    name: Vec<u8>,
+    crc32: u32,
+    compressed_size: u32,
+    size: u32,
+    offset: u32,
+    time: u16,
+    date: u16,
+}
+
+#[inline]
+fn dos_date_time(date: DateTime<Utc>) -> (u16, u16) {
+    let time = (date.hour() << 11) | (date.minute() << 5) | (date.second() / 2);
+    let date =
+        ((date.year().clamp(1980, 2107) as u32 - 1980) << 9) | (date.month() << 5) | date.day();
+
+    (time as u16, date as u16)
+}
+
+fn tar_header(path: &str, size: u64, modified: DateTime<Utc>) -> std::io::Result<[u8; 512]> {
+    let Some((prefix, name)) = ustar_path(path) else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidFilename,
+            format!("{path} is too long for a ustar header"),
+        ));
+    };
+
+    let mut header = [0; 512];
+
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    header[100..108].copy_from_slice(b"0000644\0");
+    header[108..116].copy_from_slice(b"0000000\0");
+    header[116..124].copy_from_slice(b"0000000\0");
+    header[124..136].copy_from_slice(format!("{size:011o}\0").as_bytes());
+    header[136..148].copy_from_slice(format!("{:011o}\0", modified.timestamp().max(0)).as_bytes());
+    header[148..156].copy_from_slice(b"        ");
+    header[156] = b'0';
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+    let checksum = header.iter().map(|b| *b as u32).sum::<u32>();
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+
+    Ok(header)
+}
+
+/// Streams an archive of every file (not directory) in `files`, which are the entries of
+/// the `root` directory. Each file is read through the `FileCache`.
+pub fn stream(
+    state: State,
+    root: PathBuf,
+    files: Vec<File>,
+    format: Format,
+) -> Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>> {
+    let (tx, mut rx) = mpsc::channel(8);
+
+    tokio::spawn(async move {
+        let mut output = Output {
+            tx: tx.clone(),
+            gzip: match format {
+                Format::Zip => None,
+                Format::TarGz => Some(flate2::write::GzEncoder::new(
+                    Vec::new(),
+                    flate2::Compression::fast(),
+                )),
+            },
+            written: 0,
+        };
+
+        let directory = directory_name(&root);
+
+        let run = async {
+            let mut entries = Vec::new();
+            let mut buffer = vec![0; BUFFER_SIZE];
+
+            for file in files.iter().filter(|file| !file.is_directory) {
+                let path = root.join(file.name.as_str());
+                let modified = state.files.modified(&path).await.unwrap_or_else(Utc::now);
+                let (_, mut reader) = state.files.get(&path, file, 0).await?;
+
+                match format {
+                    Format::Zip => {
+                        let name = format!("{directory}/{}", file.name).into_bytes();
+                        let (time, date) = dos_date_time(modified);
+                        let offset = output.written as u32;
+
+                        let mut header = Vec::with_capacity(30 + name.len());
+                        header.extend_from_slice(&0x04034b50u32.to_le_bytes());
+                        header.extend_from_slice(&20u16.to_le_bytes());
+                        header.extend_from_slice(&0x0808u16.to_le_bytes());
+                        header.extend_from_slice(&8u16.to_le_bytes());
+                        header.extend_from_slice(&time.to_le_bytes());
+                        header.extend_from_slice(&date.to_le_bytes());
+                        header.extend_from_slice(&[0; 12]);
+                        header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+                        header.extend_from_slice(&0u16.to_le_bytes());
+                        header.extend_from_slice(&name);
+                        output.write(&header).await?;
+
+                        let mut crc32 = crc32fast::Hasher::new();
+                        let mut deflate = flate2::write::DeflateEncoder::new(
+                            Vec::new(),
+                            flate2::Compression::fast(),
+                        );
+                        let mut size = 0u64;
+                        let mut compressed_size = 0u64;
+
+                        loop {
+                            match reader.read(&mut buffer).await? {
+                                0 => break,
+                                n => {
+                                    crc32.update(&buffer[..n]);
+                                    deflate.write_all(&buffer[..n])?;
+                                    size += n as u64;
+
+                                    let chunk = std::mem::take(deflate.get_mut());
+                                    compressed_size += chunk.len() as u64;
+                                    output.write(&chunk).await?;
+                                }
+                            }
+                        }
+
+                        let chunk = deflate.finish()?;
+                        compressed_size += chunk.len() as u64;
+                        output.write(&chunk).await?;
+
+                        let crc32 = crc32.finalize();
+
+                        let mut descriptor = Vec::with_capacity(16);
+                        descriptor.extend_from_slice(&0x08074b50u32.to_le_bytes());
+                        descriptor.extend_from_slice(&crc32.to_le_bytes());
+                        descriptor.extend_from_slice(&(compressed_size as u32).to_le_bytes());
+                        descriptor.extend_from_slice(&(size as u32).to_le_bytes());
+                        output.write(&descriptor).await?;
+
+                        entries.push(ZipEntry {
+                            name,
+                            crc32,
+                            compressed_size: compressed_size as u32,
+                            size: size as u32,
+                            offset,
+                            time,
+                            date,
+                        });
+                    }
+                    Format::TarGz => {
+                        let size = file.size as u64;
+                        let name = format!("{directory}/{}", file.name);
+                        output.write(&tar_header(&name, size, modified)?).await?;
+
+                        let mut reader = reader.take(size);
+                        let mut written = 0u64;
+
+                        loop {
+                            match reader.read(&mut buffer).await? {
+                                0 => break,
+                                n => {
+                                    output.write(&buffer[..n]).await?;
+                                    written += n as u64;
+                                }
+                            }
+                        }
+
+                        if written != size {
+                            return Err(std::io::Error::other(format!(
+                                "{} ended after {written} of {size} bytes",
+                                path.display()
+                            )));
+                        }
+
+                        let padding = (512 - (size % 512) as usize) % 512;
+                        output.write(&vec![0; padding]).await?;
+                    }
+                }
+            }
+
+            match format {
+                Format::Zip => {
+                    let offset = output.written as u32;
+                    let mut directory = Vec::new();
+
+                    for entry in entries.iter() {
+                        directory.extend_from_slice(&0x02014b50u32.to_le_bytes());
+                        directory.extend_from_slice(&20u16.to_le_bytes());
+                        directory.extend_from_slice(&20u16.to_le_bytes());
+                        directory.extend_from_slice(&0x0808u16.to_le_bytes());
+                        directory.extend_from_slice(&8u16.to_le_bytes());
+                        directory.extend_from_slice(&entry.time.to_le_bytes());
+                        directory.extend_from_slice(&entry.date.to_le_bytes());
+                        directory.extend_from_slice(&entry.crc32.to_le_bytes());
+                        directory.extend_from_slice(&entry.compressed_size.to_le_bytes());
+                        directory.extend_from_slice(&entry.size.to_le_bytes());
+                        directory.extend_from_slice(&(entry.name.len() as u16).to_le_bytes());
+                        directory.extend_from_slice(&[0; 12]);
+                        directory.extend_from_slice(&entry.offset.to_le_bytes());
+                        directory.extend_from_slice(&entry.name);
+                    }
+
+                    let size = directory.len() as u32;
+                    directory.extend_from_slice(&0x06054b50u32.to_le_bytes());
+                    directory.extend_from_slice(&[0; 4]);
+                    directory.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+                    directory.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+                    directory.extend_from_slice(&size.to_le_bytes());
+                    directory.extend_from_slice(&offset.to_le_bytes());
+                    directory.extend_from_slice(&0u16.to_le_bytes());
+                    output.write(&directory).await?;
+                }
+                Format::TarGz => {
+                    output.write(&[0; 1024]).await?;
+                }
+            }
+
+            output.finish().await
+        };
+
+        if let Err(err) = run.await {
+            tracing::warn!("archive of {} stopped early: {err:?}", root.display());
+            tx.send(Err(err)).await.unwrap_or_default();
+        }
+    });
+
+    futures_util::stream::poll_fn(move |cx| rx.poll_recv(cx)).boxed()
+}
@@ -0,0 +1,108 @@
+use crate::models::file::File;
+
+/// Checksum formats understood by `sha256sum -c` style tooling. Every algorithm is
+/// served as a `<file>.<ext>` sidecar, only md5, sha256 and sha512 get a
+/// per-directory `*SUMS` manifest.
+#[derive(Clone, Copy)]
+pub enum Algorithm {
+    Md5,
+    Sha1,
+    Sha256,
+    Sha512,
+}
+
+impl Algorithm {
+    /// Manifests linked from the directory index, in display order.
+    pub const MANIFESTS: [Self; 3] = [Self::Sha256, Self::Sha512, Self::Md5];
+
+    /// Sidecars linked from the directory index, in display order.
+    pub const LISTED_SIDECARS: [Self; 2] = [Self::Sha256, Self::Md5];
+
+    #[inline]
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Md5 => "md5",
+            Self::Sha1 => "sha1",
+            Self::Sha256 => "sha256",
+            Self::Sha512 => "sha512",
+        }
+    }
+
+    #[inline]
+    pub fn manifest_name(self) -> Option<&'static str> {
+        match self {
+            Self::Md5 => Some("MD5SUMS"),
+            Self::Sha1 => None,
+            Self::Sha256 => Some("SHA256SUMS"),
+            Self::Sha512 => Some("SHA512SUMS"),
+        }
+    }
+
+    pub fn from_manifest(name: &str) -> Option<Self> {
+        Self::MANIFESTS
+            .into_iter()
+            .find(|algorithm| algorithm.manifest_name() == Some(name))
+    }
+
+    /// Splits `<file>.<ext>` into the file name and the sidecar algorithm.
+    pub fn from_sidecar(name: &str) -> Option<(&str, Self)> {
+        let (file, extension) = name.rsplit_once('.')?;
+        let algorithm = [Self::Md5, Self::Sha1, Self::Sha256, Self::Sha512]
+            .into_iter()
+            .find(|algorithm| algorithm.extension() == extension)?;
+
+        (!file.is_empty()).then_some((file, algorithm))
+    }
+
+    #[inline]
+    fn hex_length(self) -> usize {
+        match self {
+            Self::Md5 => 32,
+            Self::Sha1 => 40,
+            Self::Sha256 => 64,
+            Self::Sha512 => 128,
+        }
+    }
+
+    #[inline]
+    fn hex(self, file: &File) -> String {
+        match self {
+            Self::Md5 => hex::encode(file.md5),
+            Self::Sha1 => hex::encode(file.sha1),
+            Self::Sha256 => hex::encode(file.sha256),
+            Self::Sha512 => hex::encode(file.sha512),
+        }
+    }
+}
+
+/// One `<hex>  <name>` line, the format `sha256sum -c` expects.
+#[inline]
+fn line(algorithm: Algorithm, file: &File) -> String {
+    format!("{}  {}\n", algorithm.hex(file), file.name)
+}
+
+#[inline]
+pub fn sidecar(algorithm: Algorithm, file: &File) -> String {
+    line(algorithm, file)
+}
+
+#[inline]
+pub fn sidecar_length(algorithm: Algorithm, file: &File) -> usize {
+    algorithm.hex_length() + 2 + file.name.len() + 1
+}
+
+pub fn manifest(algorithm: Algorithm, files: &[File]) -> String {
+    files
+        .iter()
+        .filter(|file| !file.is_directory)
+        .map(|file| line(algorithm, file))
+        .collect()
+}
+
+pub fn manifest_length(algorithm: Algorithm, files: &[File]) -> usize {
+    files
+        .iter()
+        .filter(|file| !file.is_directory)
+        .map(|file| sidecar_length(algorithm, file))
+        .sum()
+}
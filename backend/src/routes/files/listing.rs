@@ -0,0 +1,167 @@
+use crate::{
+    models::file::File,
+    response::{ApiResponse, ApiResponseResult},
+};
+use axum::http::{HeaderMap, StatusCode, Uri};
+use serde::{Deserialize, Serialize};
+use sqlx::types::chrono::NaiveDateTime;
+
+const DEFAULT_PER_PAGE: usize = 100;
+const MAX_PER_PAGE: usize = 1000;
+
+/// Formats that `ApiResponse::new_serialized` can produce, anything negotiating to
+/// `text/html` (browsers, `*/*`) keeps the rendered index page.
+const AVAILABLE_FORMATS: &[mime::Mime] = &[
+    mime::TEXT_HTML,
+    mime::APPLICATION_JSON,
+    mime::APPLICATION_MSGPACK,
+    mime::TEXT_XML,
+];
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum Sort {
+    #[default]
+    Name,
+    Size,
+    LastAccess,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+enum Order {
+    #[default]
+    Asc,
+    Desc,
+}
+
+#[derive(Deserialize)]
+struct Params {
+    #[serde(default = "default_page")]
+    page: usize,
+    #[serde(default = "default_per_page")]
+    per_page: usize,
+    #[serde(default)]
+    sort: Sort,
+    #[serde(default)]
+    order: Order,
+}
+
+#[inline]
+fn default_page() -> usize {
+    1
+}
+
+#[inline]
+fn default_per_page() -> usize {
+    DEFAULT_PER_PAGE
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum EntryType {
+    File,
+    Directory,
+}
+
+#[derive(Serialize)]
+struct Hashes {
+    md5: String,
+    sha1: String,
+    sha224: String,
+    sha256: String,
+    sha384: String,
+    sha512: String,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    name: compact_str::CompactString,
+    r#type: EntryType,
+    size: i64,
+    hashes: Option<Hashes>,
+    last_access: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize)]
+struct Listing {
+    success: bool,
+
+    path: String,
+    page: usize,
+    per_page: usize,
+    total: usize,
+    entries: Vec<Entry>,
+}
+
+pub fn requested(headers: &HeaderMap) -> bool {
+    crate::response::accept_from_headers(headers)
+        .and_then(|accept| accept.negotiate(AVAILABLE_FORMATS).ok())
+        .is_some_and(|negotiated| negotiated.essence_str() != mime::TEXT_HTML.essence_str())
+}
+
+pub fn render(location: &str, uri: &Uri, mut files: Vec<File>) -> ApiResponseResult {
+    let params = match axum::extract::Query::<Params>::try_from_uri(uri) {
+        Ok(params) => params.0,
+        Err(_) => {
+            return ApiResponse::error("invalid listing parameters")
+                .with_status(StatusCode::BAD_REQUEST)
+                .ok();
+        }
+    };
+
+    if params.page < 1 || !(1..=MAX_PER_PAGE).contains(&params.per_page) {
+        return ApiResponse::error(&format!(
+            "page must be at least 1 and per_page between 1 and {MAX_PER_PAGE}"
+        ))
+        .with_status(StatusCode::BAD_REQUEST)
+        .ok();
+    }
+
+    match params.sort {
+        Sort::Name => files.sort_by(|a, b| a.name.cmp(&b.name)),
+        Sort::Size => files.sort_by(|a, b| a.size.cmp(&b.size).then(a.name.cmp(&b.name))),
+        Sort::LastAccess => {
+            files.sort_by(|a, b| a.last_access.cmp(&b.last_access).then(a.name.cmp(&b.name)))
+        }
+    }
+
+    if matches!(params.order, Order::Desc) {
+        files.reverse();
+    }
+
+    let total = files.len();
+    let entries = files
+        .into_iter()
+        .skip((params.page - 1).saturating_mul(params.per_page))
+        .take(params.per_page)
+        .map(|file| Entry {
+            hashes: (!file.is_directory).then(|| Hashes {
+                md5: hex::encode(file.md5),
+                sha1: hex::encode(file.sha1),
+                sha224: hex::encode(file.sha224),
+                sha256: hex::encode(file.sha256),
+                sha384: hex::encode(file.sha384),
+                sha512: hex::encode(file.sha512),
+            }),
+            r#type: if file.is_directory {
+                EntryType::Directory
+            } else {
+                EntryType::File
+            },
+            name: file.name,
+            size: file.size,
+            last_access: (!file.is_directory).then_some(file.last_access).flatten(),
+        })
+        .collect();
+
+    ApiResponse::new_serialized(Listing {
+        success: true,
+        path: location.to_string(),
+        page: params.page,
+        per_page: params.per_page,
+        total,
+        entries,
+    })
+    .ok()
+}
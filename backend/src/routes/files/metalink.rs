@@ -0,0 +1,57 @@
+use crate::models::{file::File, mirror::Mirror};
+use std::path::Path;
+
+fn escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// Metalink 4 (RFC 5854) document for `file`, listing `mirrors` in the given order
+/// before the origin.
+pub fn document(app_url: &str, path: &Path, file: &File, mirrors: &[&Mirror]) -> String {
+    let mut document = String::new();
+
+    document.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    document.push_str("<metalink xmlns=\"urn:ietf:params:xml:ns:metalink\">\n");
+    document.push_str("  <generator>MCJars API</generator>\n");
+    document.push_str(&format!("  <file name=\"{}\">\n", escape(&file.name)));
+    document.push_str(&format!("    <size>{}</size>\n", file.size));
+
+    for (r#type, hash) in [
+        ("md5", hex::encode(file.md5)),
+        ("sha-1", hex::encode(file.sha1)),
+        ("sha-224", hex::encode(file.sha224)),
+        ("sha-256", hex::encode(file.sha256)),
+        ("sha-384", hex::encode(file.sha384)),
+        ("sha-512", hex::encode(file.sha512)),
+    ] {
+        document.push_str(&format!("    <hash type=\"{type}\">{hash}</hash>\n"));
+    }
+
+    for (priority, mirror) in mirrors.iter().enumerate() {
+        document.push_str(&format!(
+            "    <url{} priority=\"{}\">{}</url>\n",
+            mirror
+                .country
+                .as_ref()
+                .map(|country| format!(" location=\"{}\"", escape(&country.to_lowercase())))
+                .unwrap_or_default(),
+            priority + 1,
+            escape(&mirror.file_url(path))
+        ));
+    }
+
+    document.push_str(&format!(
+        "    <url priority=\"{}\">{}</url>\n",
+        mirrors.len() + 1,
+        escape(&format!("{app_url}/files/{}", path.display()))
+    ));
+    document.push_str("  </file>\n");
+    document.push_str("</metalink>\n");
+
+    document
+}
@@ -0,0 +1,902 @@
+use super::{GetState, State};
+use crate::{
+    files::CacheStatus,
+    models::{file::File, mirror::Mirror, organization::Organization},
+    requests::{FileRequestKind, TrackedFileStream},
+    response::{ApiResponse, ApiResponseResult},
+    routes::index::{IndexFile, render, render_not_found, render_robots},
+};
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{Method, StatusCode, request::Parts},
+    routing::{any, get},
+};
+use chrono::{DateTime, Utc};
+use rand::distr::SampleString;
+use range::RangeRequest;
+use serde::Deserialize;
+use sha2::Digest;
+use std::{
+    path::{Component, Path, PathBuf},
+    time::Instant,
+};
+use tokio::io::AsyncReadExt;
+use utoipa_axum::router::OpenApiRouter;
+
+pub mod archive;
+mod checksums;
+mod listing;
+mod metalink;
+mod range;
+pub mod signed;
+
+/// md5, sha1, sha224, sha256, sha384 and sha512: 416 hex chars, 42 chars of
+/// padded label, 6 newlines.
+const CHECKSUMS_LENGTH: i64 = 464;
+
+struct CompletedFile<'a> {
+    kind: FileRequestKind,
+    path: &'a Path,
+    size: i64,
+    bytes_sent: i64,
+    status: StatusCode,
+    started: Instant,
+}
+
+/// Logs a request that was answered without reading the file through the `FileCache`,
+/// so it counts as a bypass.
+async fn log_complete(
+    state: &GetState,
+    parts: &Parts,
+    organization: Option<&Organization>,
+    completed: CompletedFile<'_>,
+) {
+    let id = state
+        .requests
+        .log_file(
+            parts,
+            organization,
+            completed.kind,
+            completed.path,
+            completed.size,
+            CacheStatus::Bypass,
+        )
+        .await;
+
+    state.requests.finish_file(
+        id,
+        completed.status.as_u16() as i16,
+        completed.started.elapsed().as_millis() as i32,
+        completed.bytes_sent,
+    );
+}
+
+fn with_validators(
+    response: ApiResponse,
+    etag: &str,
+    last_modified: Option<DateTime<Utc>>,
+) -> ApiResponse {
+    let response = response
+        .with_header("ETag", etag)
+        .with_header("Accept-Ranges", "bytes")
+        .with_header("Cache-Control", "public, max-age=604800");
+
+    match last_modified {
+        Some(last_modified) => {
+            response.with_header("Last-Modified", &range::format_http_date(last_modified))
+        }
+        None => response,
+    }
+}
+
+/// Logs and serves a generated checksum or metalink file, `None` renders the not found page.
+async fn checksum_response(
+    state: &GetState,
+    parts: &Parts,
+    organization: Option<&Organization>,
+    path: &Path,
+    started: Instant,
+    body: Option<String>,
+    content_type: &'static str,
+) -> ApiResponseResult {
+    let size = body.as_ref().map_or(0, |body| body.len() as i64);
+
+    log_complete(
+        state,
+        parts,
+        organization,
+        CompletedFile {
+            kind: FileRequestKind::Checksums,
+            path,
+            size,
+            bytes_sent: if parts.method == Method::HEAD {
+                0
+            } else {
+                size
+            },
+            status: if body.is_some() {
+                StatusCode::OK
+            } else {
+                StatusCode::NOT_FOUND
+            },
+            started,
+        },
+    )
+    .await;
+
+    let Some(body) = body else {
+        return render_not_found(state, &format!("/{}", path.to_string_lossy()));
+    };
+
+    ApiResponse::new(if parts.method == Method::HEAD {
+        Body::empty()
+    } else {
+        Body::from(body)
+    })
+    .with_header("Content-Type", content_type)
+    .with_header("Content-Length", &size.to_string())
+    .ok()
+}
+
+#[derive(Deserialize)]
+struct MirrorParams {
+    mirror: Option<compact_str::CompactString>,
+}
+
+/// Location of the file on the nearest healthy mirror when `FILES_MIRROR_REDIRECT` is on,
+/// only mirrors on the client's own continent are considered. `?mirror=none` opts out,
+/// which `mirror-sync` relies on to always pull from the origin.
+async fn mirror_redirect(
+    state: &GetState,
+    parts: &Parts,
+    path: &Path,
+    file: &File,
+) -> Result<Option<String>, anyhow::Error> {
+    if !state.env.files_mirror_redirect
+        || parts.method != Method::GET
+        || (file.size as u64) < state.env.files_mirror_redirect_min_size
+        || axum::extract::Query::<MirrorParams>::try_from_uri(&parts.uri)
+            .is_ok_and(|params| params.0.mirror.as_deref() == Some("none"))
+    {
+        return Ok(None);
+    }
+
+    let Some(ip) = crate::utils::extract_ip(&parts.headers) else {
+        return Ok(None);
+    };
+    let Some(continent) = state.requests.continent(ip).await else {
+        return Ok(None);
+    };
+
+    let mirrors = Mirror::available(&state.database, &state.cache).await?;
+
+    Ok(Mirror::nearest(&mirrors, Some(continent.as_str()))
+        .first()
+        .filter(|mirror| mirror.continent == continent)
+        .map(|mirror| mirror.file_url(path)))
+}
+
+pub fn router(state: &State) -> OpenApiRouter<State> {
+    OpenApiRouter::new()
+        .route("/robots.txt", get(|| async { render_robots() }))
+        .fallback(any(|state: GetState, request: Request| async move {
+            let started = Instant::now();
+            let (parts, _) = request.into_parts();
+            let organization = parts
+                .extensions
+                .get::<Option<Organization>>()
+                .cloned()
+                .flatten();
+            let organization = organization.as_ref();
+
+            let path = Path::new(&parts.uri.path()[1..]);
+
+            if path.components().any(|c| matches!(c, Component::ParentDir)) {
+                return render_not_found(&state, &format!("/{}", path.to_string_lossy()));
+            }
+
+            if let Some(name) = path.file_name().and_then(|name| name.to_str()) {
+                let parent = path.parent().unwrap_or(Path::new(""));
+
+                if let Some(algorithm) = checksums::Algorithm::from_manifest(name) {
+                    let files = File::all_for_root(&state.database, &state.cache, parent).await?;
+                    let body = files
+                        .iter()
+                        .any(|file| !file.is_directory)
+                        .then(|| checksums::manifest(algorithm, &files));
+
+                    return checksum_response(
+                        &state,
+                        &parts,
+                        organization,
+                        path,
+                        started,
+                        body,
+                        "text/plain",
+                    )
+                    .await;
+                }
+
+                if let Some(file_name) = name.strip_suffix(".meta4") {
+                    let file_path = parent.join(file_name);
+                    let body = match File::by_path(&state.database, &state.cache, &file_path)
+                        .await?
+                    {
+                        Some(file) => {
+                            let mirrors = Mirror::available(&state.database, &state.cache).await?;
+                            let continent = match crate::utils::extract_ip(&parts.headers) {
+                                Some(ip) => state.requests.continent(ip).await,
+                                None => None,
+                            };
+
+                            Some(metalink::document(
+                                &state.env.app_url,
+                                &file_path,
+                                &file,
+                                &Mirror::nearest(&mirrors, continent.as_deref()),
+                            ))
+                        }
+                        None => None,
+                    };
+
+                    return checksum_response(
+                        &state,
+                        &parts,
+                        organization,
+                        path,
+                        started,
+                        body,
+                        "application/metalink4+xml",
+                    )
+                    .await;
+                }
+
+                if let Some((file_name, algorithm)) = checksums::Algorithm::from_sidecar(name) {
+                    let file =
+                        File::by_path(&state.database, &state.cache, &parent.join(file_name))
+                            .await?;
+                    let body = file.map(|file| checksums::sidecar(algorithm, &file));
+
+                    return checksum_response(
+                        &state,
+                        &parts,
+                        organization,
+                        path,
+                        started,
+                        body,
+                        "text/plain",
+                    )
+                    .await;
+                }
+            }
+
+            if path.components().next_back().is_some_and(|c| {
+                let string = c.as_os_str().to_string_lossy();
+
+                string.ends_with(".txt") || string.ends_with(".jar") || string.ends_with(".zip")
+            }) {
+                let last = path
+                    .components()
+                    .next_back()
+                    .unwrap()
+                    .as_os_str()
+                    .to_string_lossy();
+
+                if let Some(last) = last.strip_suffix(".CHECKSUMS.txt") {
+                    let file = match File::by_path(
+                        &state.database,
+                        &state.cache,
+                        &path
+                            .components()
+                            .take(path.components().count() - 1)
+                            .collect::<PathBuf>()
+                            .join(last),
+                    )
+                    .await?
+                    {
+                        Some(file) => file,
+                        None => {
+                            log_complete(
+                                &state,
+                                &parts,
+                                organization,
+                                CompletedFile {
+                                    kind: FileRequestKind::Checksums,
+                                    path,
+                                    size: 0,
+                                    bytes_sent: 0,
+                                    status: StatusCode::NOT_FOUND,
+                                    started,
+                                },
+                            )
+                            .await;
+
+                            return render_not_found(
+                                &state,
+                                &format!("/{}", path.to_string_lossy()),
+                            );
+                        }
+                    };
+
+                    log_complete(
+                        &state,
+                        &parts,
+                        organization,
+                        CompletedFile {
+                            kind: FileRequestKind::Checksums,
+                            path,
+                            size: CHECKSUMS_LENGTH,
+                            bytes_sent: if parts.method == Method::HEAD {
+                                0
+                            } else {
+                                CHECKSUMS_LENGTH
+                            },
+                            status: StatusCode::OK,
+                            started,
+                        },
+                    )
+                    .await;
+
+                    if parts.method == Method::HEAD {
+                        return ApiResponse::new(Body::empty())
+                            .with_header("Content-Type", "text/plain")
+                            .with_header("Content-Length", &CHECKSUMS_LENGTH.to_string())
+                            .ok();
+                    } else {
+                        let mut string = String::new();
+                        string.reserve_exact(CHECKSUMS_LENGTH as usize);
+
+                        string.push_str(&format!(
+                            "md5    {}\n",
+                            file.md5
+                                .iter()
+                                .map(|b| format!("{:02x}", b))
+                                .collect::<String>()
+                        ));
+                        string.push_str(&format!(
+                            "sha1   {}\n",
+                            file.sha1
+                                .iter()
+                                .map(|b| format!("{:02x}", b))
+                                .collect::<String>()
+                        ));
+                        string.push_str(&format!(
+                            "sha224 {}\n",
+                            file.sha224
+                                .iter()
+                                .map(|b| format!("{:02x}", b))
+                                .collect::<String>()
+                        ));
+                        string.push_str(&format!(
+                            "sha256 {}\n",
+                            file.sha256
+                                .iter()
+                                .map(|b| format!("{:02x}", b))
+                                .collect::<String>()
+                        ));
+                        string.push_str(&format!(
+                            "sha384 {}\n",
+                            file.sha384
+                                .iter()
+                                .map(|b| format!("{:02x}", b))
+                                .collect::<String>()
+                        ));
+                        string.push_str(&format!(
+                            "sha512 {}\n",
+                            file.sha512
+                                .iter()
+                                .map(|b| format!("{:02x}", b))
+                                .collect::<String>()
+                        ));
+
+                        return ApiResponse::new(Body::from(string))
+                            .with_header("Content-Type", "text/plain")
+                            .ok();
+                    }
+                }
+
+                let file = match File::by_path(&state.database, &state.cache, path).await? {
+                    Some(file) => file,
+                    None => {
+                        log_complete(
+                            &state,
+                            &parts,
+                            organization,
+                            CompletedFile {
+                                kind: FileRequestKind::File,
+                                path,
+                                size: 0,
+                                bytes_sent: 0,
+                                status: StatusCode::NOT_FOUND,
+                                started,
+                            },
+                        )
+                        .await;
+
+                        return render_not_found(&state, &format!("/{}", path.to_string_lossy()));
+                    }
+                };
+
+                if state.files.is_quarantined(path).await {
+                    log_complete(
+                        &state,
+                        &parts,
+                        organization,
+                        CompletedFile {
+                            kind: FileRequestKind::File,
+                            path,
+                            size: file.size,
+                            bytes_sent: 0,
+                            status: StatusCode::SERVICE_UNAVAILABLE,
+                            started,
+                        },
+                    )
+                    .await;
+
+                    return ApiResponse::error(
+                        "file failed integrity verification and is temporarily unavailable",
+                    )
+                    .with_status(StatusCode::SERVICE_UNAVAILABLE)
+                    .with_header("Cache-Control", "no-store")
+                    .ok();
+                }
+
+                if let Some(location) = mirror_redirect(&state, &parts, path, &file).await? {
+                    log_complete(
+                        &state,
+                        &parts,
+                        organization,
+                        CompletedFile {
+                            kind: FileRequestKind::File,
+                            path,
+                            size: file.size,
+                            bytes_sent: 0,
+                            status: StatusCode::FOUND,
+                            started,
+                        },
+                    )
+                    .await;
+
+                    return ApiResponse::new(Body::empty())
+                        .with_status(StatusCode::FOUND)
+                        .with_header("Location", &location)
+                        .with_header("Cache-Control", "no-store")
+                        .ok();
+                }
+
+                let etag = hex::encode(file.sha256);
+                let last_modified = state.files.modified(path).await;
+                let content_type = if last.ends_with(".jar") {
+                    "application/java-archive"
+                } else {
+                    "application/zip"
+                };
+
+                if range::not_modified(&parts.headers, &etag, last_modified) {
+                    log_complete(
+                        &state,
+                        &parts,
+                        organization,
+                        CompletedFile {
+                            kind: FileRequestKind::File,
+                            path,
+                            size: file.size,
+                            bytes_sent: 0,
+                            status: StatusCode::NOT_MODIFIED,
+                            started,
+                        },
+                    )
+                    .await;
+
+                    return with_validators(
+                        ApiResponse::new(Body::empty()).with_status(StatusCode::NOT_MODIFIED),
+                        &etag,
+                        last_modified,
+                    )
+                    .ok();
+                }
+
+                if parts.method == Method::HEAD {
+                    log_complete(
+                        &state,
+                        &parts,
+                        organization,
+                        CompletedFile {
+                            kind: FileRequestKind::File,
+                            path,
+                            size: file.size,
+                            bytes_sent: 0,
+                            status: StatusCode::OK,
+                            started,
+                        },
+                    )
+                    .await;
+
+                    return with_validators(
+                        ApiResponse::new(Body::empty())
+                            .with_header("Content-Type", content_type)
+                            .with_header("Content-Length", &file.size.to_string()),
+                        &etag,
+                        last_modified,
+                    )
+                    .ok();
+                }
+
+                let size = file.size as u64;
+                let ranges = match range::parse_range(&parts.headers, size, &etag, last_modified) {
+                    RangeRequest::Full => None,
+                    RangeRequest::Partial(ranges) => Some(ranges),
+                    RangeRequest::Unsatisfiable => {
+                        log_complete(
+                            &state,
+                            &parts,
+                            organization,
+                            CompletedFile {
+                                kind: FileRequestKind::File,
+                                path,
+                                size: file.size,
+                                bytes_sent: 0,
+                                status: StatusCode::RANGE_NOT_SATISFIABLE,
+                                started,
+                            },
+                        )
+                        .await;
+
+                        return with_validators(
+                            ApiResponse::new(Body::empty())
+                                .with_status(StatusCode::RANGE_NOT_SATISFIABLE)
+                                .with_header("Content-Range", &format!("bytes */{size}")),
+                            &etag,
+                            last_modified,
+                        )
+                        .ok();
+                    }
+                };
+
+                let opened = state
+                    .files
+                    .get(
+                        path,
+                        &file,
+                        ranges.as_ref().map_or(0, |ranges| ranges[0].start),
+                    )
+                    .await;
+
+                let id = state
+                    .requests
+                    .log_file(
+                        &parts,
+                        organization,
+                        FileRequestKind::File,
+                        path,
+                        file.size,
+                        opened
+                            .as_ref()
+                            .map_or(CacheStatus::Miss, |(status, _)| *status),
+                    )
+                    .await;
+
+                let (_, file_reader) = match opened {
+                    Ok(opened) => opened,
+                    Err(err) => {
+                        state.requests.finish_file(
+                            id,
+                            StatusCode::INTERNAL_SERVER_ERROR.as_u16() as i16,
+                            started.elapsed().as_millis() as i32,
+                            0,
+                        );
+
+                        return Err(err.into());
+                    }
+                };
+
+                let response = match ranges {
+                    None => ApiResponse::new(Body::from_stream(TrackedFileStream::new(
+                        tokio_util::io::ReaderStream::new(file_reader),
+                        state.0.clone(),
+                        id,
+                        StatusCode::OK.as_u16() as i16,
+                        started,
+                    )))
+                    .with_header("Content-Type", content_type)
+                    .with_header("Content-Length", &file.size.to_string()),
+                    Some(ranges) if ranges.len() == 1 => {
+                        let range = ranges[0];
+
+                        ApiResponse::new(Body::from_stream(TrackedFileStream::new(
+                            tokio_util::io::ReaderStream::new(file_reader.take(range.length())),
+                            state.0.clone(),
+                            id,
+                            StatusCode::PARTIAL_CONTENT.as_u16() as i16,
+                            started,
+                        )))
+                        .with_status(StatusCode::PARTIAL_CONTENT)
+                        .with_header("Content-Type", content_type)
+                        .with_header("Content-Length", &range.length().to_string())
+                        .with_header("Content-Range", &range.content_range(size))
+                    }
+                    Some(ranges) => {
+                        let boundary =
+                            rand::distr::Alphanumeric.sample_string(&mut rand::rng(), 32);
+                        let length =
+                            range::multipart_length(&ranges, &boundary, content_type, size);
+
+                        ApiResponse::new(Body::from_stream(TrackedFileStream::new(
+                            range::multipart(
+                                state.0.clone(),
+                                path.to_path_buf(),
+                                file.clone(),
+                                file_reader,
+                                ranges,
+                                boundary.clone(),
+                                content_type,
+                                size,
+                            ),
+                            state.0.clone(),
+                            id,
+                            StatusCode::PARTIAL_CONTENT.as_u16() as i16,
+                            started,
+                        )))
+                        .with_status(StatusCode::PARTIAL_CONTENT)
+                        .with_header(
+                            "Content-Type",
+                            &format!("multipart/byteranges; boundary={boundary}"),
+                        )
+                        .with_header("Content-Length", &length.to_string())
+                    }
+                };
+
+                return with_validators(response, &etag, last_modified).ok();
+            }
+
+            let files = File::all_for_root(&state.database, &state.cache, path).await?;
+
+            match archive::Format::from_uri(&parts.uri) {
+                Ok(Some(format)) => {
+                    let total_size = files
+                        .iter()
+                        .filter(|f| !f.is_directory)
+                        .map(|f| f.size as u64)
+                        .sum::<u64>();
+
+                    let mut quarantined = false;
+                    for f in files.iter().filter(|f| !f.is_directory) {
+                        if state.files.is_quarantined(&path.join(f.name.as_str())).await {
+                            quarantined = true;
+                            break;
+                        }
+                    }
+
+                    let status = if total_size == 0 {
+                        StatusCode::NOT_FOUND
+                    } else if total_size > format.max_size(state.env.files_archive_max_size) {
+                        StatusCode::PAYLOAD_TOO_LARGE
+                    } else if !format.names_fit(path, &files) {
+                        StatusCode::BAD_REQUEST
+                    } else if quarantined {
+                        StatusCode::SERVICE_UNAVAILABLE
+                    } else {
+                        StatusCode::OK
+                    };
+
+                    if status != StatusCode::OK || parts.method == Method::HEAD {
+                        log_complete(
+                            &state,
+                            &parts,
+                            organization,
+                            CompletedFile {
+                                kind: FileRequestKind::Archive,
+                                path,
+                                size: total_size as i64,
+                                bytes_sent: 0,
+                                status,
+                                started,
+                            },
+                        )
+                        .await;
+                    }
+
+                    match status {
+                        StatusCode::NOT_FOUND => {
+                            return ApiResponse::error("directory has no files to archive")
+                                .with_status(StatusCode::NOT_FOUND)
+                                .ok();
+                        }
+                        StatusCode::PAYLOAD_TOO_LARGE => {
+                            return ApiResponse::error(&format!(
+                                "directory is too large to archive ({} max)",
+                                human_bytes::human_bytes(
+                                    format.max_size(state.env.files_archive_max_size) as f64
+                                )
+                            ))
+                            .with_status(StatusCode::PAYLOAD_TOO_LARGE)
+                            .ok();
+                        }
+                        StatusCode::BAD_REQUEST => {
+                            return ApiResponse::error(&format!(
+                                "directory has file names too long for {}",
+                                format.extension()
+                            ))
+                            .with_status(StatusCode::BAD_REQUEST)
+                            .ok();
+                        }
+                        StatusCode::SERVICE_UNAVAILABLE => {
+                            return ApiResponse::error(
+                                "directory has files that failed integrity verification and is temporarily unavailable",
+                            )
+                            .with_status(StatusCode::SERVICE_UNAVAILABLE)
+                            .with_header("Cache-Control", "no-store")
+                            .ok();
+                        }
+                        _ => {}
+                    }
+
+                    let mut hash = sha2::Sha256::new();
+                    hash.update(format.extension());
+                    for f in files.iter().filter(|f| !f.is_directory) {
+                        hash.update(f.sha256);
+                    }
+
+                    let filename = format!(
+                        "{}.{}",
+                        path.file_name()
+                            .map_or("files".into(), |name| name.to_string_lossy()),
+                        format.extension()
+                    );
+
+                    let response = ApiResponse::new(Body::empty())
+                        .with_header("Content-Type", format.content_type())
+                        .with_header(
+                            "Content-Disposition",
+                            &format!("attachment; filename=\"{filename}\""),
+                        )
+                        .with_header("ETag", &format!("W/\"{}\"", hex::encode(hash.finalize())));
+
+                    if parts.method == Method::HEAD {
+                        return response.ok();
+                    }
+
+                    let id = state
+                        .requests
+                        .log_file(
+                            &parts,
+                            organization,
+                            FileRequestKind::Archive,
+                            path,
+                            total_size as i64,
+                            CacheStatus::Bypass,
+                        )
+                        .await;
+
+                    return ApiResponse {
+                        body: Body::from_stream(TrackedFileStream::new(
+                            archive::stream(state.0.clone(), path.to_path_buf(), files, format),
+                            state.0.clone(),
+                            id,
+                            StatusCode::OK.as_u16() as i16,
+                            started,
+                        )),
+                        ..response
+                    }
+                    .ok();
+                }
+                Ok(None) => {}
+                Err(_) => {
+                    return ApiResponse::error("archive must be either zip or tar.gz")
+                        .with_status(StatusCode::BAD_REQUEST)
+                        .ok();
+                }
+            }
+
+            let mut index_files = Vec::new();
+            index_files.reserve_exact(
+                files
+                    .iter()
+                    .map(|f| {
+                        if f.is_directory {
+                            1
+                        } else {
+                            2 + checksums::Algorithm::LISTED_SIDECARS.len()
+                        }
+                    })
+                    .sum::<usize>()
+                    + checksums::Algorithm::MANIFESTS.len(),
+            );
+
+            if files.iter().any(|f| !f.is_directory) {
+                for algorithm in checksums::Algorithm::MANIFESTS {
+                    let name = algorithm.manifest_name().unwrap_or_default();
+
+                    index_files.push(IndexFile {
+                        name: name.into(),
+                        size: human_bytes::human_bytes(
+                            checksums::manifest_length(algorithm, &files) as f64,
+                        )
+                        .into(),
+                        href: Some(name.into()),
+                    });
+                }
+            }
+
+            for f in files.iter() {
+                index_files.push(IndexFile {
+                    name: compact_str::format_compact!(
+                        "{}{}",
+                        f.name,
+                        if f.is_directory { "/" } else { "" }
+                    ),
+                    size: human_bytes::human_bytes(f.size as f64).into(),
+                    href: Some(compact_str::format_compact!(
+                        "{}{}",
+                        f.name,
+                        if f.is_directory { "/" } else { "" }
+                    )),
+                });
+
+                if !f.is_directory {
+                    index_files.push(IndexFile {
+                        name: compact_str::format_compact!("{}.CHECKSUMS.txt", f.name),
+                        size: human_bytes::human_bytes(CHECKSUMS_LENGTH as f64).into(),
+                        href: Some(compact_str::format_compact!("{}.CHECKSUMS.txt", f.name)),
+                    });
+
+                    for algorithm in checksums::Algorithm::LISTED_SIDECARS {
+                        let name =
+                            compact_str::format_compact!("{}.{}", f.name, algorithm.extension());
+
+                        index_files.push(IndexFile {
+                            size: human_bytes::human_bytes(
+                                checksums::sidecar_length(algorithm, f) as f64
+                            )
+                            .into(),
+                            href: Some(name.clone()),
+                            name,
+                        });
+                    }
+                }
+            }
+
+            let missing = index_files.is_empty() && !path.as_os_str().is_empty();
+
+            log_complete(
+                &state,
+                &parts,
+                organization,
+                CompletedFile {
+                    kind: FileRequestKind::Index,
+                    path,
+                    size: 0,
+                    bytes_sent: 0,
+                    status: if missing {
+                        StatusCode::NOT_FOUND
+                    } else {
+                        StatusCode::OK
+                    },
+                    started,
+                },
+            )
+            .await;
+
+            let location = compact_str::format_compact!("/{}", path.to_string_lossy());
+
+            if listing::requested(&parts.headers) {
+                if missing {
+                    return ApiResponse::error("directory not found")
+                        .with_status(StatusCode::NOT_FOUND)
+                        .ok();
+                }
+
+                return listing::render(&location, &parts.uri, files)
+                    .map(|response| response.with_header("Vary", "Accept"));
+            }
+
+            if missing {
+                return render_not_found(&state, &location);
+            }
+
+            render(&state, &location, index_files)
+                .map(|response| response.with_header("Vary", "Accept"))
+        }))
+        .with_state(state.clone())
+}
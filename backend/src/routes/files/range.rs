@@ -0,0 +1,261 @@
+use crate::{models::file::File, routes::State};
+use axum::{
+    body::Bytes,
+    http::{
+        HeaderMap,
+        header::{IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, RANGE},
+    },
+};
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt};
+use std::{path::PathBuf, pin::Pin};
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Requests asking for more (merged) ranges than this are served in full instead.
+const MAX_RANGES: usize = 16;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    #[inline]
+    pub fn length(self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    #[inline]
+    pub fn content_range(self, size: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, size)
+    }
+}
+
+pub enum RangeRequest {
+    Full,
+    Partial(Vec<ByteRange>),
+    Unsatisfiable,
+}
+
+#[inline]
+pub fn format_http_date(date: DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+#[inline]
+fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value.trim())
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+#[inline]
+fn trim_etag(tag: &str) -> &str {
+    tag.trim().trim_start_matches("W/").trim_matches('"')
+}
+
+/// Evaluates `If-None-Match` (weak comparison) and, only when that is absent,
+/// `If-Modified-Since` against the file's validators.
+pub fn not_modified(headers: &HeaderMap, etag: &str, last_modified: Option<DateTime<Utc>>) -> bool {
+    if let Some(if_none_match) = headers.get(IF_NONE_MATCH) {
+        return if_none_match.to_str().is_ok_and(|value| {
+            value
+                .split(',')
+                .any(|tag| tag.trim() == "*" || trim_etag(tag) == etag)
+        });
+    }
+
+    if let Some(last_modified) = last_modified
+        && let Some(since) = headers
+            .get(IF_MODIFIED_SINCE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(parse_http_date)
+    {
+        return last_modified.timestamp() <= since.timestamp();
+    }
+
+    false
+}
+
+/// `If-Range` only lets the `Range` header apply when the validator still matches,
+/// entity tags are compared strongly and dates exactly.
+fn if_range_matches(headers: &HeaderMap, etag: &str, last_modified: Option<DateTime<Utc>>) -> bool {
+    let Some(if_range) = headers.get(IF_RANGE).and_then(|value| value.to_str().ok()) else {
+        return true;
+    };
+
+    if let Some(date) = parse_http_date(if_range) {
+        return last_modified
+            .is_some_and(|last_modified| last_modified.timestamp() == date.timestamp());
+    }
+
+    let if_range = if_range.trim();
+    !if_range.starts_with("W/") && if_range.trim_matches('"') == etag
+}
+
+pub fn parse_range(
+    headers: &HeaderMap,
+    size: u64,
+    etag: &str,
+    last_modified: Option<DateTime<Utc>>,
+) -> RangeRequest {
+    let Some(range) = headers.get(RANGE).and_then(|value| value.to_str().ok()) else {
+        return RangeRequest::Full;
+    };
+
+    if !if_range_matches(headers, etag, last_modified) {
+        return RangeRequest::Full;
+    }
+
+    let Some(specs) = range.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let Some((start, end)) = spec.split_once('-') else {
+            return RangeRequest::Full;
+        };
+
+        let range = match (start.trim(), end.trim()) {
+            ("", suffix) => {
+                let Ok(suffix) = suffix.parse::<u64>() else {
+                    return RangeRequest::Full;
+                };
+
+                if suffix == 0 || size == 0 {
+                    continue;
+                }
+
+                ByteRange {
+                    start: size.saturating_sub(suffix),
+                    end: size - 1,
+                }
+            }
+            (start, end) => {
+                let Ok(start) = start.parse::<u64>() else {
+                    return RangeRequest::Full;
+                };
+                let end = if end.is_empty() {
+                    u64::MAX
+                } else {
+                    match end.parse::<u64>() {
+                        Ok(end) if end >= start => end,
+                        _ => return RangeRequest::Full,
+                    }
+                };
+
+                if start >= size {
+                    continue;
+                }
+
+                ByteRange {
+                    start,
+                    end: end.min(size - 1),
+                }
+            }
+        };
+
+        ranges.push(range);
+    }
+
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        if let Some(last) = merged.last_mut()
+            && range.start <= last.end.saturating_add(1)
+        {
+            last.end = last.end.max(range.end);
+        } else {
+            merged.push(range);
+        }
+    }
+
+    if merged.len() > MAX_RANGES {
+        return RangeRequest::Full;
+    }
+
+    RangeRequest::Partial(merged)
+}
+
+#[inline]
+fn part_header(boundary: &str, content_type: &str, range: ByteRange, size: u64) -> String {
+    format!(
+        "\r\n--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: {}\r\n\r\n",
+        range.content_range(size)
+    )
+}
+
+#[inline]
+fn part_trailer(boundary: &str) -> String {
+    format!("\r\n--{boundary}--\r\n")
+}
+
+pub fn multipart_length(
+    ranges: &[ByteRange],
+    boundary: &str,
+    content_type: &str,
+    size: u64,
+) -> u64 {
+    ranges
+        .iter()
+        .map(|range| {
+            part_header(boundary, content_type, *range, size).len() as u64 + range.length()
+        })
+        .sum::<u64>()
+        + part_trailer(boundary).len() as u64
+}
+
+/// Streams a `multipart/byteranges` body. The reader for the first range is passed in
+/// already opened, every following range is opened lazily through the `FileCache`.
+#[allow(clippy::too_many_arguments)]
+pub fn multipart(
+    state: State,
+    path: PathBuf,
+    file: File,
+    first: Box<dyn AsyncRead + Send + Unpin>,
+    ranges: Vec<ByteRange>,
+    boundary: String,
+    content_type: &'static str,
+    size: u64,
+) -> Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>> {
+    let trailer = Bytes::from(part_trailer(&boundary));
+    let mut first = Some(first);
+
+    futures_util::stream::iter(ranges)
+        .then(move |range| {
+            let state = state.clone();
+            let path = path.clone();
+            let file = file.clone();
+            let reader = first.take();
+            let header = Bytes::from(part_header(&boundary, content_type, range, size));
+
+            async move {
+                let reader = match reader {
+                    Some(reader) => reader,
+                    None => match state.files.get(&path, &file, range.start).await {
+                        Ok((_, reader)) => reader,
+                        Err(err) => {
+                            return futures_util::stream::once(async move { Err::<Bytes, _>(err) })
+                                .boxed();
+                        }
+                    },
+                };
+
+                futures_util::stream::once(async move { Ok(header) })
+                    .chain(tokio_util::io::ReaderStream::new(
+                        reader.take(range.length()),
+                    ))
+                    .boxed()
+            }
+        })
+        .flatten()
+        .chain(futures_util::stream::once(async move { Ok(trailer) }))
+        .boxed()
+}
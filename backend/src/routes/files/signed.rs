@@ -0,0 +1,124 @@
+use axum::http::{HeaderMap, Uri};
+use hmac::{Hmac, Mac, digest::KeyInit};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC};
+use serde::Deserialize;
+use std::net::IpAddr;
+
+type HmacSha256 = Hmac<sha2::Sha256>;
+
+/// Everything but the unreserved characters of RFC 3986.
+const PATH_SEGMENT: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+#[derive(Deserialize)]
+struct Params {
+    organization: Option<i32>,
+    expires: Option<i64>,
+    ip: Option<u8>,
+    signature: Option<String>,
+}
+
+/// `<organization>\n<path>\n<expires>\n<ip or empty>`, `path` is percent-decoded and
+/// includes the `/files` prefix.
+fn mac(
+    secret: &str,
+    organization_id: i32,
+    path: &str,
+    expires: i64,
+    ip: Option<IpAddr>,
+) -> HmacSha256 {
+    let mut mac = <HmacSha256 as KeyInit>::new_from_slice(secret.as_bytes())
+        .expect("hmac accepts keys of any length");
+    mac.update(
+        format!(
+            "{organization_id}\n{path}\n{expires}\n{}",
+            ip.map(|ip| ip.to_string()).unwrap_or_default()
+        )
+        .as_bytes(),
+    );
+
+    mac
+}
+
+/// Percent-encodes every segment of `path` for the url of a signed download.
+pub fn encode_path(path: &str) -> String {
+    path.split('/')
+        .map(|segment| percent_encoding::utf8_percent_encode(segment, PATH_SEGMENT).to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Builds the query string for a signed download of `path`, `None` when no
+/// `FILES_SIGNING_SECRET` is configured.
+pub fn sign(
+    env: &crate::env::Env,
+    organization_id: i32,
+    path: &str,
+    expires: i64,
+    ip: Option<IpAddr>,
+) -> Option<String> {
+    let secret = env.files_signing_secret.as_deref()?;
+    let signature = hex::encode(
+        mac(secret, organization_id, path, expires, ip)
+            .finalize()
+            .into_bytes(),
+    );
+
+    Some(format!(
+        "organization={organization_id}&expires={expires}{}&signature={signature}",
+        if ip.is_some() { "&ip=1" } else { "" }
+    ))
+}
+
+/// Checks the signature of a `/files` request. `Ok(None)` when the request is not signed,
+/// otherwise the organization the download is attributed to.
+pub fn verify(
+    env: &crate::env::Env,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Result<Option<i32>, &'static str> {
+    let Ok(axum::extract::Query(params)) = axum::extract::Query::<Params>::try_from_uri(uri) else {
+        return Ok(None);
+    };
+
+    let Some(signature) = params.signature else {
+        return Ok(None);
+    };
+
+    let (Some(secret), Some(organization_id), Some(expires)) = (
+        env.files_signing_secret.as_deref(),
+        params.organization,
+        params.expires,
+    ) else {
+        return Err("invalid download signature");
+    };
+
+    if expires < chrono::Utc::now().timestamp() {
+        return Err("download link has expired");
+    }
+
+    let ip = match params.ip {
+        Some(1) => match crate::utils::extract_ip(headers) {
+            Some(ip) => Some(ip),
+            None => return Err("invalid download signature"),
+        },
+        _ => None,
+    };
+
+    let Ok(signature) = hex::decode(signature) else {
+        return Err("invalid download signature");
+    };
+
+    let Ok(path) = percent_encoding::percent_decode_str(uri.path()).decode_utf8() else {
+        return Err("invalid download signature");
+    };
+
+    mac(secret, organization_id, &path, expires, ip)
+        .verify_slice(&signature)
+        .map_err(|_| "invalid download signature")?;
+
+    Ok(Some(organization_id))
+}
@@ -15,9 +15,12 @@ pub fn router(state: &State) -> OpenApiRouter<State> {
                     if let Some((location, version)) =
                         Version::resolve(&state.database, &state.cache, r#type, &version).await?
                     {
+                        let options =
+                            crate::cache::CacheOptions::new(1800).tag(format!("type:{type}"));
+
                         let data = state
                             .cache
-                            .cached(&format!("builds::{type}::{version}"), 1800, || {
+                            .cached_with(&format!("builds::{type}::{version}"), options, || {
                                 Build::all_by_version(&state.database, r#type, &location, &version)
                             })
                             .await?;
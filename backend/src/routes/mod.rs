@@ -1,4 +1,7 @@
-use crate::{models::organization::Organization, response::ApiResponse};
+use crate::{
+    env::RequestBodyCapture, models::organization::Organization, requests::CapturedBody,
+    response::ApiResponse,
+};
 use axum::{
     body::Body,
     extract::Request,
@@ -15,7 +18,7 @@ use utoipa::ToSchema;
 use utoipa_axum::router::OpenApiRouter;
 
 mod api;
-mod files;
+pub mod files;
 mod index;
 
 #[derive(ToSchema, Serialize)]
@@ -78,28 +81,54 @@ pub type GetData = axum::extract::Extension<Arc<Mutex<serde_json::Value>>>;
 
 async fn handle_api_request(state: GetState, req: Request, next: Next) -> Response<Body> {
     let mut organization: Option<Organization> = None;
+    let mut key_id: Option<String> = None;
     if let Some(authorization) = req.headers().get("Authorization")
         && let Ok(authorization) = authorization.to_str()
         && authorization.len() == 64
         && let Ok(org) = Organization::by_key(&state.database, &state.cache, authorization).await
     {
+        key_id = org
+            .as_ref()
+            .and(authorization.get(0..16))
+            .map(str::to_string);
         organization = org;
     }
 
+    let mut signed = false;
+    if organization.is_none() && req.uri().path().starts_with("/files/") {
+        match files::signed::verify(&state.env, req.uri(), req.headers()) {
+            Ok(Some(organization_id)) => {
+                if let Ok(Some(org)) =
+                    Organization::by_id(&state.database, &state.cache, organization_id).await
+                {
+                    organization = Some(org);
+                    signed = true;
+                }
+            }
+            Ok(None) => {}
+            Err(err) => {
+                return ApiResponse::error(err)
+                    .with_status(StatusCode::FORBIDDEN)
+                    .into_response();
+            }
+        }
+    }
+
     let (parts, body) = req.into_parts();
-    let request_id = state.requests.log(&parts, organization.as_ref()).await;
+    let request_id = state
+        .requests
+        .log(&parts, organization.as_ref(), key_id.as_deref(), signed)
+        .await;
 
     if let Err(Some(ratelimit)) = request_id {
-        return ApiResponse::error("too many requests")
+        let mut response = ApiResponse::error("too many requests")
             .with_status(StatusCode::TOO_MANY_REQUESTS)
-            .with_header("X-RateLimit-Limit", &ratelimit.limit.to_string())
-            .with_header(
-                "X-RateLimit-Remaining",
-                &(ratelimit.limit - ratelimit.hits).max(0).to_string(),
-            )
-            .with_header("X-RateLimit-Reset", &ratelimit.reset.to_string())
-            .with_header("Retry-After", &ratelimit.reset.to_string())
-            .into_response();
+            .with_header("Retry-After", &ratelimit.retry_after.to_string());
+        for (name, value) in ratelimit.headers() {
+            response = response.with_header(name, &value);
+        }
+
+        return response.into_response();
     } else if let Err(None) = request_id {
         return ApiResponse::error("broken request, likely invalid IP")
             .with_status(StatusCode::BAD_REQUEST)
@@ -113,22 +142,9 @@ async fn handle_api_request(state: GetState, req: Request, next: Next) -> Respon
     }
 
     if let Some(ratelimit) = ratelimit {
-        headers.insert(
-            "X-RateLimit-Limit",
-            ratelimit.limit.to_string().parse().unwrap(),
-        );
-        headers.insert(
-            "X-RateLimit-Remaining",
-            (ratelimit.limit - ratelimit.hits)
-                .max(0)
-                .to_string()
-                .parse()
-                .unwrap(),
-        );
-        headers.insert(
-            "X-RateLimit-Reset",
-            ratelimit.reset.to_string().parse().unwrap(),
-        );
+        for (name, value) in ratelimit.headers() {
+            headers.insert(name, value.parse().unwrap());
+        }
     }
 
     let mut req = Request::from_parts(parts, body);
@@ -136,6 +152,13 @@ async fn handle_api_request(state: GetState, req: Request, next: Next) -> Respon
     req.extensions_mut().insert(data.clone());
     req.extensions_mut().insert(organization);
 
+    let captured_body = (request_id.is_some()
+        && state.env.request_body_capture != RequestBodyCapture::None)
+        .then(|| CapturedBody::new(state.env.request_body_max_size));
+    if let Some(captured_body) = &captured_body {
+        req.extensions_mut().insert(captured_body.clone());
+    }
+
     let start = Instant::now();
     let mut response = next.run(req).await;
 
@@ -146,16 +169,13 @@ async fn handle_api_request(state: GetState, req: Request, next: Next) -> Respon
             None
         };
 
-        state
-            .requests
-            .finish(
-                request_id,
-                response.status().as_u16() as i16,
-                start.elapsed().as_millis() as i32,
-                data,
-                None,
-            )
-            .await;
+        state.requests.finish(
+            request_id,
+            response.status().as_u16() as i16,
+            start.elapsed().as_millis() as i32,
+            data,
+            captured_body.and_then(|body| body.finish(state.env.request_body_capture)),
+        );
     }
 
     response.headers_mut().extend(headers);
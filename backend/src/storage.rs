@@ -0,0 +1,234 @@
+use chrono::{DateTime, Utc};
+use futures_util::future::BoxFuture;
+use std::{
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncRead, AsyncSeekExt, AsyncWrite, DuplexStream, ReadBuf};
+
+pub type StorageReader = Box<dyn AsyncRead + Send + Unpin>;
+
+type PipeError = Arc<Mutex<Option<std::io::Error>>>;
+
+/// An in-memory pipe for readers fed by a spawned task. An error reported through
+/// [`PipeWriter::fail`] reaches the reader in place of the end of the stream, so a
+/// truncated body is never mistaken for a complete one.
+pub fn pipe(max_buf_size: usize) -> (PipeReader, PipeWriter) {
+    let (reader, writer) = tokio::io::duplex(max_buf_size);
+    let error = PipeError::default();
+
+    (
+        PipeReader {
+            reader,
+            error: error.clone(),
+        },
+        PipeWriter { writer, error },
+    )
+}
+
+pub struct PipeReader {
+    reader: DuplexStream,
+    error: PipeError,
+}
+
+impl AsyncRead for PipeReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let filled = buf.filled().len();
+
+        match Pin::new(&mut self.reader).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) if buf.filled().len() == filled && buf.remaining() > 0 => {
+                match self.error.lock().unwrap().take() {
+                    Some(err) => Poll::Ready(Err(err)),
+                    None => Poll::Ready(Ok(())),
+                }
+            }
+            poll => poll,
+        }
+    }
+}
+
+pub struct PipeWriter {
+    writer: DuplexStream,
+    error: PipeError,
+}
+
+impl PipeWriter {
+    /// Ends the stream with `err`.
+    pub fn fail(self, err: std::io::Error) {
+        *self.error.lock().unwrap() = Some(err);
+    }
+}
+
+impl AsyncWrite for PipeWriter {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.writer).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.writer).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.writer).poll_shutdown(cx)
+    }
+}
+
+/// Where the `/files` tree is read from. The `FileCache` fills and bypasses through this,
+/// so nodes without the mirror on local disk can still serve files.
+pub trait Storage: Send + Sync {
+    /// Opens the file at `path`, positioned at `offset` bytes. Missing files must fail
+    /// with `std::io::ErrorKind::NotFound`.
+    fn open<'a>(
+        &'a self,
+        path: &'a Path,
+        offset: u64,
+    ) -> BoxFuture<'a, std::io::Result<StorageReader>>;
+
+    fn modified<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Option<DateTime<Utc>>>;
+}
+
+pub fn from_env(env: &crate::env::Env, s3: Arc<crate::s3::S3>) -> Arc<dyn Storage> {
+    match env.files_storage {
+        crate::env::FilesStorage::Local => Arc::new(LocalStorage {
+            root: PathBuf::from(&env.files_location),
+        }),
+        crate::env::FilesStorage::S3 => Arc::new(S3Storage::new(s3, &env.files_s3_prefix)),
+    }
+}
+
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl Storage for LocalStorage {
+    fn open<'a>(
+        &'a self,
+        path: &'a Path,
+        offset: u64,
+    ) -> BoxFuture<'a, std::io::Result<StorageReader>> {
+        Box::pin(async move {
+            let mut file = tokio::fs::File::open(self.root.join(path)).await?;
+            if offset > 0 {
+                file.seek(std::io::SeekFrom::Start(offset)).await?;
+            }
+
+            Ok(Box::new(file) as StorageReader)
+        })
+    }
+
+    fn modified<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Option<DateTime<Utc>>> {
+        Box::pin(async move {
+            let metadata = tokio::fs::metadata(self.root.join(path)).await.ok()?;
+
+            metadata.modified().ok().map(Into::into)
+        })
+    }
+}
+
+/// Reads objects from the configured S3 bucket, works with any S3-compatible
+/// server (e.g. MinIO with `S3_PATH_STYLE=true`).
+pub struct S3Storage {
+    s3: Arc<crate::s3::S3>,
+    prefix: String,
+}
+
+impl S3Storage {
+    /// Reads objects under `prefix` in the bucket of `s3`, without leading or
+    /// trailing slashes.
+    pub fn new(s3: Arc<crate::s3::S3>, prefix: &str) -> Self {
+        Self {
+            s3,
+            prefix: prefix.trim_matches('/').to_string(),
+        }
+    }
+
+    fn key(&self, path: &Path) -> String {
+        let path = path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if self.prefix.is_empty() {
+            path
+        } else {
+            format!("{}/{}", self.prefix, path)
+        }
+    }
+
+    fn map_error(err: s3::error::S3Error) -> std::io::Error {
+        match err {
+            s3::error::S3Error::HttpFailWithBody(404, _) => {
+                std::io::Error::new(std::io::ErrorKind::NotFound, "object not found")
+            }
+            err => std::io::Error::other(err),
+        }
+    }
+}
+
+impl Storage for S3Storage {
+    fn open<'a>(
+        &'a self,
+        path: &'a Path,
+        offset: u64,
+    ) -> BoxFuture<'a, std::io::Result<StorageReader>> {
+        Box::pin(async move {
+            let key = self.key(path);
+
+            let (_, status) = self
+                .s3
+                .bucket
+                .head_object(&key)
+                .await
+                .map_err(Self::map_error)?;
+            if status == 404 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "object not found",
+                ));
+            }
+
+            let (reader, mut writer) = pipe(64 * 1024);
+            let s3 = self.s3.clone();
+
+            tokio::spawn(async move {
+                let err = match s3
+                    .bucket
+                    .get_object_range_to_writer(&key, offset, None, &mut writer)
+                    .await
+                {
+                    Ok(status) if status >= 300 => {
+                        std::io::Error::other(format!("s3 responded with status {status}"))
+                    }
+                    Ok(_) => return,
+                    Err(err) => Self::map_error(err),
+                };
+
+                tracing::error!("failed to stream s3 object {}: {:?}", key, err);
+                writer.fail(err);
+            });
+
+            Ok(Box::new(reader) as StorageReader)
+        })
+    }
+
+    fn modified<'a>(&'a self, path: &'a Path) -> BoxFuture<'a, Option<DateTime<Utc>>> {
+        Box::pin(async move {
+            let (head, _) = self.s3.bucket.head_object(self.key(path)).await.ok()?;
+
+            DateTime::parse_from_rfc2822(&head.last_modified?)
+                .ok()
+                .map(|date| date.with_timezone(&Utc))
+        })
+    }
+}
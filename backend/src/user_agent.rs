@@ -0,0 +1,208 @@
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(ToSchema, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[schema(rename_all = "lowercase")]
+pub enum ClientCategory {
+    Unknown,
+    Cli,
+    Panel,
+    Browser,
+    Bot,
+    Sdk,
+    Library,
+}
+
+impl ClientCategory {
+    #[inline]
+    pub fn as_i8(self) -> i8 {
+        match self {
+            Self::Unknown => 0,
+            Self::Cli => 1,
+            Self::Panel => 2,
+            Self::Browser => 3,
+            Self::Bot => 4,
+            Self::Sdk => 5,
+            Self::Library => 6,
+        }
+    }
+
+    #[inline]
+    pub fn from_i8(value: i8) -> Self {
+        match value {
+            1 => Self::Cli,
+            2 => Self::Panel,
+            3 => Self::Browser,
+            4 => Self::Bot,
+            5 => Self::Sdk,
+            6 => Self::Library,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+/// Requests and downloads of one client family, as aggregated by the stats endpoints.
+/// Rows logged before clients were classified are not backfilled and count as
+/// `unknown`/`Other`.
+#[derive(ToSchema, Serialize, Deserialize)]
+pub struct ClientStats {
+    pub category: ClientCategory,
+    pub family: String,
+    pub requests: u64,
+}
+
+/// A `(client_category, client_family, requests)` row of ClickHouse.
+impl From<(i8, String, u64)> for ClientStats {
+    #[inline]
+    fn from((category, family, requests): (i8, String, u64)) -> Self {
+        Self {
+            category: ClientCategory::from_i8(category),
+            family,
+            requests,
+        }
+    }
+}
+
+pub struct Client {
+    pub category: ClientCategory,
+    pub family: &'static str,
+    pub version: String,
+}
+
+/// Product names matched case-insensitively against every `name/version` token of a
+/// user agent, including the ones inside comments. The first rule with a matching token
+/// wins, so more specific clients come before the libraries and browsers they are built on.
+const RULES: &[(&str, &str, ClientCategory)] = &[
+    ("mcvcli", "mcvcli", ClientCategory::Sdk),
+    ("mirror-sync", "mirror-sync", ClientCategory::Sdk),
+    ("mcjars", "MCJars", ClientCategory::Sdk),
+    ("pterodactyl", "Pterodactyl", ClientCategory::Panel),
+    ("pelican", "Pelican", ClientCategory::Panel),
+    ("pufferpanel", "PufferPanel", ClientCategory::Panel),
+    ("crafty", "Crafty Controller", ClientCategory::Panel),
+    ("mcsmanager", "MCSManager", ClientCategory::Panel),
+    ("multicraft", "Multicraft", ClientCategory::Panel),
+    ("amp", "AMP", ClientCategory::Panel),
+    ("calagopus", "Calagopus", ClientCategory::Panel),
+    ("curl", "curl", ClientCategory::Cli),
+    ("wget", "Wget", ClientCategory::Cli),
+    ("wget2", "Wget", ClientCategory::Cli),
+    ("httpie", "HTTPie", ClientCategory::Cli),
+    ("windowspowershell", "PowerShell", ClientCategory::Cli),
+    ("powershell", "PowerShell", ClientCategory::Cli),
+    ("aria2", "aria2", ClientCategory::Cli),
+    (
+        "python-requests",
+        "python-requests",
+        ClientCategory::Library,
+    ),
+    ("aiohttp", "aiohttp", ClientCategory::Library),
+    ("python-httpx", "httpx", ClientCategory::Library),
+    ("python-urllib", "urllib", ClientCategory::Library),
+    ("axios", "axios", ClientCategory::Library),
+    ("node-fetch", "node-fetch", ClientCategory::Library),
+    ("undici", "undici", ClientCategory::Library),
+    ("got", "got", ClientCategory::Library),
+    ("node", "Node.js", ClientCategory::Library),
+    ("bun", "Bun", ClientCategory::Library),
+    ("deno", "Deno", ClientCategory::Library),
+    ("guzzlehttp", "Guzzle", ClientCategory::Library),
+    ("go-http-client", "Go", ClientCategory::Library),
+    ("okhttp", "OkHttp", ClientCategory::Library),
+    ("java-http-client", "Java", ClientCategory::Library),
+    ("java", "Java", ClientCategory::Library),
+    (
+        "apache-httpclient",
+        "Apache HttpClient",
+        ClientCategory::Library,
+    ),
+    ("dart", "Dart", ClientCategory::Library),
+    ("reqwest", "reqwest", ClientCategory::Library),
+    ("edg", "Edge", ClientCategory::Browser),
+    ("opr", "Opera", ClientCategory::Browser),
+    ("firefox", "Firefox", ClientCategory::Browser),
+    ("chrome", "Chrome", ClientCategory::Browser),
+    ("safari", "Safari", ClientCategory::Browser),
+];
+
+const MAX_VERSION_LENGTH: usize = 31;
+
+/// Splits a user agent into `(name, version)` product tokens, comments included.
+fn tokens(user_agent: &str) -> impl Iterator<Item = (&str, &str)> {
+    user_agent
+        .split(|c: char| c.is_whitespace() || matches!(c, '(' | ')' | ';' | ','))
+        .filter(|token| !token.is_empty())
+        .map(|token| token.split_once('/').unwrap_or((token, "")))
+}
+
+fn is_bot(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+
+    name.contains("bot") || name.contains("spider") || name.contains("crawler")
+}
+
+/// Classifies a `User-Agent` header into the client family that sent it.
+pub fn classify(user_agent: &str) -> Client {
+    let version = |version: &str| {
+        crate::utils::slice_up_to(
+            version.trim_matches(|c: char| !c.is_alphanumeric()),
+            MAX_VERSION_LENGTH,
+        )
+        .to_string()
+    };
+
+    // crawlers usually pretend to be a browser, so they are recognized first
+    if let Some((name, bot_version)) = tokens(user_agent).find(|(name, _)| is_bot(name)) {
+        let family = match name.to_ascii_lowercase().as_str() {
+            "googlebot" => "Googlebot",
+            "bingbot" => "Bingbot",
+            "applebot" => "Applebot",
+            "duckduckbot" => "DuckDuckBot",
+            "yandexbot" => "YandexBot",
+            "gptbot" => "GPTBot",
+            "claudebot" => "ClaudeBot",
+            "ahrefsbot" => "AhrefsBot",
+            "semrushbot" => "SemrushBot",
+            "discordbot" => "Discordbot",
+            "twitterbot" => "Twitterbot",
+            _ => "Other",
+        };
+
+        return Client {
+            category: ClientCategory::Bot,
+            family,
+            version: version(bot_version),
+        };
+    }
+
+    for (rule, family, category) in RULES {
+        let Some((_, token_version)) =
+            tokens(user_agent).find(|(name, _)| name.eq_ignore_ascii_case(rule))
+        else {
+            continue;
+        };
+
+        // safari reports its engine after the product, the release is in `Version/`
+        let token_version = if *rule == "safari" {
+            tokens(user_agent)
+                .find(|(name, _)| *name == "Version")
+                .map(|(_, v)| v)
+                .unwrap_or(token_version)
+        } else {
+            token_version
+        };
+
+        return Client {
+            category: *category,
+            family,
+            version: version(token_version),
+        };
+    }
+
+    Client {
+        category: ClientCategory::Unknown,
+        family: "Other",
+        version: String::new(),
+    }
+}
@@ -1,9 +1,12 @@
+use crate::env::IpAnonymization;
 use axum::http::HeaderMap;
 use compact_str::ToCompactString;
 use garde::Validate;
+use hmac::{Hmac, Mac, digest::KeyInit};
+use ipnetwork::{IpNetwork, Ipv4Network, Ipv6Network};
 use serde::Serialize;
 use sha2::Digest;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv6Addr};
 
 #[inline]
 pub fn cache_key_hash(data: &str) -> String {
@@ -16,24 +19,101 @@ pub fn is_hex(data: &str, length: usize) -> bool {
 }
 
 #[inline]
+/// The client address resolved by [`client_ip`] when the request entered the router,
+/// which replaces whatever `x-real-ip` the client sent.
 pub fn extract_ip(headers: &HeaderMap) -> Option<IpAddr> {
-    let ip = headers
+    headers
         .get("x-real-ip")
-        .or_else(|| headers.get("x-forwarded-for"))
-        .map(|ip| ip.to_str().unwrap_or_default())
-        .unwrap_or_default();
+        .and_then(|ip| ip.to_str().ok())
+        .and_then(|ip| ip.trim().parse().ok())
+}
+
+/// Client address of a request received from `peer`. Forwarding headers are only
+/// believed when `peer` is a trusted proxy: `x-forwarded-for` is read from right to
+/// left and the first address that is not a trusted proxy is the client, falling back
+/// to `x-real-ip` when no proxy appended to `x-forwarded-for`.
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpNetwork]) -> IpAddr {
+    let is_trusted = |ip: IpAddr| trusted_proxies.iter().any(|network| network.contains(ip));
 
-    if ip.is_empty() {
-        return None;
+    let peer = peer.to_canonical();
+    if !is_trusted(peer) {
+        return peer;
     }
 
-    let ip = if ip.contains(',') {
-        ip.split(',').next().unwrap_or_default().trim().to_string()
-    } else {
-        ip.to_string()
-    };
+    let mut client = None;
+    for value in headers.get_all("x-forwarded-for").iter().rev() {
+        let Ok(value) = value.to_str() else {
+            return client.unwrap_or(peer);
+        };
+
+        for ip in value.rsplit(',') {
+            let Ok(ip) = ip.trim().parse::<IpAddr>() else {
+                return client.unwrap_or(peer);
+            };
+            let ip = ip.to_canonical();
+
+            if !is_trusted(ip) {
+                return ip;
+            }
+
+            client = Some(ip);
+        }
+    }
 
-    ip.parse().ok()
+    client
+        .or_else(|| {
+            headers
+                .get("x-real-ip")
+                .and_then(|ip| ip.to_str().ok())
+                .and_then(|ip| ip.trim().parse::<IpAddr>().ok())
+                .map(|ip| ip.to_canonical())
+        })
+        .unwrap_or(peer)
+}
+
+/// IPv6 clients usually hold a whole prefix, so they are limited by their network
+/// rather than by a single address.
+pub fn ratelimit_subject(ip: IpAddr, ipv6_prefix: u8) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => match Ipv6Network::new(ip, ipv6_prefix) {
+            Ok(network) => format!("{}/{ipv6_prefix}", network.network()),
+            Err(_) => ip.to_string(),
+        },
+    }
+}
+
+/// Applies `IP_ANONYMIZATION` to `ip` before it is stored. Hashed addresses are keyed
+/// so they cannot be reversed by hashing every address, and are placed in `fd00::/8` so
+/// they are never mistaken for (or located as) a real client.
+pub fn anonymize_ip(ip: IpAddr, anonymization: &IpAnonymization) -> IpAddr {
+    let ip = ip.to_canonical();
+
+    match anonymization {
+        IpAnonymization::None => ip,
+        IpAnonymization::Truncate {
+            ipv4_prefix,
+            ipv6_prefix,
+        } => match ip {
+            IpAddr::V4(ip) => Ipv4Network::new(ip, *ipv4_prefix)
+                .map(|network| network.network().into())
+                .unwrap_or(ip.into()),
+            IpAddr::V6(ip) => Ipv6Network::new(ip, *ipv6_prefix)
+                .map(|network| network.network().into())
+                .unwrap_or(ip.into()),
+        },
+        IpAnonymization::Hash { key } => {
+            let mut mac = <Hmac<sha2::Sha256> as KeyInit>::new_from_slice(key.as_bytes())
+                .expect("hmac accepts keys of any length");
+            mac.update(ip.to_string().as_bytes());
+
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&mac.finalize().into_bytes()[..16]);
+            octets[0] = 0xfd;
+
+            Ipv6Addr::from(octets).into()
+        }
+    }
 }
 
 #[inline]
@@ -0,0 +1,98 @@
+//! Round trips objects through `S3Storage` against a real S3-compatible server.
+//! These are ignored by default, start MinIO and run them with
+//!
+//! ```bash
+//! docker compose -f backend/tests/docker-compose.yml up -d
+//! cargo test --test s3_storage -- --ignored
+//! ```
+//!
+//! `TEST_S3_ENDPOINT`, `TEST_S3_BUCKET`, `TEST_S3_ACCESS_KEY` and `TEST_S3_SECRET_KEY`
+//! point them at another server.
+
+use api::storage::{S3Storage, Storage};
+use std::{path::Path, sync::Arc};
+use tokio::io::AsyncReadExt;
+
+fn var(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or(default.to_string())
+}
+
+fn s3() -> Arc<api::s3::S3> {
+    let endpoint = var("TEST_S3_ENDPOINT", "http://localhost:9100");
+
+    let mut bucket = s3::Bucket::new(
+        &var("TEST_S3_BUCKET", "mcjars-test"),
+        s3::Region::Custom {
+            region: "us-east-1".to_string(),
+            endpoint: endpoint.clone(),
+        },
+        s3::creds::Credentials::new(
+            Some(&var("TEST_S3_ACCESS_KEY", "minioadmin")),
+            Some(&var("TEST_S3_SECRET_KEY", "minioadmin")),
+            None,
+            None,
+            None,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    bucket.set_path_style();
+
+    Arc::new(api::s3::S3 {
+        public_url: endpoint,
+        bucket,
+    })
+}
+
+async fn read(storage: &S3Storage, path: &str, offset: u64) -> std::io::Result<Vec<u8>> {
+    let mut reader = storage.open(Path::new(path), offset).await?;
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await?;
+
+    Ok(data)
+}
+
+#[tokio::test]
+#[ignore = "needs an S3-compatible server, see tests/docker-compose.yml"]
+async fn round_trip() {
+    let s3 = s3();
+    let storage = S3Storage::new(s3.clone(), "/storage-test/");
+
+    // larger than the pipe buffer, so the reader has to keep up with the download
+    let content = (0..256 * 1024u32)
+        .map(|i| (i % 251) as u8)
+        .collect::<Vec<_>>();
+    s3.bucket
+        .put_object("storage-test/PAPER/1.21.4/server.jar", &content)
+        .await
+        .unwrap();
+
+    let data = read(&storage, "PAPER/1.21.4/server.jar", 0).await.unwrap();
+    assert_eq!(data, content);
+
+    let data = read(&storage, "PAPER/1.21.4/server.jar", 100_000)
+        .await
+        .unwrap();
+    assert_eq!(data, content[100_000..]);
+
+    assert!(
+        storage
+            .modified(Path::new("PAPER/1.21.4/server.jar"))
+            .await
+            .is_some()
+    );
+
+    s3.bucket
+        .delete_object("storage-test/PAPER/1.21.4/server.jar")
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+#[ignore = "needs an S3-compatible server, see tests/docker-compose.yml"]
+async fn missing_object() {
+    let storage = S3Storage::new(s3(), "storage-test");
+
+    let err = read(&storage, "PAPER/missing.jar", 0).await.unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+}
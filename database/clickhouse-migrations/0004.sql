@@ -0,0 +1,2 @@
+ALTER TABLE file_requests
+	MODIFY COLUMN cache_hit Enum8('miss' = 0, 'hit' = 1, 'bypass' = 2);
@@ -0,0 +1,2 @@
+ALTER TABLE file_requests
+	MODIFY COLUMN kind Enum8('index' = 1, 'file' = 2, 'checksums' = 3, 'archive' = 4);
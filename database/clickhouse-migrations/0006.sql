@@ -0,0 +1,4 @@
+ALTER TABLE requests
+	ADD COLUMN `client_category` Enum8('unknown' = 0, 'cli' = 1, 'panel' = 2, 'browser' = 3, 'bot' = 4, 'sdk' = 5, 'library' = 6) DEFAULT 'unknown' AFTER user_agent,
+	ADD COLUMN `client_family` LowCardinality(String) DEFAULT 'Other' AFTER client_category,
+	ADD COLUMN `client_version` LowCardinality(String) DEFAULT '' AFTER client_family;
@@ -0,0 +1,42 @@
+CREATE TABLE "file_changes" (
+	"id" bigserial PRIMARY KEY NOT NULL,
+	"path" varchar(255)[] NOT NULL,
+	"deleted" boolean DEFAULT false NOT NULL,
+	"size" integer,
+	"sha1" "bytea",
+	"sha224" "bytea",
+	"sha256" "bytea",
+	"sha384" "bytea",
+	"sha512" "bytea",
+	"md5" "bytea",
+	"created" timestamp DEFAULT now() NOT NULL
+);
+--> statement-breakpoint
+CREATE INDEX "fileChanges_created_idx" ON "file_changes" USING btree ("created");--> statement-breakpoint
+CREATE OR REPLACE FUNCTION files_changes() RETURNS trigger AS $$
+BEGIN
+	IF TG_OP = 'DELETE' OR (TG_OP = 'UPDATE' AND OLD.path IS DISTINCT FROM NEW.path) THEN
+		INSERT INTO file_changes (path, deleted) VALUES (OLD.path, true);
+	END IF;
+
+	IF TG_OP = 'DELETE' THEN
+		RETURN OLD;
+	END IF;
+
+	IF TG_OP = 'UPDATE'
+		AND OLD.path IS NOT DISTINCT FROM NEW.path
+		AND OLD.size IS NOT DISTINCT FROM NEW.size
+		AND OLD.sha256 IS NOT DISTINCT FROM NEW.sha256
+		AND OLD.sha512 IS NOT DISTINCT FROM NEW.sha512
+	THEN
+		RETURN NEW;
+	END IF;
+
+	INSERT INTO file_changes (path, size, sha1, sha224, sha256, sha384, sha512, md5)
+	VALUES (NEW.path, NEW.size, NEW.sha1, NEW.sha224, NEW.sha256, NEW.sha384, NEW.sha512, NEW.md5);
+
+	RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;--> statement-breakpoint
+CREATE TRIGGER files_changes_trigger AFTER INSERT OR UPDATE OR DELETE ON "files" FOR EACH ROW EXECUTE FUNCTION files_changes();--> statement-breakpoint
+INSERT INTO "file_changes" ("path", "size", "sha1", "sha224", "sha256", "sha384", "sha512", "md5") SELECT "path", "size", "sha1", "sha224", "sha256", "sha384", "sha512", "md5" FROM "files" ORDER BY "path";
\ No newline at end of file
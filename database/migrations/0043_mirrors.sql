@@ -0,0 +1,14 @@
+CREATE TABLE "mirrors" (
+	"id" serial PRIMARY KEY NOT NULL,
+	"name" varchar(63) NOT NULL,
+	"url" varchar(255) NOT NULL,
+	"continent" char(2) NOT NULL,
+	"country" char(2),
+	"enabled" boolean DEFAULT true NOT NULL,
+	"healthy" boolean DEFAULT false NOT NULL,
+	"last_check" timestamp,
+	"created" timestamp DEFAULT now() NOT NULL
+);
+--> statement-breakpoint
+CREATE UNIQUE INDEX "mirrors_url_idx" ON "mirrors" USING btree ("url");--> statement-breakpoint
+CREATE INDEX "mirrors_continent_idx" ON "mirrors" USING btree ("continent");
\ No newline at end of file
@@ -0,0 +1,24 @@
+CREATE TABLE "ratelimit_policies" (
+	"id" serial PRIMARY KEY NOT NULL,
+	"organization_id" integer,
+	"organization_key_id" integer,
+	"route_group" varchar(31),
+	"burst" integer NOT NULL,
+	"rate" integer NOT NULL,
+	"period" integer DEFAULT 60 NOT NULL,
+	"created" timestamp DEFAULT now() NOT NULL
+);
+--> statement-breakpoint
+CREATE TABLE "ratelimit_costs" (
+	"id" serial PRIMARY KEY NOT NULL,
+	"route_group" varchar(31) NOT NULL,
+	"path" varchar(255) NOT NULL,
+	"cost" integer DEFAULT 1 NOT NULL,
+	"created" timestamp DEFAULT now() NOT NULL
+);
+--> statement-breakpoint
+ALTER TABLE "ratelimit_policies" ADD CONSTRAINT "ratelimit_policies_organization_id_organizations_id_fk" FOREIGN KEY ("organization_id") REFERENCES "public"."organizations"("id") ON DELETE cascade ON UPDATE no action;--> statement-breakpoint
+ALTER TABLE "ratelimit_policies" ADD CONSTRAINT "ratelimit_policies_organization_key_id_organization_keys_id_fk" FOREIGN KEY ("organization_key_id") REFERENCES "public"."organization_keys"("id") ON DELETE cascade ON UPDATE no action;--> statement-breakpoint
+CREATE INDEX "ratelimitPolicies_organization_idx" ON "ratelimit_policies" USING btree ("organization_id");--> statement-breakpoint
+CREATE INDEX "ratelimitPolicies_organization_key_idx" ON "ratelimit_policies" USING btree ("organization_key_id");--> statement-breakpoint
+CREATE UNIQUE INDEX "ratelimitCosts_route_group_path_idx" ON "ratelimit_costs" USING btree ("route_group","path");
@@ -0,0 +1,31 @@
+CREATE INDEX "fileChanges_path_idx" ON "file_changes" USING btree ("path");--> statement-breakpoint
+CREATE OR REPLACE FUNCTION files_changes() RETURNS trigger AS $$
+BEGIN
+	IF TG_OP = 'UPDATE'
+		AND OLD.path IS NOT DISTINCT FROM NEW.path
+		AND OLD.size IS NOT DISTINCT FROM NEW.size
+		AND OLD.sha256 IS NOT DISTINCT FROM NEW.sha256
+		AND OLD.sha512 IS NOT DISTINCT FROM NEW.sha512
+	THEN
+		RETURN NEW;
+	END IF;
+
+	-- ids are taken from the sequence at insert time, so writers are serialized until
+	-- they commit. Otherwise a slower transaction could commit an id below a cursor a
+	-- mirror already stored, and that change would never be delivered.
+	PERFORM pg_advisory_xact_lock(hashtext('file_changes'));
+
+	IF TG_OP = 'DELETE' OR (TG_OP = 'UPDATE' AND OLD.path IS DISTINCT FROM NEW.path) THEN
+		INSERT INTO file_changes (path, deleted) VALUES (OLD.path, true);
+	END IF;
+
+	IF TG_OP = 'DELETE' THEN
+		RETURN OLD;
+	END IF;
+
+	INSERT INTO file_changes (path, size, sha1, sha224, sha256, sha384, sha512, md5)
+	VALUES (NEW.path, NEW.size, NEW.sha1, NEW.sha224, NEW.sha256, NEW.sha384, NEW.sha512, NEW.md5);
+
+	RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,4 @@
+CREATE TABLE "file_quarantine" (
+	"path" varchar(255)[] PRIMARY KEY NOT NULL,
+	"created" timestamp DEFAULT now() NOT NULL
+);
\ No newline at end of file
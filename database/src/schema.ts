@@ -1,5 +1,5 @@
 import { isNotNull, relations, sql } from "drizzle-orm"
-import { foreignKey, index, integer, uuid, primaryKey, pgTable, varchar, uniqueIndex, pgEnum, serial, jsonb, char, boolean, smallint, timestamp, inet, text, customType, bigint, date } from "drizzle-orm/pg-core"
+import { foreignKey, index, integer, uuid, primaryKey, pgTable, varchar, uniqueIndex, pgEnum, serial, jsonb, char, boolean, smallint, timestamp, inet, text, customType, bigint, bigserial, date } from "drizzle-orm/pg-core"
 
 export const bytea = customType<{ data: string; notNull: false; default: false }>({
   dataType() {
@@ -240,6 +240,74 @@ export const files = pgTable('files', {
 	index('files_md5_idx').on(files.md5).with({ fillfactor: 100 })
 ])
 
+export const fileChanges = pgTable('file_changes', {
+	id: bigserial('id', { mode: 'number' }).primaryKey().notNull(),
+	path: varchar({ length: 255 }).array().notNull(),
+	deleted: boolean('deleted').default(false).notNull(),
+	size: integer('size'),
+
+	sha1: bytea('sha1'),
+	sha224: bytea('sha224'),
+	sha256: bytea('sha256'),
+	sha384: bytea('sha384'),
+	sha512: bytea('sha512'),
+	md5: bytea('md5'),
+
+	created: timestamp('created').default(sql`now()`).notNull()
+}, (fileChanges) => [
+	index('fileChanges_created_idx').on(fileChanges.created),
+	index('fileChanges_path_idx').on(fileChanges.path)
+])
+
+export const fileQuarantine = pgTable('file_quarantine', {
+	path: varchar({ length: 255 }).array().primaryKey().notNull(),
+	created: timestamp('created').default(sql`now()`).notNull()
+})
+
+export const mirrors = pgTable('mirrors', {
+	id: serial('id').primaryKey().notNull(),
+	name: varchar('name', { length: 63 }).notNull(),
+	url: varchar('url', { length: 255 }).notNull(),
+	continent: char('continent', { length: 2 }).notNull(),
+	country: char('country', { length: 2 }),
+
+	enabled: boolean('enabled').default(true).notNull(),
+	healthy: boolean('healthy').default(false).notNull(),
+	lastCheck: timestamp('last_check'),
+
+	created: timestamp('created').default(sql`now()`).notNull()
+}, (mirrors) => [
+	uniqueIndex('mirrors_url_idx').on(mirrors.url),
+	index('mirrors_continent_idx').on(mirrors.continent)
+])
+
+export const ratelimitPolicies = pgTable('ratelimit_policies', {
+	id: serial('id').primaryKey().notNull(),
+	organizationId: integer('organization_id').references(() => organizations.id, { onDelete: 'cascade' }),
+	organizationKeyId: integer('organization_key_id').references(() => organizationKeys.id, { onDelete: 'cascade' }),
+	routeGroup: varchar('route_group', { length: 31 }),
+
+	burst: integer('burst').notNull(),
+	rate: integer('rate').notNull(),
+	period: integer('period').default(60).notNull(),
+
+	created: timestamp('created').default(sql`now()`).notNull()
+}, (ratelimitPolicies) => [
+	index('ratelimitPolicies_organization_idx').on(ratelimitPolicies.organizationId),
+	index('ratelimitPolicies_organization_key_idx').on(ratelimitPolicies.organizationKeyId)
+])
+
+export const ratelimitCosts = pgTable('ratelimit_costs', {
+	id: serial('id').primaryKey().notNull(),
+	routeGroup: varchar('route_group', { length: 31 }).notNull(),
+	path: varchar('path', { length: 255 }).notNull(),
+	cost: integer('cost').default(1).notNull(),
+
+	created: timestamp('created').default(sql`now()`).notNull()
+}, (ratelimitCosts) => [
+	uniqueIndex('ratelimitCosts_route_group_path_idx').on(ratelimitCosts.routeGroup, ratelimitCosts.path)
+])
+
 export const builds = pgTable('builds', {
 	id: serial('id').primaryKey().notNull(),
 	uuid: uuid('uuid').notNull().default(sql`gen_random_uuid()`),